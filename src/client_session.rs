@@ -0,0 +1,716 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Client side request timeouts.
+
+A `ClientSession` sends requests to the server with a timeout: if the server does not respond in time,
+the request future completes with a timeout error (see `is_timeout_error`), and a `$/cancelRequest`
+notification is sent to the server.
+
+The responses to the requests sent with a timeout are taken from the incoming messages by the reader returned
+by `ClientSession::message_reader`, which must be used for the message read loop of the endpoint:
+
+```ignore
+let mut session = ClientSession::new(endpoint.clone(), timeouts);
+let mut reader = session.message_reader(LSPMessageReader(input));
+thread::spawn(move || LSPEndpoint::run_endpoint_loop(&mut reader, endpoint, new(handler)));
+```
+
+*/
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use util::core::*;
+
+use jsonrpc::*;
+use jsonrpc::futures;
+use jsonrpc::futures::Future;
+use jsonrpc::jsonrpc_common::*;
+use jsonrpc::jsonrpc_message::Message;
+use jsonrpc::jsonrpc_response::ResponseResult;
+use jsonrpc::method_types::RequestResult;
+use jsonrpc::service_util::MessageReader;
+
+use serde;
+use serde_json;
+
+use ls_types::*;
+use ls_types_ext::*;
+use lsp::LSPServerRpc;
+use lsp::duration_millis;
+
+/* ----------------- Timeout error ----------------- */
+
+/// The error code of a request that timed out. This is in the JSON-RPC range reserved for implementation-defined
+/// errors, so it is never sent by the server.
+pub const ERROR_CODE__RequestTimeout: i64 = -32050;
+
+pub fn error_RequestTimeout(method_name: &str, timeout: Duration) -> RequestError {
+    let millis = duration_millis(timeout);
+    RequestError::new(ERROR_CODE__RequestTimeout,
+        format!("Request `{}` timed out: no response from the server after {} ms.", method_name, millis))
+}
+
+/// Whether given error is the error of a request that timed out.
+pub fn is_timeout_error(error: &RequestError) -> bool {
+    error.code == ERROR_CODE__RequestTimeout
+}
+
+/// Whether given request result is that of a request that timed out.
+pub fn is_timeout<RET, RET_ERROR>(result: &RequestResult<RET, RET_ERROR>) -> bool {
+    match *result {
+        RequestResult::RequestError(ref error) => is_timeout_error(error),
+        RequestResult::MethodResult(_) => false,
+    }
+}
+
+/* ----------------- Timeouts configuration ----------------- */
+
+/// The timeouts of the requests sent to the server.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RequestTimeouts {
+    /// The timeout of requests without a specific timeout. If `None`, these requests never time out.
+    pub default_timeout: Option<Duration>,
+    /// Timeouts for specific methods, overriding the default timeout.
+    pub method_timeouts: HashMap<String, Duration>,
+}
+
+impl RequestTimeouts {
+
+    pub fn new(default_timeout: Option<Duration>) -> RequestTimeouts {
+        RequestTimeouts { default_timeout : default_timeout, method_timeouts : HashMap::new() }
+    }
+
+    pub fn with_method_timeout(mut self, method_name: &str, timeout: Duration) -> RequestTimeouts {
+        self.method_timeouts.insert(method_name.into(), timeout);
+        self
+    }
+
+    pub fn timeout_for(&self, method_name: &str) -> Option<Duration> {
+        self.method_timeouts.get(method_name).cloned().or(self.default_timeout)
+    }
+
+}
+
+/* ----------------- Timer ----------------- */
+
+type TimerAction = Box<FnMut() + Send>;
+
+/// A thread running actions at given instants.
+/// Once the timer is dropped, the remaining actions still run, then the thread ends.
+struct Timer {
+    sender: mpsc::Sender<(Instant, TimerAction)>,
+}
+
+impl Timer {
+
+    fn start() -> Timer {
+        let (sender, receiver) = mpsc::channel::<(Instant, TimerAction)>();
+
+        thread::spawn(move || {
+            let mut entries : Vec<(Instant, TimerAction)> = vec![];
+            let mut connected = true;
+            loop {
+                let now = Instant::now();
+                let (expired, pending) : (Vec<_>, Vec<_>) = entries.into_iter().partition(|entry| entry.0 <= now);
+                entries = pending;
+                for (_, mut action) in expired {
+                    // A panicking action must not terminate the thread, and with it the later actions
+                    if panic::catch_unwind(AssertUnwindSafe(|| action())).is_err() {
+                        error!("Timer action panicked.");
+                    }
+                }
+
+                let wait = entries.iter().map(|entry| entry.0).min().map(|deadline| {
+                    let now = Instant::now();
+                    if deadline > now { deadline - now } else { Duration::from_millis(0) }
+                });
+
+                let received = match (connected, wait) {
+                    (true, Some(wait)) => receiver.recv_timeout(wait),
+                    (true, None) => receiver.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                    (false, Some(wait)) => {
+                        thread::sleep(wait);
+                        continue;
+                    }
+                    (false, None) => break,
+                };
+                match received {
+                    Ok(entry) => entries.push(entry),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => connected = false,
+                }
+            }
+        });
+
+        Timer { sender : sender }
+    }
+
+    fn schedule(&self, deadline: Instant, action: TimerAction) {
+        if self.sender.send((deadline, action)).is_err() {
+            error!("Timer thread has terminated, action not scheduled.");
+        }
+    }
+
+}
+
+/* ----------------- Client session ----------------- */
+
+/// The maximum number of timed out requests whose late response is dropped.
+/// The late responses to older timed out requests are passed on to the endpoint, which ignores them.
+const MAX_TIMED_OUT_REQUESTS: usize = 256;
+
+/// The requests sent with a timeout, awaiting their response.
+#[derive(Default)]
+struct PendingRequests {
+    awaiting: HashMap<Id, futures::Complete<ResponseResult>>,
+    /// The last requests that timed out, whose late response is dropped.
+    timed_out: VecDeque<Id>,
+}
+
+impl PendingRequests {
+
+    /// Remove the request with given id, if it is still awaiting a response, and record that it timed out.
+    fn time_out(&mut self, id: &Id) -> Option<futures::Complete<ResponseResult>> {
+        let completable = self.awaiting.remove(id);
+        if completable.is_some() {
+            if self.timed_out.len() == MAX_TIMED_OUT_REQUESTS {
+                self.timed_out.pop_front();
+            }
+            self.timed_out.push_back(id.clone());
+        }
+        completable
+    }
+
+    /// Remove given id from the timed out requests, returning whether it was one of them.
+    fn remove_timed_out(&mut self, id: &Id) -> bool {
+        match self.timed_out.iter().position(|timed_out_id| timed_out_id == id) {
+            Some(index) => {
+                self.timed_out.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+}
+
+type SharedPendingRequests = Arc<Mutex<PendingRequests>>;
+
+/// The client side of an LSP connection, sending requests with timeouts.
+///
+/// Note: the responses to the requests sent with a timeout are only received if the message read loop
+/// of the endpoint uses the reader returned by `message_reader`.
+pub struct ClientSession {
+    endpoint: Endpoint,
+    timeouts: RequestTimeouts,
+    timer: Timer,
+    pending_requests: SharedPendingRequests,
+}
+
+impl ClientSession {
+
+    pub fn new(endpoint: Endpoint, timeouts: RequestTimeouts) -> ClientSession {
+        ClientSession {
+            endpoint : endpoint,
+            timeouts : timeouts,
+            timer : Timer::start(),
+            pending_requests : Arc::new(Mutex::new(PendingRequests::default())),
+        }
+    }
+
+    /// Wrap the reader of the incoming messages, to take the responses to the requests sent with a timeout.
+    pub fn message_reader<MR : MessageReader>(&self, reader: MR) -> SessionMessageReader<MR> {
+        SessionMessageReader { reader : reader, pending_requests : self.pending_requests.clone() }
+    }
+
+    pub fn endpoint(&mut self) -> &mut Endpoint {
+        &mut self.endpoint
+    }
+
+    pub fn timeouts(&self) -> &RequestTimeouts {
+        &self.timeouts
+    }
+
+    pub fn timeouts_mut(&mut self) -> &mut RequestTimeouts {
+        &mut self.timeouts
+    }
+
+    /// An RPC handle sending requests with the configured timeouts.
+    pub fn server_rpc(&mut self) -> TimeoutServerRpc {
+        TimeoutServerRpc { session : self, timeout_override : None }
+    }
+
+    /// An RPC handle sending requests with given timeout, instead of the configured ones.
+    pub fn server_rpc_with_timeout(&mut self, timeout: Duration) -> TimeoutServerRpc {
+        TimeoutServerRpc { session : self, timeout_override : Some(timeout) }
+    }
+
+    /// Send a request which times out after given duration (if any).
+    pub fn send_request<PARAMS, RET, RET_ERROR>(&mut self, method_name: &str, params: PARAMS,
+        timeout: Option<Duration>) -> GResult<RequestFuture<RET, RET_ERROR>>
+    where
+        PARAMS : serde::Serialize,
+        RET : serde::Deserialize + Send + 'static,
+        RET_ERROR : serde::Deserialize + Send + 'static,
+    {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => return self.endpoint.send_request(method_name, params),
+        };
+
+        let id = self.endpoint.next_id();
+        let (completable, future) = futures::oneshot::<ResponseResult>();
+        self.pending_requests.lock().unwrap().awaiting.insert(id.clone(), completable);
+
+        if let Err(error) = self.endpoint.write_request(Some(id.clone()), method_name, params) {
+            self.pending_requests.lock().unwrap().awaiting.remove(&id);
+            return Err(error);
+        }
+
+        let pending_requests = self.pending_requests.clone();
+        let endpoint = self.endpoint.clone();
+        let method_name = String::from(method_name);
+
+        self.timer.schedule(Instant::now() + timeout, new(move || {
+            let completable = pending_requests.lock().unwrap().time_out(&id);
+            let completable = match completable {
+                Some(completable) => completable,
+                None => return, // The response has arrived
+            };
+
+            warn!("Request `{}` (id {}) timed out, cancelling it.", method_name, id);
+            completable.complete(ResponseResult::Error(error_RequestTimeout(&method_name, timeout)));
+
+            if endpoint.is_shutdown() {
+                return;
+            }
+            if let Id::Number(number) = id {
+                let params = CancelParams { id : NumberOrString::Number(number) };
+                if let Err(error) = endpoint.send_notification(NOTIFICATION__Cancel, params) {
+                    warn!("Failed to send `{}`: {}", NOTIFICATION__Cancel, error);
+                }
+            }
+        }));
+
+        let future = future.map(|response_result| RequestResult::<RET, RET_ERROR>::from(response_result));
+        Ok(future.boxed())
+    }
+
+}
+
+/// A `MessageReader` that takes the responses to the requests sent with a timeout by a `ClientSession`,
+/// and returns all other messages. See `ClientSession::message_reader`.
+pub struct SessionMessageReader<MR> {
+    reader: MR,
+    pending_requests: SharedPendingRequests,
+}
+
+impl<MR : MessageReader> MessageReader for SessionMessageReader<MR> {
+
+    fn read_next(&mut self) -> GResult<String> {
+        loop {
+            let message = try!(self.reader.read_next());
+
+            if let Ok(Message::Response(response)) = serde_json::from_str::<Message>(&message) {
+                let mut pending_requests = self.pending_requests.lock().unwrap();
+                if let Some(completable) = pending_requests.awaiting.remove(&response.id) {
+                    completable.complete(response.result_or_error);
+                    continue;
+                }
+                if pending_requests.remove_timed_out(&response.id) {
+                    debug!("Dropping the response to timed out request (id {}).", response.id);
+                    continue;
+                }
+            }
+            return Ok(message);
+        }
+    }
+
+}
+
+/// An `LSPServerRpc` sending requests with timeouts. See `ClientSession`.
+pub struct TimeoutServerRpc<'a> {
+    session: &'a mut ClientSession,
+    timeout_override: Option<Duration>,
+}
+
+impl<'a> TimeoutServerRpc<'a> {
+
+    fn send_request<PARAMS, RET, RET_ERROR>(&mut self, method_name: &str, params: PARAMS)
+        -> GResult<RequestFuture<RET, RET_ERROR>>
+    where
+        PARAMS : serde::Serialize,
+        RET : serde::Deserialize + Send + 'static,
+        RET_ERROR : serde::Deserialize + Send + 'static,
+    {
+        let timeout = self.timeout_override.or_else(|| self.session.timeouts.timeout_for(method_name));
+        self.session.send_request(method_name, params, timeout)
+    }
+
+}
+
+impl<'a> LSPServerRpc for TimeoutServerRpc<'a> {
+    
+    fn initialize(&mut self, params: InitializeParams)
+        -> GResult<RequestFuture<InitializeResult, InitializeError>> 
+    {
+        self.send_request(REQUEST__Initialize, params)
+    }
+    
+    fn shutdown(&mut self)
+        -> GResult<RequestFuture<(), ()>>
+    {
+        self.send_request(REQUEST__Shutdown, ())
+    }
+    
+    fn exit(&mut self)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__Exit, ())
+    }
+    
+    fn workspace_change_configuration(&mut self, params: DidChangeConfigurationParams)
+        -> GResult<()>
+    {
+         self.session.endpoint.send_notification(NOTIFICATION__WorkspaceChangeConfiguration, params)
+    }
+    
+    fn did_open_text_document(&mut self, params: DidOpenTextDocumentParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__DidOpenTextDocument, params)
+    }
+    
+    fn did_change_text_document(&mut self, params: DidChangeTextDocumentParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__DidChangeTextDocument, params)
+    }
+    
+    fn did_close_text_document(&mut self, params: DidCloseTextDocumentParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__DidCloseTextDocument, params)
+    }
+    
+    fn did_save_text_document(&mut self, params: DidSaveTextDocumentParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__DidSaveTextDocument, params)
+    }
+    
+    fn did_change_watched_files(&mut self, params: DidChangeWatchedFilesParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__DidChangeWatchedFiles, params)
+    }
+    
+    fn completion(&mut self, params: TextDocumentPositionParams)
+        -> GResult<RequestFuture<CompletionList, ()>>
+    {
+        self.send_request(REQUEST__Completion, params)
+    }
+    
+    fn resolve_completion_item(&mut self, params: CompletionItem)
+        -> GResult<RequestFuture<CompletionItem, ()>>
+    {
+        self.send_request(REQUEST__ResolveCompletionItem, params)
+    }
+    
+    fn hover(&mut self, params: TextDocumentPositionParams)
+        -> GResult<RequestFuture<Hover, ()>>
+    {
+        self.send_request(REQUEST__Hover, params)
+    }
+    
+    fn signature_help(&mut self, params: TextDocumentPositionParams)
+        -> GResult<RequestFuture<SignatureHelp, ()>>
+    {
+        self.send_request(REQUEST__SignatureHelp, params)
+    }
+    
+    fn goto_definition(&mut self, params: TextDocumentPositionParams)
+        -> GResult<RequestFuture<Vec<Location>, ()>>
+    {
+        self.send_request(REQUEST__GotoDefinition, params)
+    }
+    
+    fn references(&mut self, params: ReferenceParams)
+        -> GResult<RequestFuture<Vec<Location>, ()>>
+    {
+        self.send_request(REQUEST__References, params)
+    }
+    
+    fn document_highlight(&mut self, params: TextDocumentPositionParams)
+        -> GResult<RequestFuture<Vec<DocumentHighlight>, ()>>
+    {
+        self.send_request(REQUEST__DocumentHighlight, params)
+    }
+    
+    fn document_symbols(&mut self, params: DocumentSymbolParams)
+        -> GResult<RequestFuture<Vec<SymbolInformation>, ()>>
+    {
+        self.send_request(REQUEST__DocumentSymbols, params)
+    }
+    
+    fn workspace_symbols(&mut self, params: WorkspaceSymbolParams)
+        -> GResult<RequestFuture<Vec<SymbolInformation>, ()>>
+    {
+        self.send_request(REQUEST__WorkspaceSymbols, params)
+    }
+    
+    fn code_action(&mut self, params: CodeActionParams)
+        -> GResult<RequestFuture<Vec<Command>, ()>>
+    {
+        self.send_request(REQUEST__CodeAction, params)
+    }
+    
+    fn code_lens(&mut self, params: CodeLensParams)
+        -> GResult<RequestFuture<Vec<CodeLens>, ()>>
+    {
+        self.send_request(REQUEST__CodeLens, params)
+    }
+    
+    fn code_lens_resolve(&mut self, params: CodeLens)
+        -> GResult<RequestFuture<CodeLens, ()>>
+    {
+        self.send_request(REQUEST__CodeLensResolve, params)
+    }
+    
+    fn formatting(&mut self, params: DocumentFormattingParams)
+        -> GResult<RequestFuture<Vec<TextEdit>, ()>>
+    {
+        self.send_request(REQUEST__Formatting, params)
+    }
+    
+    fn range_formatting(&mut self, params: DocumentRangeFormattingParams)
+        -> GResult<RequestFuture<Vec<TextEdit>, ()>>
+    {
+        self.send_request(REQUEST__RangeFormatting, params)
+    }
+    
+    fn on_type_formatting(&mut self, params: DocumentOnTypeFormattingParams)
+        -> GResult<RequestFuture<Vec<TextEdit>, ()>>
+    {
+        self.send_request(REQUEST__OnTypeFormatting, params)
+    }
+    
+    fn rename(&mut self, params: RenameParams)
+        -> GResult<RequestFuture<WorkspaceEdit, ()>>
+    {
+        self.send_request(REQUEST__Rename, params)
+    }
+    
+    fn prepare_call_hierarchy(&mut self, params: CallHierarchyPrepareParams)
+        -> GResult<RequestFuture<Option<Vec<CallHierarchyItem>>, ()>>
+    {
+        self.send_request(REQUEST__PrepareCallHierarchy, params)
+    }
+    
+    fn call_hierarchy_incoming_calls(&mut self, params: CallHierarchyIncomingCallsParams)
+        -> GResult<RequestFuture<Option<Vec<CallHierarchyIncomingCall>>, ()>>
+    {
+        self.send_request(REQUEST__CallHierarchyIncomingCalls, params)
+    }
+    
+    fn call_hierarchy_outgoing_calls(&mut self, params: CallHierarchyOutgoingCallsParams)
+        -> GResult<RequestFuture<Option<Vec<CallHierarchyOutgoingCall>>, ()>>
+    {
+        self.send_request(REQUEST__CallHierarchyOutgoingCalls, params)
+    }
+    
+    fn prepare_type_hierarchy(&mut self, params: TypeHierarchyPrepareParams)
+        -> GResult<RequestFuture<Option<Vec<TypeHierarchyItem>>, ()>>
+    {
+        self.send_request(REQUEST__PrepareTypeHierarchy, params)
+    }
+    
+    fn type_hierarchy_supertypes(&mut self, params: TypeHierarchySupertypesParams)
+        -> GResult<RequestFuture<Option<Vec<TypeHierarchyItem>>, ()>>
+    {
+        self.send_request(REQUEST__TypeHierarchySupertypes, params)
+    }
+    
+    fn type_hierarchy_subtypes(&mut self, params: TypeHierarchySubtypesParams)
+        -> GResult<RequestFuture<Option<Vec<TypeHierarchyItem>>, ()>>
+    {
+        self.send_request(REQUEST__TypeHierarchySubtypes, params)
+    }
+    
+    fn inlay_hint(&mut self, params: InlayHintParams)
+        -> GResult<RequestFuture<Option<Vec<InlayHint>>, ()>>
+    {
+        self.send_request(REQUEST__InlayHint, params)
+    }
+    
+    fn inlay_hint_resolve(&mut self, params: InlayHint)
+        -> GResult<RequestFuture<InlayHint, ()>>
+    {
+        self.send_request(REQUEST__InlayHintResolve, params)
+    }
+    
+    fn inline_value(&mut self, params: InlineValueParams)
+        -> GResult<RequestFuture<Option<Vec<InlineValue>>, ()>>
+    {
+        self.send_request(REQUEST__InlineValue, params)
+    }
+    
+    fn document_diagnostic(&mut self, params: DocumentDiagnosticParams)
+        -> GResult<RequestFuture<DocumentDiagnosticReport, ()>>
+    {
+        self.send_request(REQUEST__DocumentDiagnostic, params)
+    }
+    
+    fn workspace_diagnostic(&mut self, params: WorkspaceDiagnosticParams)
+        -> GResult<RequestFuture<WorkspaceDiagnosticReport, ()>>
+    {
+        self.send_request(REQUEST__WorkspaceDiagnostic, params)
+    }
+    
+    fn prepare_rename(&mut self, params: PrepareRenameParams)
+        -> GResult<RequestFuture<Option<PrepareRenameResponse>, ()>>
+    {
+        self.send_request(REQUEST__PrepareRename, params)
+    }
+    
+    fn linked_editing_range(&mut self, params: LinkedEditingRangeParams)
+        -> GResult<RequestFuture<Option<LinkedEditingRanges>, ()>>
+    {
+        self.send_request(REQUEST__LinkedEditingRange, params)
+    }
+    
+    fn did_change_workspace_folders(&mut self, params: DidChangeWorkspaceFoldersParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__DidChangeWorkspaceFolders, params)
+    }
+    
+    fn will_create_files(&mut self, params: CreateFilesParams)
+        -> GResult<RequestFuture<Option<WorkspaceEdit>, ()>>
+    {
+        self.send_request(REQUEST__WillCreateFiles, params)
+    }
+    
+    fn did_create_files(&mut self, params: CreateFilesParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__DidCreateFiles, params)
+    }
+    
+    fn will_rename_files(&mut self, params: RenameFilesParams)
+        -> GResult<RequestFuture<Option<WorkspaceEdit>, ()>>
+    {
+        self.send_request(REQUEST__WillRenameFiles, params)
+    }
+    
+    fn did_rename_files(&mut self, params: RenameFilesParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__DidRenameFiles, params)
+    }
+    
+    fn will_delete_files(&mut self, params: DeleteFilesParams)
+        -> GResult<RequestFuture<Option<WorkspaceEdit>, ()>>
+    {
+        self.send_request(REQUEST__WillDeleteFiles, params)
+    }
+    
+    fn did_delete_files(&mut self, params: DeleteFilesParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__DidDeleteFiles, params)
+    }
+    
+    fn work_done_progress_cancel(&mut self, params: WorkDoneProgressCancelParams)
+        -> GResult<()>
+    {
+        self.session.endpoint.send_notification(NOTIFICATION__WorkDoneProgressCancel, params)
+    }
+    
+}
+
+#[test]
+fn test_ClientSession_timeout() {
+    use serde_json::Value;
+    use lsp::LSPEndpoint;
+    use memory_transport::test_messages::*;
+
+    let (sender, receiver) = mpsc::channel();
+    let endpoint = LSPEndpoint::create_lsp_output(move || ChannelMessageWriter(sender));
+    let next_message = || -> Value {
+        serde_json::from_str(&receiver.recv_timeout(Duration::from_secs(5)).unwrap()).unwrap()
+    };
+
+    let timeouts = RequestTimeouts::new(None).with_method_timeout(REQUEST__Hover, Duration::from_millis(10));
+    assert_eq!(timeouts.timeout_for(REQUEST__Hover), Some(Duration::from_millis(10)));
+    assert_eq!(timeouts.timeout_for(REQUEST__Completion), None);
+
+    let mut session = ClientSession::new(endpoint, timeouts);
+
+    // Nothing answers the requests, so they time out, and are cancelled
+    let params = TextDocumentPositionParams::new(
+        TextDocumentIdentifier::new("file:///foo.rs".parse().unwrap()), Position::new(0, 0));
+    let result = session.server_rpc().hover(params.clone()).unwrap().wait().unwrap();
+    assert!(is_timeout(&result));
+    let request = next_message();
+    assert_eq!(request.find("method").and_then(Value::as_str), Some(REQUEST__Hover));
+    let request_id = request.find("id").and_then(Value::as_u64).unwrap();
+    let cancel = next_message();
+    assert_eq!(cancel.find("method").and_then(Value::as_str), Some(NOTIFICATION__Cancel));
+    assert_eq!(cancel.find_path(&["params", "id"]).and_then(Value::as_u64), Some(request_id));
+    assert!(session.pending_requests.lock().unwrap().awaiting.is_empty());
+    assert_eq!(session.pending_requests.lock().unwrap().timed_out, vec![Id::Number(request_id)]);
+
+    let timeout = Duration::from_millis(10);
+    let result = session.server_rpc_with_timeout(timeout).completion(params.clone()).unwrap().wait().unwrap();
+    assert!(is_timeout(&result));
+    assert!(result.unwrap_error().message.contains(REQUEST__Completion));
+    let completion_id = next_message().find("id").and_then(Value::as_u64).unwrap();
+    assert_eq!(completion_id, request_id + 1);
+    next_message();
+
+    // A request answered in time is not cancelled, and late responses are dropped
+    let future = session.server_rpc_with_timeout(Duration::from_millis(200)).hover(params).unwrap();
+    let hover_id = next_message().find("id").and_then(Value::as_u64).unwrap();
+    let response = |id: u64| format!(r#"{{"jsonrpc": "2.0", "id": {}, "result": {{"contents": []}}}}"#, id);
+    let other_message = r#"{"jsonrpc": "2.0", "method": "window/logMessage", "params": {}}"#.to_string();
+    let mut reader = session.message_reader(QueueMessageReader(
+        vec![response(request_id), response(hover_id), other_message.clone()].into_iter().collect()
+    ));
+    assert_eq!(reader.read_next().unwrap(), other_message);
+
+    let result = future.wait().unwrap();
+    assert!(!is_timeout(&result));
+    assert!(result.unwrap_result().unwrap().contents.is_empty());
+    assert!(receiver.recv_timeout(Duration::from_millis(400)).is_err());
+    assert_eq!(session.pending_requests.lock().unwrap().timed_out, vec![Id::Number(completion_id)]);
+
+    // Only the last timed out requests are remembered
+    let mut pending_requests = PendingRequests::default();
+    for number in 0..MAX_TIMED_OUT_REQUESTS as u64 + 1 {
+        pending_requests.awaiting.insert(Id::Number(number), futures::oneshot().0);
+        assert!(pending_requests.time_out(&Id::Number(number)).is_some());
+    }
+    assert!(pending_requests.time_out(&Id::Number(1)).is_none());
+    assert_eq!(pending_requests.timed_out.len(), MAX_TIMED_OUT_REQUESTS);
+    assert!(!pending_requests.remove_timed_out(&Id::Number(0)));
+    assert!(pending_requests.remove_timed_out(&Id::Number(1)));
+
+    session.endpoint().request_shutdown();
+}
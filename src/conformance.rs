@@ -0,0 +1,675 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Protocol conformance checks for `LanguageServerHandling` implementations.
+
+`ConformanceKit::run` drives a server through a series of scenarios, sending raw JSON-RPC messages
+(as an editor would) and checking the responses:
+
+* lifecycle: requests before `initialize`, the `initialize` result, the `initialized` notification.
+* document sync: `didOpen`, `didChange` (according to the advertised `textDocumentSync` kind) and `didClose`.
+* errors: unknown methods, invalid params, unknown `$/` notifications.
+* cancellation: a cancelled request must still be answered.
+* capabilities: for each provider advertised in `ServerCapabilities`, the shape of the response of
+  the corresponding request on the test document.
+* shutdown: the `shutdown` result, requests after `shutdown`, and `exit` ending the message loop.
+
+The result is a `ConformanceReport`, listing each check as passed, failed or skipped. Example, in a test:
+
+```ignore
+ConformanceKit::new().run(|endpoint| MyLanguageServer::new(endpoint)).assert_success();
+```
+
+Requests the server sends to the client during the checks are answered with a `null` result.
+
+*/
+
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use jsonrpc::*;
+use jsonrpc::jsonrpc_common::*;
+
+use serde_json;
+use serde_json::Value;
+use serde_json::builder::ObjectBuilder;
+
+use ls_types::*;
+use ls_types_ext::*;
+use lsp::*;
+use lsp_transport::parse_transport_message;
+use lsp_transport::write_transport_message;
+use memory_transport::PipeWriter;
+use memory_transport::pipe;
+
+/* ----------------- Report ----------------- */
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum CheckOutcome {
+    Passed,
+    Failed(String),
+    /// The check does not apply to the server, for example because it does not advertise the capability.
+    Skipped(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CheckResult {
+    pub scenario: &'static str,
+    pub check: String,
+    pub outcome: CheckOutcome,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ConformanceReport {
+    pub results: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+
+    /// The result of the check with given description, if it was run.
+    pub fn result(&self, check: &str) -> Option<&CheckResult> {
+        self.results.iter().find(|result| result.check == check)
+    }
+
+    pub fn failures(&self) -> Vec<&CheckResult> {
+        self.results.iter().filter(|result| match result.outcome {
+            CheckOutcome::Failed(_) => true,
+            _ => false,
+        }).collect()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failures().is_empty()
+    }
+
+    /// Panic, with the report as message, if any check failed.
+    pub fn assert_success(&self) {
+        if !self.is_success() {
+            panic!("{}", self);
+        }
+    }
+
+    fn add(&mut self, scenario: &'static str, check: &str, outcome: CheckOutcome) {
+        self.results.push(CheckResult { scenario : scenario, check : check.to_string(), outcome : outcome });
+    }
+
+    fn add_result(&mut self, scenario: &'static str, check: &str, result: Result<(), String>) {
+        let outcome = match result {
+            Ok(()) => CheckOutcome::Passed,
+            Err(message) => CheckOutcome::Failed(message),
+        };
+        self.add(scenario, check, outcome)
+    }
+
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let passed = self.results.iter().filter(|result| result.outcome == CheckOutcome::Passed).count();
+        let failed = self.failures().len();
+        try!(writeln!(f, "Conformance: {} passed, {} failed, {} skipped",
+            passed, failed, self.results.len() - passed - failed));
+
+        for result in &self.results {
+            try!(match result.outcome {
+                CheckOutcome::Passed => writeln!(f, "[PASS] {}: {}", result.scenario, result.check),
+                CheckOutcome::Failed(ref message) => {
+                    writeln!(f, "[FAIL] {}: {}\n       {}", result.scenario, result.check, message)
+                }
+                CheckOutcome::Skipped(ref reason) => {
+                    writeln!(f, "[SKIP] {}: {} ({})", result.scenario, result.check, reason)
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/* ----------------- Conformance kit ----------------- */
+
+/// The conformance checks configuration. See module docs.
+pub struct ConformanceKit {
+    /// The params of the `initialize` request.
+    pub initialize_params: Value,
+    /// The document opened for the document sync and capability checks.
+    pub document_uri: String,
+    pub language_id: String,
+    pub document_text: String,
+    /// How long to wait for a response.
+    pub timeout: Duration,
+    /// How long to wait for a message that should not arrive (such as a response to a notification).
+    pub quiet_period: Duration,
+}
+
+impl ConformanceKit {
+
+    pub fn new() -> ConformanceKit {
+        let initialize_params = ObjectBuilder::new()
+            .insert("processId", Value::Null)
+            .insert("rootPath", Value::Null)
+            .insert_object("capabilities", |builder| builder)
+            .build();
+
+        ConformanceKit {
+            initialize_params : initialize_params,
+            document_uri : "file:///conformance/document.txt".to_string(),
+            language_id : "plaintext".to_string(),
+            document_text : "first line\nsecond line\n".to_string(),
+            timeout : Duration::from_secs(5),
+            quiet_period : Duration::from_millis(200),
+        }
+    }
+
+    /// Run the checks. `create_server` is called once for each server instance the scenarios need,
+    /// with the endpoint the server must use to send messages to the client.
+    pub fn run<LS, FN>(&self, create_server: FN) -> ConformanceReport
+    where
+        LS : LanguageServerHandling + Send + 'static,
+        FN : Fn(Endpoint) -> LS,
+    {
+        let mut report = ConformanceReport::default();
+
+        let mut server = ServerUnderTest::start(&create_server, self.timeout, self.quiet_period);
+        let capabilities = self.check_lifecycle(&mut server, &mut report);
+        drop(server);
+
+        let capabilities = match capabilities {
+            Some(capabilities) => capabilities,
+            None => {
+                report.add("lifecycle", "other scenarios", CheckOutcome::Skipped("initialize failed".into()));
+                return report;
+            }
+        };
+
+        let mut server = ServerUnderTest::start(&create_server, self.timeout, self.quiet_period);
+        server.call(REQUEST__Initialize, self.initialize_params.clone());
+        server.notify("initialized", object(|builder| builder));
+
+        self.check_document_sync(&mut server, &capabilities, &mut report);
+        self.check_errors(&mut server, &capabilities, &mut report);
+        self.check_cancellation(&mut server, &capabilities, &mut report);
+        self.check_capabilities(&mut server, &capabilities, &mut report);
+        self.check_document_close(&mut server, &mut report);
+        self.check_shutdown(&mut server, &mut report);
+
+        report
+    }
+
+    fn text_document_position(&self) -> Value {
+        object(|builder| builder
+            .insert_object("textDocument", |builder| builder.insert("uri", &self.document_uri))
+            .insert_object("position", |builder| builder.insert("line", 0).insert("character", 1))
+        )
+    }
+
+    /// Returns the server capabilities, if `initialize` succeeded.
+    fn check_lifecycle(&self, server: &mut ServerUnderTest, report: &mut ConformanceReport) -> Option<Value> {
+        const SCENARIO : &'static str = "lifecycle";
+
+        let response = server.call(REQUEST__Hover, self.text_document_position());
+        report.add_result(SCENARIO, "a request before initialize fails with ServerNotInitialized",
+            expect_error(response, ERROR_CODE__ServerNotInitialized));
+
+        let response = server.call(REQUEST__Initialize, self.initialize_params.clone());
+        let capabilities = response.as_ref()
+            .and_then(|response| response.find_path(&["result", "capabilities"]))
+            .and_then(|capabilities| if capabilities.is_object() { Some(capabilities.clone()) } else { None });
+        report.add_result(SCENARIO, "initialize returns the server capabilities", match capabilities {
+            Some(_) => Ok(()),
+            None => Err(format!("Expected a result with a `capabilities` object, got: {}", describe(&response))),
+        });
+
+        server.notify("initialized", object(|builder| builder));
+        report.add_result(SCENARIO, "the initialized notification is not answered", server.expect_no_response());
+
+        capabilities
+    }
+
+    fn check_document_sync(&self, server: &mut ServerUnderTest, capabilities: &Value,
+        report: &mut ConformanceReport)
+    {
+        const SCENARIO : &'static str = "document sync";
+
+        let sync_kind = match capabilities.find("textDocumentSync") {
+            Some(&Value::U64(kind)) => Some(kind),
+            Some(sync_options) => sync_options.find("change").and_then(Value::as_u64),
+            None => None,
+        };
+        let sync_kind = match sync_kind {
+            Some(kind) if kind != 0 => kind,
+            _ => {
+                let reason = CheckOutcome::Skipped("textDocumentSync not advertised".into());
+                report.add(SCENARIO, "document notifications are not answered", reason);
+                // The capability checks still need the document
+                self.open_document(server);
+                return;
+            }
+        };
+
+        self.open_document(server);
+
+        let content_change = if sync_kind == 1 {
+            object(|builder| builder.insert("text", format!("{}third line\n", self.document_text)))
+        } else {
+            let line_count = self.document_text.lines().count() as u64;
+            object(|builder| builder
+                .insert_object("range", |builder| builder
+                    .insert_object("start", |builder| builder.insert("line", line_count).insert("character", 0))
+                    .insert_object("end", |builder| builder.insert("line", line_count).insert("character", 0))
+                )
+                .insert("text", "third line\n")
+            )
+        };
+        server.notify(NOTIFICATION__DidChangeTextDocument, object(|builder| builder
+            .insert_object("textDocument", |builder| builder.insert("uri", &self.document_uri).insert("version", 2))
+            .insert_array("contentChanges", |builder| builder.push(content_change))
+        ));
+
+        report.add_result(SCENARIO, "document notifications are not answered", server.expect_no_response());
+    }
+
+    fn open_document(&self, server: &mut ServerUnderTest) {
+        server.notify(NOTIFICATION__DidOpenTextDocument, object(|builder| builder
+            .insert_object("textDocument", |builder| builder
+                .insert("uri", &self.document_uri)
+                .insert("languageId", &self.language_id)
+                .insert("version", 1)
+                .insert("text", &self.document_text)
+            )
+        ));
+    }
+
+    fn check_document_close(&self, server: &mut ServerUnderTest, report: &mut ConformanceReport) {
+        server.notify(NOTIFICATION__DidCloseTextDocument, object(|builder| builder
+            .insert_object("textDocument", |builder| builder.insert("uri", &self.document_uri))
+        ));
+        report.add_result("document sync", "the didClose notification is not answered", server.expect_no_response());
+    }
+
+    fn check_errors(&self, server: &mut ServerUnderTest, capabilities: &Value, report: &mut ConformanceReport) {
+        const SCENARIO : &'static str = "errors";
+
+        let response = server.call("conformance/unknownRequest", object(|builder| builder));
+        let code = error_JSON_RPC_MethodNotFound().code;
+        report.add_result(SCENARIO, "an unknown request fails with MethodNotFound", expect_error(response, code));
+
+        server.notify("$/conformance/unknownNotification", object(|builder| builder));
+        report.add_result(SCENARIO, "an unknown $/ notification is ignored", server.expect_no_response());
+
+        let check = "a request with invalid params fails with InvalidParams";
+        if is_advertised(capabilities, "hoverProvider") {
+            let params = object(|builder| builder.insert("textDocument", 42));
+            let response = server.call(REQUEST__Hover, params);
+            report.add_result(SCENARIO, check, expect_error(response, error_JSON_RPC_InvalidParams("").code));
+        } else {
+            report.add(SCENARIO, check, CheckOutcome::Skipped("hoverProvider not advertised".into()));
+        }
+    }
+
+    fn check_cancellation(&self, server: &mut ServerUnderTest, capabilities: &Value,
+        report: &mut ConformanceReport)
+    {
+        const SCENARIO : &'static str = "cancellation";
+        let check = "a cancelled request is still answered";
+
+        if !is_advertised(capabilities, "hoverProvider") {
+            report.add(SCENARIO, check, CheckOutcome::Skipped("hoverProvider not advertised".into()));
+            return;
+        }
+
+        let id = server.request(REQUEST__Hover, self.text_document_position());
+        server.notify(NOTIFICATION__Cancel, object(|builder| builder.insert("id", id)));
+        let response = server.response(id);
+        report.add_result(SCENARIO, check, match response {
+            Some(ref response) if response.find("result").is_some() => Ok(()),
+            Some(ref response) if error_code(response) == Some(ERROR_CODE__RequestCancelled) => Ok(()),
+            _ => Err(format!("Expected a result or a RequestCancelled error, got: {}", describe(&response))),
+        });
+    }
+
+    fn check_capabilities(&self, server: &mut ServerUnderTest, capabilities: &Value,
+        report: &mut ConformanceReport)
+    {
+        const SCENARIO : &'static str = "capabilities";
+
+        let text_document = object(|builder| builder.insert("uri", &self.document_uri));
+        let range = object(|builder| builder
+            .insert_object("start", |builder| builder.insert("line", 0).insert("character", 0))
+            .insert_object("end", |builder| builder.insert("line", 1).insert("character", 0))
+        );
+        let formatting_options = object(|builder| builder.insert("tabSize", 4).insert("insertSpaces", true));
+
+        let document_params = object(|builder| builder.insert("textDocument", text_document.clone()));
+        let references_params = with_field(self.text_document_position(), "context",
+            object(|builder| builder.insert("includeDeclaration", true)));
+        let code_action_params = object(|builder| builder
+            .insert("textDocument", text_document.clone())
+            .insert("range", range.clone())
+            .insert_object("context", |builder| builder.insert_array("diagnostics", |builder| builder))
+        );
+        let formatting_params = with_field(document_params.clone(), "options", formatting_options.clone());
+        let range_formatting_params = with_field(formatting_params.clone(), "range", range.clone());
+        let rename_params = with_field(self.text_document_position(), "newName", Value::String("renamed".into()));
+
+        let checks : Vec<(&str, &str, Value, ResultCheck)> = vec![
+            ("hoverProvider", REQUEST__Hover, self.text_document_position(), check_hover as ResultCheck),
+            ("completionProvider", REQUEST__Completion, self.text_document_position(),
+                check_completion as ResultCheck),
+            ("signatureHelpProvider", REQUEST__SignatureHelp, self.text_document_position(),
+                check_signature_help as ResultCheck),
+            ("definitionProvider", REQUEST__GotoDefinition, self.text_document_position(),
+                check_locations as ResultCheck),
+            ("referencesProvider", REQUEST__References, references_params, check_locations as ResultCheck),
+            ("documentHighlightProvider", REQUEST__DocumentHighlight, self.text_document_position(),
+                check_document_highlights as ResultCheck),
+            ("documentSymbolProvider", REQUEST__DocumentSymbols, document_params.clone(), check_symbols as ResultCheck),
+            ("workspaceSymbolProvider", REQUEST__WorkspaceSymbols,
+                object(|builder| builder.insert("query", "")), check_symbols as ResultCheck),
+            ("codeActionProvider", REQUEST__CodeAction, code_action_params, check_code_actions as ResultCheck),
+            ("codeLensProvider", REQUEST__CodeLens, document_params.clone(), check_code_lenses as ResultCheck),
+            ("documentFormattingProvider", REQUEST__Formatting, formatting_params, check_text_edits as ResultCheck),
+            ("documentRangeFormattingProvider", REQUEST__RangeFormatting, range_formatting_params,
+                check_text_edits as ResultCheck),
+            ("renameProvider", REQUEST__Rename, rename_params, check_workspace_edit as ResultCheck),
+        ];
+
+        for (capability, method_name, params, check_result) in checks {
+            let check = format!("{} returns a well-formed `{}` result", capability, method_name);
+            if !is_advertised(capabilities, capability) {
+                report.add(SCENARIO, &check, CheckOutcome::Skipped("not advertised".into()));
+                continue;
+            }
+
+            let response = server.call(method_name, params);
+            let result = match response.as_ref().and_then(|response| response.find("result")) {
+                Some(result) => check_result(result),
+                None => Err(format!("Expected a result, got: {}", describe(&response))),
+            };
+            report.add_result(SCENARIO, &check, result);
+        }
+    }
+
+    fn check_shutdown(&self, server: &mut ServerUnderTest, report: &mut ConformanceReport) {
+        const SCENARIO : &'static str = "shutdown";
+
+        let response = server.call(REQUEST__Shutdown, Value::Null);
+        report.add_result(SCENARIO, "shutdown returns a null result", match response {
+            Some(ref response) if response.find("result") == Some(&Value::Null) => Ok(()),
+            _ => Err(format!("Expected a null result, got: {}", describe(&response))),
+        });
+
+        let response = server.call(REQUEST__Hover, self.text_document_position());
+        report.add_result(SCENARIO, "a request after shutdown fails with InvalidRequest",
+            expect_error(response, error_JSON_RPC_InvalidRequest("").code));
+
+        server.notify(NOTIFICATION__Exit, Value::Null);
+        report.add_result(SCENARIO, "exit ends the message loop", server.expect_loop_end());
+    }
+
+}
+
+/* ----------------- Result shape checks ----------------- */
+
+type ResultCheck = fn(&Value) -> Result<(), String>;
+
+fn check_array_or_null<FN>(result: &Value, check_element: FN) -> Result<(), String>
+where
+    FN : Fn(&Value) -> Result<(), String>
+{
+    match *result {
+        Value::Null => Ok(()),
+        Value::Array(ref elements) => {
+            for element in elements {
+                try!(check_element(element));
+            }
+            Ok(())
+        }
+        _ => Err(format!("Expected an array or null, got: {}", result)),
+    }
+}
+
+fn check_fields(value: &Value, fields: &[&str]) -> Result<(), String> {
+    if !value.is_object() {
+        return Err(format!("Expected an object, got: {}", value));
+    }
+    for field in fields {
+        if value.find(field).is_none() {
+            return Err(format!("Missing field `{}` in: {}", field, value));
+        }
+    }
+    Ok(())
+}
+
+fn check_hover(result: &Value) -> Result<(), String> {
+    if result.is_null() { Ok(()) } else { check_fields(result, &["contents"]) }
+}
+
+fn check_completion(result: &Value) -> Result<(), String> {
+    match result.find("items") {
+        Some(items) => check_array_or_null(items, |item| check_fields(item, &["label"])),
+        None => check_array_or_null(result, |item| check_fields(item, &["label"])),
+    }
+}
+
+fn check_signature_help(result: &Value) -> Result<(), String> {
+    if result.is_null() { Ok(()) } else { check_fields(result, &["signatures"]) }
+}
+
+fn check_locations(result: &Value) -> Result<(), String> {
+    if result.is_object() {
+        check_fields(result, &["uri", "range"])
+    } else {
+        check_array_or_null(result, |location| check_fields(location, &["uri", "range"]))
+    }
+}
+
+fn check_document_highlights(result: &Value) -> Result<(), String> {
+    check_array_or_null(result, |highlight| check_fields(highlight, &["range"]))
+}
+
+fn check_symbols(result: &Value) -> Result<(), String> {
+    check_array_or_null(result, |symbol| check_fields(symbol, &["name", "kind"]))
+}
+
+fn check_code_actions(result: &Value) -> Result<(), String> {
+    check_array_or_null(result, |action| check_fields(action, &["title"]))
+}
+
+fn check_code_lenses(result: &Value) -> Result<(), String> {
+    check_array_or_null(result, |code_lens| check_fields(code_lens, &["range"]))
+}
+
+fn check_text_edits(result: &Value) -> Result<(), String> {
+    check_array_or_null(result, |edit| check_fields(edit, &["range", "newText"]))
+}
+
+fn check_workspace_edit(result: &Value) -> Result<(), String> {
+    if result.is_null() || result.is_object() {
+        Ok(())
+    } else {
+        Err(format!("Expected a WorkspaceEdit or null, got: {}", result))
+    }
+}
+
+/* ----------------- Helpers ----------------- */
+
+fn object<FN>(build: FN) -> Value
+where
+    FN : FnOnce(ObjectBuilder) -> ObjectBuilder
+{
+    build(ObjectBuilder::new()).build()
+}
+
+fn with_field(mut value: Value, field: &str, field_value: Value) -> Value {
+    if let Some(object) = value.as_object_mut() {
+        object.insert(field.to_string(), field_value);
+    }
+    value
+}
+
+fn is_advertised(capabilities: &Value, capability: &str) -> bool {
+    match capabilities.find(capability) {
+        None | Some(&Value::Null) | Some(&Value::Bool(false)) => false,
+        Some(_) => true,
+    }
+}
+
+fn error_code(response: &Value) -> Option<i64> {
+    response.find_path(&["error", "code"]).and_then(Value::as_i64)
+}
+
+fn expect_error(response: Option<Value>, expected_code: i64) -> Result<(), String> {
+    match response {
+        Some(ref response) if error_code(response) == Some(expected_code) => Ok(()),
+        _ => Err(format!("Expected an error with code {}, got: {}", expected_code, describe(&response))),
+    }
+}
+
+fn describe(response: &Option<Value>) -> String {
+    match *response {
+        Some(ref response) => response.to_string(),
+        None => "no response".to_string(),
+    }
+}
+
+/* ----------------- Server under test ----------------- */
+
+/// A server running its message loop on a separate thread, connected with in-memory pipes.
+/// Its output is read on another thread, and passed on through a channel, to wait for it with a timeout.
+struct ServerUnderTest {
+    input: PipeWriter,
+    output: mpsc::Receiver<Value>,
+    loop_ended: mpsc::Receiver<()>,
+    next_id: u64,
+    timeout: Duration,
+    quiet_period: Duration,
+}
+
+impl ServerUnderTest {
+
+    fn start<LS, FN>(create_server: &FN, timeout: Duration, quiet_period: Duration) -> ServerUnderTest
+    where
+        LS : LanguageServerHandling + Send + 'static,
+        FN : Fn(Endpoint) -> LS,
+    {
+        let (input_writer, mut input_reader) = pipe();
+        let (output_writer, mut output_reader) = pipe();
+        let (output_sender, output_receiver) = mpsc::channel();
+        let (loop_ended_sender, loop_ended_receiver) = mpsc::channel();
+
+        let endpoint = LSPEndpoint::create_lsp_output_with_output_stream(move || output_writer);
+        let ls = create_server(endpoint.clone());
+        thread::spawn(move || {
+            LSPEndpoint::run_server_from_input(&mut input_reader, endpoint, ls);
+            loop_ended_sender.send(()).ok();
+        });
+
+        // Ends once the output is closed, or the checks are over
+        thread::spawn(move || {
+            while let Ok(message) = parse_transport_message(&mut output_reader) {
+                let message = match serde_json::from_str::<Value>(&message) {
+                    Ok(message) => message,
+                    Err(error) => {
+                        error!("Invalid message from the server under test: {}", error);
+                        continue;
+                    }
+                };
+                if output_sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        ServerUnderTest {
+            input : input_writer,
+            output : output_receiver,
+            loop_ended : loop_ended_receiver,
+            next_id : 1,
+            timeout : timeout,
+            quiet_period : quiet_period,
+        }
+    }
+
+    fn send(&mut self, message: Value) {
+        // If the message loop has ended, the checks will fail on the missing responses
+        write_transport_message(&message.to_string(), &mut self.input).ok();
+    }
+
+    fn request(&mut self, method_name: &str, params: Value) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(object(|builder| builder
+            .insert("jsonrpc", "2.0").insert("id", id).insert("method", method_name).insert("params", params)
+        ));
+        id
+    }
+
+    fn notify(&mut self, method_name: &str, params: Value) {
+        self.send(object(|builder| builder
+            .insert("jsonrpc", "2.0").insert("method", method_name).insert("params", params)
+        ));
+    }
+
+    /// Send a request and wait for its response.
+    fn call(&mut self, method_name: &str, params: Value) -> Option<Value> {
+        let id = self.request(method_name, params);
+        self.response(id)
+    }
+
+    /// Wait for the next message from the server, until given deadline.
+    /// Requests from the server are answered with a null result.
+    fn next_message(&mut self, deadline: Instant) -> Option<Value> {
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        let message = match self.output.recv_timeout(deadline - now) {
+            Ok(message) => message,
+            Err(_) => return None,
+        };
+
+        if let (Some(_), Some(id)) = (message.find("method"), message.find("id")) {
+            self.send(object(|builder| builder
+                .insert("jsonrpc", "2.0").insert("id", id).insert("result", Value::Null)
+            ));
+        }
+        Some(message)
+    }
+
+    fn response(&mut self, id: u64) -> Option<Value> {
+        let deadline = Instant::now() + self.timeout;
+        while let Some(message) = self.next_message(deadline) {
+            if message.find("method").is_none() && message.find("id") == Some(&Value::U64(id)) {
+                return Some(message);
+            }
+        }
+        None
+    }
+
+    fn expect_no_response(&mut self) -> Result<(), String> {
+        let deadline = Instant::now() + self.quiet_period;
+        while let Some(message) = self.next_message(deadline) {
+            if message.find("method").is_none() {
+                return Err(format!("Expected no response, got: {}", message));
+            }
+        }
+        Ok(())
+    }
+
+    fn expect_loop_end(&mut self) -> Result<(), String> {
+        match self.loop_ended.recv_timeout(self.timeout) {
+            Ok(()) => Ok(()),
+            Err(_) => Err("The message loop did not end".to_string()),
+        }
+    }
+
+}
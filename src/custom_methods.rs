@@ -0,0 +1,337 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Typed custom (non-standard) methods, for protocol extensions.
+
+A custom method is declared as a type implementing `CustomRequest` or `CustomNotification`,
+usually with the `custom_request!` and `custom_notification!` macros:
+
+```ignore
+custom_request!(
+    /// Return the status of the analyzer.
+    AnalyzerStatus, "rust/analyzerStatus", AnalyzerStatusParams, String
+);
+custom_notification!(ReloadWorkspace, "rust/reloadWorkspace", ());
+```
+
+Handlers are registered in a `CustomMethods`, for a `LanguageServerHandling` or a `LanguageClientHandling`,
+and a `CustomMethodsHandler` dispatches the registered methods to them, and all other methods as before:
+
+```ignore
+let methods = CustomMethods::new()
+    .on_request::<AnalyzerStatus, _>(|ls: &mut MyServer, params, completable| ls.analyzer_status(params, completable))
+    .on_notification::<ReloadWorkspace, _>(|ls: &mut MyServer, _| ls.reload_workspace());
+let handler = CustomMethodsHandler::new(ServerRequestHandler(ls), methods);
+LSPEndpoint::run_endpoint_loop(&mut reader, endpoint, new(handler));
+```
+
+The other side sends them with `CustomRequest::send` and `CustomNotification::send`, or through the rpc handles:
+
+```ignore
+let status = server_rpc_handle(&mut endpoint).request::<AnalyzerStatus>(params);
+client_rpc_handle(&mut endpoint).notify::<ReloadWorkspace>(());
+```
+
+*/
+
+use std::collections::HashMap;
+
+use serde;
+
+use util::core::*;
+
+use jsonrpc::*;
+use jsonrpc::jsonrpc_common::*;
+use jsonrpc::jsonrpc_request::RequestParams;
+
+use lsp::*;
+
+/* ----------------- Declaration ----------------- */
+
+/// A custom request, with its params and result types.
+pub trait CustomRequest {
+    type Params : serde::Serialize + serde::Deserialize;
+    type Result : serde::Serialize + serde::Deserialize;
+
+    fn method_name() -> &'static str;
+
+    fn send(endpoint: &mut Endpoint, params: Self::Params) -> GResult<RequestFuture<Self::Result, ()>> {
+        endpoint.send_request(Self::method_name(), params)
+    }
+}
+
+/// A custom notification, with its params type.
+pub trait CustomNotification {
+    type Params : serde::Serialize + serde::Deserialize;
+
+    fn method_name() -> &'static str;
+
+    fn send(endpoint: &Endpoint, params: Self::Params) -> GResult<()> {
+        endpoint.send_notification(Self::method_name(), params)
+    }
+}
+
+/// Declare a `CustomRequest`: `custom_request!(Name, "method/name", ParamsType, ResultType);`
+#[macro_export]
+macro_rules! custom_request {
+    ($(#[$attr:meta])* $name:ident, $method_name:expr, $params:ty, $result:ty) => {
+        $(#[$attr])*
+        pub enum $name {}
+
+        impl $crate::custom_methods::CustomRequest for $name {
+            type Params = $params;
+            type Result = $result;
+
+            fn method_name() -> &'static str {
+                $method_name
+            }
+        }
+    };
+}
+
+/// Declare a `CustomNotification`: `custom_notification!(Name, "method/name", ParamsType);`
+#[macro_export]
+macro_rules! custom_notification {
+    ($(#[$attr:meta])* $name:ident, $method_name:expr, $params:ty) => {
+        $(#[$attr])*
+        pub enum $name {}
+
+        impl $crate::custom_methods::CustomNotification for $name {
+            type Params = $params;
+
+            fn method_name() -> &'static str {
+                $method_name
+            }
+        }
+    };
+}
+
+/* ----------------- Sending ----------------- */
+
+/// Sending of custom methods, through the rpc handles `LspServerRpc_` and `LspClientRpc_`.
+pub trait CustomMethodRpc {
+    fn request<R : CustomRequest>(&mut self, params: R::Params) -> GResult<RequestFuture<R::Result, ()>>;
+
+    fn notify<N : CustomNotification>(&mut self, params: N::Params) -> GResult<()>;
+}
+
+impl<'a> CustomMethodRpc for LspServerRpc_<'a> {
+    fn request<R : CustomRequest>(&mut self, params: R::Params) -> GResult<RequestFuture<R::Result, ()>> {
+        R::send(self.endpoint, params)
+    }
+
+    fn notify<N : CustomNotification>(&mut self, params: N::Params) -> GResult<()> {
+        N::send(self.endpoint, params)
+    }
+}
+
+impl<'a> CustomMethodRpc for LspClientRpc_<'a> {
+    fn request<R : CustomRequest>(&mut self, params: R::Params) -> GResult<RequestFuture<R::Result, ()>> {
+        R::send(self.endpoint, params)
+    }
+
+    fn notify<N : CustomNotification>(&mut self, params: N::Params) -> GResult<()> {
+        N::send(self.endpoint, params)
+    }
+}
+
+/* ----------------- Handling ----------------- */
+
+type MethodHandler<LS> = Box<Fn(&mut LS, RequestParams, ResponseCompletable)>;
+
+/// The handlers of custom methods, for a handling object of type `LS`
+/// (typically a `LanguageServerHandling` or a `LanguageClientHandling`).
+pub struct CustomMethods<LS : ?Sized> {
+    handlers: HashMap<String, MethodHandler<LS>>,
+}
+
+impl<LS : ?Sized + 'static> CustomMethods<LS> {
+
+    pub fn new() -> CustomMethods<LS> {
+        CustomMethods { handlers : HashMap::new() }
+    }
+
+    /// Register the handler of request `R`. Replaces the handler registered before for the same method, if any.
+    pub fn on_request<R, FN>(mut self, handler: FN) -> CustomMethods<LS>
+    where
+        R : CustomRequest + 'static,
+        FN : Fn(&mut LS, R::Params, LSCompletable<R::Result>) + 'static,
+    {
+        self.handlers.insert(R::method_name().to_string(), new(
+            move |ls: &mut LS, params: RequestParams, completable: ResponseCompletable| {
+                completable.handle_request_with(params, |params, completable| handler(ls, params, completable))
+            }
+        ));
+        self
+    }
+
+    /// Register the handler of notification `N`. Replaces the handler registered before for the same method, if any.
+    pub fn on_notification<N, FN>(mut self, handler: FN) -> CustomMethods<LS>
+    where
+        N : CustomNotification + 'static,
+        FN : Fn(&mut LS, N::Params) + 'static,
+    {
+        self.handlers.insert(N::method_name().to_string(), new(
+            move |ls: &mut LS, params: RequestParams, completable: ResponseCompletable| {
+                completable.handle_notification_with(params, |params| handler(ls, params))
+            }
+        ));
+        self
+    }
+
+    pub fn handles(&self, method_name: &str) -> bool {
+        self.handlers.contains_key(method_name)
+    }
+
+    /// Dispatch a message to its handler, or answer it with a `MethodNotFound` error if it has none.
+    /// Can be used to implement `handle_other_method`.
+    pub fn handle(&self, ls: &mut LS, method_name: &str, params: RequestParams, completable: ResponseCompletable) {
+        match self.handlers.get(method_name) {
+            Some(handler) => handler(ls, params, completable),
+            None => completable.complete_with_error(error_JSON_RPC_MethodNotFound()),
+        }
+    }
+
+}
+
+/// A request handler that dispatches to the methods of a handling object.
+pub trait HandlingTarget {
+    type Target : ?Sized;
+
+    fn target(&mut self) -> &mut Self::Target;
+}
+
+impl<LS : ?Sized> HandlingTarget for ServerRequestHandler<LS> {
+    type Target = LS;
+
+    fn target(&mut self) -> &mut LS {
+        &mut self.0
+    }
+}
+
+impl<LS : ?Sized> HandlingTarget for ClientRequestHandler<LS> {
+    type Target = LS;
+
+    fn target(&mut self) -> &mut LS {
+        &mut self.0
+    }
+}
+
+/// A request handler that dispatches the registered custom methods to their handlers,
+/// and all other methods to `handler` (a `ServerRequestHandler` or a `ClientRequestHandler`).
+pub struct CustomMethodsHandler<HANDLER : HandlingTarget> {
+    pub handler: HANDLER,
+    pub methods: CustomMethods<HANDLER::Target>,
+}
+
+impl<HANDLER : HandlingTarget + RequestHandler> CustomMethodsHandler<HANDLER>
+where
+    HANDLER::Target : 'static
+{
+    pub fn new(handler: HANDLER, methods: CustomMethods<HANDLER::Target>) -> CustomMethodsHandler<HANDLER> {
+        CustomMethodsHandler { handler : handler, methods : methods }
+    }
+}
+
+impl<HANDLER : HandlingTarget + RequestHandler> RequestHandler for CustomMethodsHandler<HANDLER>
+where
+    HANDLER::Target : 'static
+{
+
+    fn handle_request(
+        &mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable
+    ) {
+        if self.methods.handles(method_name) {
+            self.methods.handle(self.handler.target(), method_name, params, completable);
+        } else {
+            self.handler.handle_request(method_name, params, completable);
+        }
+    }
+
+}
+
+
+#[test]
+fn test_CustomMethods() {
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use jsonrpc::jsonrpc_response::Response;
+    use jsonrpc::jsonrpc_response::ResponseResult;
+    use serde_json;
+    use serde_json::Value;
+    use memory_transport::test_messages::ChannelMessageWriter;
+
+    #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+    pub struct AddParams {
+        pub amount: u64,
+    }
+
+    custom_request!(
+        /// Add to the counter, and return its new value.
+        AddRequest, "test/add", AddParams, u64
+    );
+    custom_notification!(ResetNotification, "test/reset", ());
+
+    struct Counter {
+        count: u64,
+    }
+
+    let methods = CustomMethods::<Counter>::new()
+        .on_request::<AddRequest, _>(|counter, params, completable| {
+            counter.count += params.amount;
+            completable.complete(Ok(counter.count));
+        })
+        .on_notification::<ResetNotification, _>(|counter, _| counter.count = 0);
+    assert!(methods.handles("test/add"));
+    assert!(!methods.handles("test/other"));
+
+    let (sender, receiver) = mpsc::channel();
+    let completable = |id| {
+        let sender = sender.clone();
+        ResponseCompletable::new(id, new(move |response: Option<Response>| {
+            sender.send(response.map(|response| response.result_or_error)).unwrap();
+        }))
+    };
+    let params = |value: Value| match value {
+        Value::Object(object) => RequestParams::Object(object),
+        _ => RequestParams::None,
+    };
+
+    let mut counter = Counter { count : 1 };
+    let add_params = params(serde_json::to_value(&AddParams { amount : 2 }));
+    methods.handle(&mut counter, "test/add", add_params, completable(Some(Id::Number(1))));
+    assert_eq!(receiver.recv().unwrap(), Some(ResponseResult::Result(Value::U64(3))));
+
+    methods.handle(&mut counter, "test/reset", RequestParams::None, completable(None));
+    assert_eq!(receiver.recv().unwrap(), None);
+    assert_eq!(counter.count, 0);
+
+    methods.handle(&mut counter, "test/other", RequestParams::None, completable(Some(Id::Number(2))));
+    assert_eq!(receiver.recv().unwrap(), Some(ResponseResult::Error(error_JSON_RPC_MethodNotFound())));
+
+    // Sending
+    let (sender, receiver) = mpsc::channel();
+    let mut endpoint = LSPEndpoint::create_lsp_output(move || ChannelMessageWriter(sender));
+    let next_message = || -> Value {
+        serde_json::from_str(&receiver.recv_timeout(Duration::from_secs(5)).unwrap()).unwrap()
+    };
+
+    server_rpc_handle(&mut endpoint).request::<AddRequest>(AddParams { amount : 2 }).unwrap();
+    let message = next_message();
+    assert_eq!(message.find("method").and_then(Value::as_str), Some("test/add"));
+    assert_eq!(message.find_path(&["params", "amount"]).and_then(Value::as_u64), Some(2));
+    assert!(message.find("id").is_some());
+
+    ResetNotification::send(&endpoint, ()).unwrap();
+    let message = next_message();
+    assert_eq!(message.find("method").and_then(Value::as_str), Some("test/reset"));
+    assert!(message.find("id").is_none());
+
+    endpoint.request_shutdown();
+}
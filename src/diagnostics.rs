@@ -0,0 +1,437 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use util::core::*;
+
+use jsonrpc::Endpoint;
+
+use url::Url;
+
+use ls_types::*;
+use ls_types_ext::*;
+use lsp::LspClientRpc;
+
+/* -----------------  ----------------- */
+
+/// How diagnostics are delivered to the client.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DiagnosticsMode {
+    /// The server sends `textDocument/publishDiagnostics` notifications.
+    Push,
+    /// The client asks for diagnostics with `textDocument/diagnostic` and `workspace/diagnostic` requests.
+    Pull,
+}
+
+impl DiagnosticsMode {
+
+    /// Determine the diagnostics mode from the capabilities the client sent in `initialize`:
+    /// pull if the client declares `textDocument.diagnostic` support, push otherwise.
+    pub fn from_client_capabilities(capabilities: &ClientCapabilities) -> DiagnosticsMode {
+        match capabilities.find_path(&["textDocument", "diagnostic"]) {
+            Some(diagnostic) if !diagnostic.is_null() => DiagnosticsMode::Pull,
+            _ => DiagnosticsMode::Push,
+        }
+    }
+
+}
+
+struct DocumentDiagnostics {
+    result_id: u64,
+    version: Option<u64>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A single source of diagnostics that can answer both push and pull clients.
+///
+/// The server reports diagnostics with `update` regardless of the negotiated mode.
+/// In push mode these are published to the client right away. In pull mode they are stored,
+/// each update getting a new result id, and the `document_diagnostic` and `workspace_diagnostic`
+/// methods answer the client requests, replying with unchanged reports where the client is up to date.
+pub struct DiagnosticsSource {
+    mode: DiagnosticsMode,
+    result_id_counter: u64,
+    documents: HashMap<Url, DocumentDiagnostics>,
+}
+
+impl DiagnosticsSource {
+
+    pub fn new(mode: DiagnosticsMode) -> DiagnosticsSource {
+        DiagnosticsSource { mode : mode, result_id_counter : 0, documents : HashMap::new() }
+    }
+
+    pub fn mode(&self) -> DiagnosticsMode {
+        self.mode
+    }
+
+    /// Set the diagnostics of given document, computed for given document version (if known).
+    pub fn update(&mut self, client: &mut LspClientRpc, uri: Url, version: Option<u64>, diagnostics: Vec<Diagnostic>)
+        -> GResult<()>
+    {
+        if self.mode == DiagnosticsMode::Push {
+            try!(client.publish_diagnostics(PublishDiagnosticsParams::new(uri.clone(), diagnostics.clone())));
+        }
+
+        self.result_id_counter += 1;
+        let entry = DocumentDiagnostics {
+            result_id : self.result_id_counter, version : version, diagnostics : diagnostics
+        };
+        self.documents.insert(uri, entry);
+        Ok(())
+    }
+
+    /// Remove all diagnostics of given document (for example, because it was deleted).
+    pub fn clear(&mut self, client: &mut LspClientRpc, uri: &Url) -> GResult<()> {
+        if self.documents.remove(uri).is_some() && self.mode == DiagnosticsMode::Push {
+            try!(client.publish_diagnostics(PublishDiagnosticsParams::new(uri.clone(), vec![])));
+        }
+        Ok(())
+    }
+
+    /// Answer a `textDocument/diagnostic` request.
+    pub fn document_diagnostic(&self, params: &DocumentDiagnosticParams) -> DocumentDiagnosticReport {
+        let uri = &params.text_document.uri;
+        match self.documents.get(uri) {
+            Some(document) => Self::report_for(document, params.previous_result_id.as_ref()),
+            None => DocumentDiagnosticReport::new_full(None, vec![]),
+        }
+    }
+
+    /// Answer a `workspace/diagnostic` request.
+    ///
+    /// Documents the client has a previous report for, but that are no longer tracked (see `clear`),
+    /// get a full report with no diagnostics, since the client only replaces the reports of documents
+    /// present in the answer.
+    pub fn workspace_diagnostic(&self, params: &WorkspaceDiagnosticParams) -> WorkspaceDiagnosticReport {
+        let mut items : Vec<_> = self.documents.iter().map(|(uri, document)| {
+            let previous_result_id = params.previous_result_ids.iter()
+                .find(|previous| &previous.uri == uri)
+                .map(|previous| &previous.value);
+
+            WorkspaceDocumentDiagnosticReport {
+                uri : uri.clone(),
+                version : document.version,
+                report : Self::report_for(document, previous_result_id),
+            }
+        }).collect();
+
+        for previous in &params.previous_result_ids {
+            if !self.documents.contains_key(&previous.uri) {
+                items.push(WorkspaceDocumentDiagnosticReport {
+                    uri : previous.uri.clone(),
+                    version : None,
+                    report : DocumentDiagnosticReport::new_full(None, vec![]),
+                });
+            }
+        }
+
+        WorkspaceDiagnosticReport { items : items }
+    }
+
+    fn report_for(document: &DocumentDiagnostics, previous_result_id: Option<&String>) -> DocumentDiagnosticReport {
+        let result_id = document.result_id.to_string();
+        if previous_result_id == Some(&result_id) {
+            DocumentDiagnosticReport::new_unchanged(result_id)
+        } else {
+            DocumentDiagnosticReport::new_full(Some(result_id), document.diagnostics.clone())
+        }
+    }
+
+}
+
+/* ----------------- Debounced publishing ----------------- */
+
+struct PendingPublication {
+    deadline: Instant,
+    version: Option<u64>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+struct PublisherState {
+    /// The open documents, with their current version.
+    open_documents: HashMap<Url, Option<u64>>,
+    pending: HashMap<Url, PendingPublication>,
+    shutdown: bool,
+}
+
+impl PublisherState {
+
+    /// Whether diagnostics computed for given version of given document should be dropped.
+    fn is_outdated(&self, uri: &Url, version: Option<u64>) -> bool {
+        match self.open_documents.get(uri) {
+            None => true,
+            Some(&current_version) => match (version, current_version) {
+                (Some(version), Some(current_version)) => version < current_version,
+                _ => false,
+            },
+        }
+    }
+
+    fn publish_due(&mut self, endpoint: &Endpoint, now: Instant) {
+        let due : Vec<Url> = self.pending.iter()
+            .filter(|&(_, publication)| publication.deadline <= now)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+
+        for uri in due {
+            if let Some(publication) = self.pending.remove(&uri) {
+                if !self.is_outdated(&uri, publication.version) {
+                    let params = VersionedPublishDiagnosticsParams::new(
+                        uri, publication.version, publication.diagnostics);
+                    send_diagnostics(endpoint, params);
+                }
+            }
+        }
+    }
+
+}
+
+fn send_diagnostics(endpoint: &Endpoint, params: VersionedPublishDiagnosticsParams) {
+    if endpoint.is_shutdown() {
+        debug!("Endpoint is shut down, not publishing diagnostics for {}", params.uri);
+        return;
+    }
+    if let Err(error) = endpoint.send_notification(NOTIFICATION__PublishDiagnostics, params) {
+        error!("Failed to publish diagnostics: {}", error);
+    }
+}
+
+/// Publishes diagnostics to the client with a delay, so that a burst of updates (typically while the user
+/// is typing) results in a single `textDocument/publishDiagnostics` notification per document.
+///
+/// Each `publish` restarts the delay of its document, and replaces its diagnostics not yet published.
+/// Diagnostics of documents that are not open are dropped, as are those computed for a version older
+/// than the current version of the document. Closing a document clears its diagnostics in the client.
+/// Publications are tagged with the document version given to `publish`.
+///
+/// The server must report the document notifications it receives with `did_open`, `did_change` and `did_close`.
+/// When dropped, the pending publications are discarded.
+pub struct DiagnosticsPublisher {
+    endpoint: Endpoint,
+    delay: Duration,
+    state: Arc<(Mutex<PublisherState>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl DiagnosticsPublisher {
+
+    pub fn new(endpoint: Endpoint, delay: Duration) -> DiagnosticsPublisher {
+        let state = PublisherState { open_documents : HashMap::new(), pending : HashMap::new(), shutdown : false };
+        let state = Arc::new((Mutex::new(state), Condvar::new()));
+
+        let thread = {
+            let state = state.clone();
+            let endpoint = endpoint.clone();
+            thread::spawn(move || run_publishing_loop(&state, &endpoint))
+        };
+
+        DiagnosticsPublisher { endpoint : endpoint, delay : delay, state : state, thread : Some(thread) }
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Lock the state, even if poisoned: the state is left consistent by each update.
+    fn lock_state(&self) -> MutexGuard<PublisherState> {
+        self.state.0.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    pub fn did_open(&self, params: &DidOpenTextDocumentParams) {
+        let document = &params.text_document;
+        self.lock_state().open_documents.insert(document.uri.clone(), document.version);
+    }
+
+    pub fn did_change(&self, params: &DidChangeTextDocumentParams) {
+        let document = &params.text_document;
+        if let Some(version) = self.lock_state().open_documents.get_mut(&document.uri) {
+            *version = Some(document.version);
+        }
+    }
+
+    /// Discard the pending diagnostics of the closed document, and clear its diagnostics in the client.
+    pub fn did_close(&self, params: &DidCloseTextDocumentParams) {
+        let uri = &params.text_document.uri;
+        let mut state = self.lock_state();
+        state.pending.remove(uri);
+        if state.open_documents.remove(uri).is_some() {
+            send_diagnostics(&self.endpoint, VersionedPublishDiagnosticsParams::new(uri.clone(), None, vec![]));
+        }
+    }
+
+    /// Publish the diagnostics of given document, computed for given document version (if known),
+    /// once no other diagnostics are published for that document during the delay.
+    pub fn publish(&self, uri: Url, version: Option<u64>, diagnostics: Vec<Diagnostic>) {
+        let mut state = self.lock_state();
+        if state.is_outdated(&uri, version) {
+            state.pending.remove(&uri);
+            return;
+        }
+
+        let publication = PendingPublication {
+            deadline : Instant::now() + self.delay, version : version, diagnostics : diagnostics
+        };
+        state.pending.insert(uri, publication);
+        self.state.1.notify_all();
+    }
+
+    /// Publish all pending diagnostics now.
+    pub fn flush(&self) {
+        let mut state = self.lock_state();
+        let now = Instant::now();
+        for publication in state.pending.values_mut() {
+            publication.deadline = now;
+        }
+        state.publish_due(&self.endpoint, now);
+    }
+
+}
+
+impl Drop for DiagnosticsPublisher {
+    fn drop(&mut self) {
+        self.lock_state().shutdown = true;
+        self.state.1.notify_all();
+        if let Some(thread) = self.thread.take() {
+            if let Err(_) = thread.join() {
+                error!("Diagnostics publisher thread panicked");
+            }
+        }
+    }
+}
+
+fn run_publishing_loop(state: &(Mutex<PublisherState>, Condvar), endpoint: &Endpoint) {
+    let (ref mutex, ref condvar) = *state;
+    let mut state = mutex.lock().unwrap_or_else(|error| error.into_inner());
+    while !state.shutdown {
+        let now = Instant::now();
+        let next_deadline = state.pending.values().map(|publication| publication.deadline).min();
+        state = match next_deadline {
+            None => condvar.wait(state).unwrap_or_else(|error| error.into_inner()),
+            Some(deadline) if deadline > now => {
+                condvar.wait_timeout(state, deadline - now).unwrap_or_else(|error| error.into_inner()).0
+            }
+            Some(_) => {
+                // Publishing with the lock held, so that a concurrent `did_close` clears after this.
+                state.publish_due(endpoint, now);
+                state
+            }
+        };
+    }
+}
+
+
+#[test]
+fn test_DiagnosticsSource() {
+    use lsp::{LSPEndpoint, client_rpc_handle};
+    use serde_json::Value;
+    use serde_json::builder::ObjectBuilder;
+
+    assert_eq!(DiagnosticsMode::from_client_capabilities(&Value::Null), DiagnosticsMode::Push);
+    let capabilities = ObjectBuilder::new()
+        .insert_object("textDocument", |builder| builder.insert_object("diagnostic", |builder| builder))
+        .build();
+    assert_eq!(DiagnosticsMode::from_client_capabilities(&capabilities), DiagnosticsMode::Pull);
+
+    let mut endpoint = LSPEndpoint::create_lsp_output_with_output_stream(|| Vec::<u8>::new());
+
+    let uri = Url::parse("file:///foo.rs").unwrap();
+    let diagnostic = Diagnostic::new_simple(Range::default(), "error".into());
+    let mut source = DiagnosticsSource::new(DiagnosticsMode::Pull);
+    source.update(&mut client_rpc_handle(&mut endpoint), uri.clone(), Some(1), vec![diagnostic.clone()]).unwrap();
+
+    let mut params = DocumentDiagnosticParams {
+        text_document : TextDocumentIdentifier::new(uri.clone()),
+        identifier : None,
+        previous_result_id : None,
+    };
+    assert_eq!(source.document_diagnostic(&params),
+        DocumentDiagnosticReport::new_full(Some("1".into()), vec![diagnostic.clone()]));
+
+    params.previous_result_id = Some("1".into());
+    assert_eq!(source.document_diagnostic(&params), DocumentDiagnosticReport::new_unchanged("1".into()));
+
+    let workspace_params = WorkspaceDiagnosticParams { identifier : None, previous_result_ids : vec![] };
+    let report = source.workspace_diagnostic(&workspace_params);
+    assert_eq!(report.items.len(), 1);
+    assert_eq!(report.items[0].version, Some(1));
+
+    source.clear(&mut client_rpc_handle(&mut endpoint), &uri).unwrap();
+    params.previous_result_id = None;
+    assert_eq!(source.document_diagnostic(&params), DocumentDiagnosticReport::new_full(None, vec![]));
+
+    // Cleared documents the client has a report for are reported empty
+    let report = source.workspace_diagnostic(&workspace_params);
+    assert!(report.items.is_empty());
+    let workspace_params = WorkspaceDiagnosticParams {
+        identifier : None,
+        previous_result_ids : vec![PreviousResultId { uri : uri.clone(), value : "1".into() }],
+    };
+    let report = source.workspace_diagnostic(&workspace_params);
+    assert_eq!(report.items.len(), 1);
+    assert_eq!(report.items[0].uri, uri);
+    assert_eq!(report.items[0].report, DocumentDiagnosticReport::new_full(None, vec![]));
+
+    endpoint.request_shutdown();
+}
+
+#[test]
+fn test_DiagnosticsPublisher() {
+    use std::sync::mpsc;
+    use lsp::LSPEndpoint;
+    use memory_transport::test_messages::ChannelMessageWriter;
+    use serde_json;
+
+    let (sender, receiver) = mpsc::channel();
+    let endpoint = LSPEndpoint::create_lsp_output(move || ChannelMessageWriter(sender));
+    let next_publication = || {
+        let message = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        let message : serde_json::Value = serde_json::from_str(&message).unwrap();
+        serde_json::from_value::<VersionedPublishDiagnosticsParams>(message.find("params").unwrap().clone()).unwrap()
+    };
+
+    let uri = Url::parse("file:///foo.rs").unwrap();
+    let diagnostic = Diagnostic::new_simple(Range::default(), "error".into());
+    let publisher = DiagnosticsPublisher::new(endpoint.clone(), Duration::from_millis(50));
+
+    // Not open: dropped
+    publisher.publish(uri.clone(), None, vec![diagnostic.clone()]);
+
+    publisher.did_open(&DidOpenTextDocumentParams {
+        text_document : TextDocumentItem::new(uri.clone(), None, Some(1), "".into())
+    });
+    publisher.publish(uri.clone(), Some(1), vec![]);
+    publisher.publish(uri.clone(), Some(1), vec![diagnostic.clone()]);
+    assert_eq!(next_publication(),
+        VersionedPublishDiagnosticsParams::new(uri.clone(), Some(1), vec![diagnostic.clone()]));
+
+    publisher.did_change(&DidChangeTextDocumentParams {
+        text_document : VersionedTextDocumentIdentifier { uri : uri.clone(), version : 2 },
+        content_changes : vec![],
+    });
+    // Outdated: dropped
+    publisher.publish(uri.clone(), Some(1), vec![]);
+    publisher.publish(uri.clone(), Some(2), vec![]);
+    publisher.flush();
+    assert_eq!(next_publication(), VersionedPublishDiagnosticsParams::new(uri.clone(), Some(2), vec![]));
+
+    publisher.publish(uri.clone(), Some(2), vec![diagnostic.clone()]);
+    publisher.did_close(&DidCloseTextDocumentParams { text_document : TextDocumentIdentifier::new(uri.clone()) });
+    assert_eq!(next_publication(), VersionedPublishDiagnosticsParams::new(uri.clone(), None, vec![]));
+
+    drop(publisher);
+    assert!(receiver.try_recv().is_err());
+    endpoint.request_shutdown();
+}
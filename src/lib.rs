@@ -17,6 +17,8 @@ https://github.com/RustDT/RustLSP/blob/master/src/server_tests.rs
 
 extern crate serde_json;
 extern crate serde;
+#[macro_use] extern crate serde_derive;
+extern crate url;
 
 pub extern crate rustdt_util as util;
 pub extern crate jsonrpc;
@@ -25,7 +27,26 @@ pub extern crate languageserver_types as ls_types;
 #[macro_use] extern crate log;
 
 pub mod lsp_transport;
+pub mod ls_types_ext;
 pub mod lsp;
+pub mod diagnostics;
+pub mod settings;
+pub mod negotiation;
+pub mod text_position;
+pub mod line_index;
+pub mod workspace_edit;
+pub mod text_diff;
+pub mod client_session;
+pub mod scheduler;
+pub mod stale_requests;
+pub mod trace;
+pub mod replay;
+pub mod memory_transport;
+pub mod conformance;
+pub mod mock_client;
+pub mod middleware;
+pub mod metrics;
+pub mod custom_methods;
 
 #[cfg(test)]
 mod server_tests;
\ No newline at end of file
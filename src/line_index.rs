@@ -0,0 +1,251 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+
+use util::core::*;
+
+use ls_types::*;
+use ls_types_ext::PositionEncodingKind;
+use text_position;
+
+/* -----------------  ----------------- */
+
+/// An index of the line start offsets of a document text, for fast conversion between
+/// `Position`s and byte offsets.
+///
+/// The index does not own the text: the text it was built for must be given to each conversion,
+/// and must not be modified other than through `apply_change`. Looking up a line is O(log n) in the number
+/// of lines; converting the column is linear in the length of that line only.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LineIndex {
+    encoding: PositionEncodingKind,
+    /// The start offset of each line. The first line always starts at 0.
+    line_starts: Vec<usize>,
+    /// The length of the indexed text.
+    text_len: usize,
+}
+
+impl LineIndex {
+
+    pub fn new(text: &str, encoding: PositionEncodingKind) -> LineIndex {
+        let mut line_index = LineIndex { encoding : encoding, line_starts : vec![0], text_len : text.len() };
+        line_index.add_line_starts(text, 0, text.len());
+        line_index
+    }
+
+    /// Add the starts of the lines after the line starting at `from`, up to (including) `until`.
+    fn add_line_starts(&mut self, text: &str, from: usize, until: usize) {
+        let mut line_start = from;
+        while let Some(next) = text_position::next_line_start(text, line_start) {
+            if next > until {
+                break;
+            }
+            self.line_starts.push(next);
+            line_start = next;
+        }
+    }
+
+    pub fn encoding(&self) -> PositionEncodingKind {
+        self.encoding
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The line containing given offset. An offset within a line terminator belongs to the line it terminates.
+    pub fn line_of_offset(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        }
+    }
+
+    /// The start and end offsets of given line, excluding the line terminator.
+    /// `None` if the text has no such line.
+    pub fn line_range(&self, text: &str, line: u64) -> Option<(usize, usize)> {
+        debug_assert_eq!(text.len(), self.text_len);
+
+        let line = line as usize;
+        if line >= self.line_starts.len() {
+            return None;
+        }
+
+        let start = self.line_starts[line];
+        let end = match self.line_starts.get(line + 1) {
+            None => text.len(),
+            Some(&next_start) => {
+                let bytes = text.as_bytes();
+                if next_start >= 2 && &bytes[next_start - 2 .. next_start] == b"\r\n" {
+                    next_start - 2
+                } else {
+                    next_start - 1
+                }
+            }
+        };
+        Some((start, end))
+    }
+
+    /// Convert given position to a byte offset into `text`.
+    /// See `text_position::position_to_offset`.
+    pub fn position_to_offset(&self, text: &str, position: &Position) -> GResult<usize> {
+        match self.line_range(text, position.line) {
+            Some((start, end)) => {
+                Ok(start + text_position::column_to_byte(&text[start..end], position.character, self.encoding))
+            }
+            None => {
+                Err(format!("Invalid position {}:{}: line is past the end of the document ({} lines).",
+                    position.line, position.character, self.line_count()).into())
+            }
+        }
+    }
+
+    /// Convert given byte offset into `text` to a position.
+    /// See `text_position::offset_to_position`.
+    pub fn offset_to_position(&self, text: &str, offset: usize) -> GResult<Position> {
+        if offset > text.len() {
+            return Err(format!("Invalid offset {}: past the end of the document ({}).", offset, text.len()).into());
+        }
+        if !text.is_char_boundary(offset) {
+            return Err(format!("Invalid offset {}: not at a character boundary.", offset).into());
+        }
+
+        let line = self.line_of_offset(offset);
+        let (start, end) = self.line_range(text, line as u64).expect("line_of_offset returned invalid line");
+        let character = text_position::byte_to_column(&text[start..end], offset - start, self.encoding);
+        Ok(Position::new(line as u64, character))
+    }
+
+    pub fn range_to_offsets(&self, text: &str, range: &Range) -> GResult<(usize, usize)> {
+        let start = try!(self.position_to_offset(text, &range.start));
+        let end = try!(self.position_to_offset(text, &range.end));
+        if start > end {
+            return Err(format!("Invalid range {}:{}-{}:{}: start is after end.",
+                range.start.line, range.start.character, range.end.line, range.end.character).into());
+        }
+        Ok((start, end))
+    }
+
+    pub fn offsets_to_range(&self, text: &str, start: usize, end: usize) -> GResult<Range> {
+        let start = try!(self.offset_to_position(text, start));
+        let end = try!(self.offset_to_position(text, end));
+        Ok(Range::new(start, end))
+    }
+
+    /// Replace the text between given offsets with `new_text`, in both `text` and the index.
+    ///
+    /// Only the lines around the edit are scanned again, the start of the following lines is just shifted.
+    pub fn replace(&mut self, text: &mut String, start: usize, end: usize, new_text: &str) {
+        debug_assert_eq!(text.len(), self.text_len);
+
+        // Rescan from the line before the edit, since the edit may join or split a `\r\n` terminator there.
+        let first_line = self.line_of_offset(start).saturating_sub(1);
+        let rescan_from = self.line_starts[first_line];
+
+        let new_end = start + new_text.len();
+        let following : Vec<usize> = self.line_starts.iter()
+            .filter(|&&line_start| line_start > end)
+            .map(|&line_start| line_start - end + new_end)
+            .collect();
+
+        let mut new_string = String::with_capacity(text.len() - (end - start) + new_text.len());
+        new_string.push_str(&text[..start]);
+        new_string.push_str(new_text);
+        new_string.push_str(&text[end..]);
+        *text = new_string;
+        self.text_len = text.len();
+
+        // Line starts after `new_end` are those of the text following the edit, which are already in `following`.
+        self.line_starts.truncate(first_line + 1);
+        self.add_line_starts(text, rescan_from, new_end);
+        self.line_starts.extend(following);
+    }
+
+    /// Apply given content change to `text` and to the index.
+    /// A change without a range replaces the whole text.
+    pub fn apply_change(&mut self, text: &mut String, change: &TextDocumentContentChangeEvent) -> GResult<()> {
+        match change.range {
+            None => {
+                *text = change.text.clone();
+                *self = LineIndex::new(text, self.encoding);
+            }
+            Some(ref range) => {
+                let (start, end) = try!(self.range_to_offsets(text, range));
+                self.replace(text, start, end, &change.text);
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the content changes of a `textDocument/didChange` notification, in order, to `text` and to the index.
+    /// On error, the changes before the failing one remain applied.
+    pub fn apply_changes(&mut self, text: &mut String, params: &DidChangeTextDocumentParams) -> GResult<()> {
+        for change in &params.content_changes {
+            try!(self.apply_change(text, change));
+        }
+        Ok(())
+    }
+
+}
+
+
+#[test]
+fn test_LineIndex() {
+    use ls_types_ext::PositionEncodingKind::*;
+
+    let text = "a𐐀b\r\nxé\ry\n";
+    let line_index = LineIndex::new(text, UTF16);
+    assert_eq!(line_index.line_starts, vec![0, 8, 12, 14]);
+    assert_eq!(line_index.line_of_offset(6), 0);
+    assert_eq!(line_index.line_of_offset(7), 0);
+    assert_eq!(line_index.line_of_offset(8), 1);
+
+    for line in 0..5 {
+        assert_eq!(line_index.line_range(text, line), text_position::line_range(text, line));
+    }
+    for offset in 0..text.len() + 1 {
+        assert_eq!(line_index.offset_to_position(text, offset).ok(),
+            text_position::offset_to_position(text, offset, UTF16).ok());
+    }
+    let position = Position::new(1, 2);
+    assert_eq!(line_index.position_to_offset(text, &position).unwrap(), 11);
+    assert!(line_index.position_to_offset(text, &Position::new(4, 0)).is_err());
+
+    fn check_replace(text: &str, start: usize, end: usize, new_text: &str) {
+        let mut text = String::from(text);
+        let mut line_index = LineIndex::new(&text, UTF8);
+        line_index.replace(&mut text, start, end, new_text);
+        assert_eq!(line_index, LineIndex::new(&text, UTF8));
+    }
+    check_replace("a\r\nb", 2, 3, "");
+    check_replace("a\rb", 2, 2, "\n");
+    check_replace("a\rb\nc\r\nd", 1, 5, "x\r");
+    check_replace("a\nb\nc", 0, 5, "");
+    check_replace("", 0, 0, "a\n\r\n\rb");
+    check_replace("ab\ncd\n", 4, 4, "\r\n\r");
+
+    let mut text = String::from("fn foo() {\r\n}\r\n");
+    let mut line_index = LineIndex::new(&text, UTF16);
+    let params = DidChangeTextDocumentParams {
+        text_document : VersionedTextDocumentIdentifier { uri : "file:///a.rs".parse().unwrap(), version : 2 },
+        content_changes : vec![
+            TextDocumentContentChangeEvent {
+                range : Some(Range::new(Position::new(1, 0), Position::new(1, 0))),
+                range_length : None,
+                text : "    bar();\n".into(),
+            },
+            TextDocumentContentChangeEvent {
+                range : Some(Range::new(Position::new(0, 3), Position::new(0, 6))),
+                range_length : None,
+                text : "baz".into(),
+            },
+        ],
+    };
+    line_index.apply_changes(&mut text, &params).unwrap();
+    assert_eq!(text, "fn baz() {\r\n    bar();\n}\r\n");
+    assert_eq!(line_index, LineIndex::new(&text, UTF16));
+}
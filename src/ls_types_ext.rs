@@ -0,0 +1,1616 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Protocol types for LSP methods not yet covered by `languageserver-types`.
+
+These follow the same conventions as `ls_types`: a `REQUEST__`/`NOTIFICATION__` constant with the
+method name, followed by the params and result structures of that method.
+
+*/
+
+use std::collections::HashMap;
+
+use serde;
+use serde::de::Error as Error_;
+use serde_json;
+use serde_json::Value;
+
+use jsonrpc::json_util::JsonObject;
+
+use url::Url;
+
+use ls_types::*;
+
+/* ----------------- Auxiliary functions ----------------- */
+
+/// Deserialize a `Value` already read from `DE`, converting any error to a `DE::Error`.
+/// Used by the `Deserialize` impls of types that are a union of several JSON shapes.
+pub fn from_value_de<T, DE>(value: Value) -> Result<T, DE::Error>
+where
+    T : serde::Deserialize,
+    DE : serde::Deserializer,
+{
+    serde_json::from_value(value).map_err(|error| DE::Error::custom(error.to_string()))
+}
+
+/// Serialize `obj` into a JSON object, and add the given extra property to it.
+/// `obj` must serialize to a JSON object.
+pub fn to_object_with<T : serde::Serialize>(obj: &T, key: &str, value: Value) -> JsonObject {
+    let mut object = match serde_json::to_value(obj) {
+        Value::Object(object) => object,
+        _ => panic!("Value did not serialize to a JSON object"),
+    };
+    object.insert(key.into(), value);
+    object
+}
+
+/* ----------------- Item data ----------------- */
+
+/// An item that carries an opaque `data` field, which the client preserves between
+/// the request that produced the item and any follow-up request that receives it back
+/// (for example `textDocument/prepareCallHierarchy` and then `callHierarchy/incomingCalls`).
+pub trait ItemData {
+
+    fn data(&self) -> &Option<Value>;
+
+    fn data_mut(&mut self) -> &mut Option<Value>;
+
+    /// Attach given handler data to this item, replacing any previous data.
+    fn set_data<DATA : serde::Serialize>(&mut self, data: &DATA) {
+        *self.data_mut() = Some(serde_json::to_value(data));
+    }
+
+    /// Obtain the handler data previously attached to this item, if any.
+    fn get_data<DATA : serde::Deserialize>(&self) -> Option<Result<DATA, serde_json::Error>> {
+        self.data().as_ref().map(|data| serde_json::from_value(data.clone()))
+    }
+}
+
+impl ItemData for CompletionItem {
+    fn data(&self) -> &Option<Value> { &self.data }
+    fn data_mut(&mut self) -> &mut Option<Value> { &mut self.data }
+}
+
+impl ItemData for CodeLens {
+    fn data(&self) -> &Option<Value> { &self.data }
+    fn data_mut(&mut self) -> &mut Option<Value> { &mut self.data }
+}
+
+/* ----------------- Call hierarchy ----------------- */
+
+/**
+ The call hierarchy request is sent from the client to the server to return a call hierarchy for the language
+ element of given text document positions. The call hierarchy requests are executed in two steps:
+
+ 1. first a call hierarchy item is resolved for the given text document position
+ 2. for a call hierarchy item the incoming or outgoing call hierarchy items are resolved.
+*/
+pub const REQUEST__PrepareCallHierarchy: &'static str = "textDocument/prepareCallHierarchy";
+
+/// The parameters of a `textDocument/prepareCallHierarchy` request.
+pub type CallHierarchyPrepareParams = TextDocumentPositionParams;
+
+/// Represents programming constructs like functions or constructors in the context of call hierarchy.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CallHierarchyItem {
+    /// The name of this item.
+    pub name: String,
+
+    /// The kind of this item.
+    pub kind: SymbolKind,
+
+    /// More detail for this item, e.g. the signature of a function.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub detail: Option<String>,
+
+    /// The resource identifier of this item.
+    pub uri: Url,
+
+    /// The range enclosing this symbol not including leading/trailing whitespace but everything else,
+    /// e.g. comments and code.
+    pub range: Range,
+
+    /// The range that should be selected and revealed when this symbol is being picked,
+    /// e.g. the name of a function. Must be contained by the `range`.
+    #[serde(rename="selectionRange")]
+    pub selection_range: Range,
+
+    /// A data entry field that is preserved between a call hierarchy prepare and
+    /// incoming calls or outgoing calls requests.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl ItemData for CallHierarchyItem {
+    fn data(&self) -> &Option<Value> { &self.data }
+    fn data_mut(&mut self) -> &mut Option<Value> { &mut self.data }
+}
+
+/// The request is sent from the client to the server to resolve incoming calls for a given call hierarchy item.
+pub const REQUEST__CallHierarchyIncomingCalls: &'static str = "callHierarchy/incomingCalls";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CallHierarchyIncomingCallsParams {
+    pub item: CallHierarchyItem,
+}
+
+/// Represents an incoming call, e.g. a caller of a method or constructor.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CallHierarchyIncomingCall {
+    /// The item that makes the call.
+    pub from: CallHierarchyItem,
+
+    /// The ranges at which the calls appear. This is relative to the caller denoted by `from`.
+    #[serde(rename="fromRanges")]
+    pub from_ranges: Vec<Range>,
+}
+
+/// The request is sent from the client to the server to resolve outgoing calls for a given call hierarchy item.
+pub const REQUEST__CallHierarchyOutgoingCalls: &'static str = "callHierarchy/outgoingCalls";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CallHierarchyOutgoingCallsParams {
+    pub item: CallHierarchyItem,
+}
+
+/// Represents an outgoing call, e.g. calling a getter from a method or a method from a constructor etc.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CallHierarchyOutgoingCall {
+    /// The item that is called.
+    pub to: CallHierarchyItem,
+
+    /// The range at which this item is called. This is the range relative to the caller,
+    /// i.e. the item passed to the `callHierarchy/outgoingCalls` request.
+    #[serde(rename="fromRanges")]
+    pub from_ranges: Vec<Range>,
+}
+
+/* ----------------- Type hierarchy ----------------- */
+
+/**
+ The type hierarchy request is sent from the client to the server to return a type hierarchy for the language
+ element of given text document positions. As with call hierarchy, the super and sub types are then
+ resolved in a second step, from the returned items.
+*/
+pub const REQUEST__PrepareTypeHierarchy: &'static str = "textDocument/prepareTypeHierarchy";
+
+/// The parameters of a `textDocument/prepareTypeHierarchy` request.
+pub type TypeHierarchyPrepareParams = TextDocumentPositionParams;
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct TypeHierarchyItem {
+    /// The name of this item.
+    pub name: String,
+
+    /// The kind of this item.
+    pub kind: SymbolKind,
+
+    /// More detail for this item, e.g. the signature of a function.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub detail: Option<String>,
+
+    /// The resource identifier of this item.
+    pub uri: Url,
+
+    /// The range enclosing this symbol not including leading/trailing whitespace but everything else,
+    /// e.g. comments and code.
+    pub range: Range,
+
+    /// The range that should be selected and revealed when this symbol is being picked,
+    /// e.g. the name of a function. Must be contained by the `range`.
+    #[serde(rename="selectionRange")]
+    pub selection_range: Range,
+
+    /// A data entry field that is preserved between a type hierarchy prepare and
+    /// supertypes or subtypes requests.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl ItemData for TypeHierarchyItem {
+    fn data(&self) -> &Option<Value> { &self.data }
+    fn data_mut(&mut self) -> &mut Option<Value> { &mut self.data }
+}
+
+/// The request is sent from the client to the server to resolve the supertypes for a given type hierarchy item.
+pub const REQUEST__TypeHierarchySupertypes: &'static str = "typeHierarchy/supertypes";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct TypeHierarchySupertypesParams {
+    pub item: TypeHierarchyItem,
+}
+
+/// The request is sent from the client to the server to resolve the subtypes for a given type hierarchy item.
+pub const REQUEST__TypeHierarchySubtypes: &'static str = "typeHierarchy/subtypes";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct TypeHierarchySubtypesParams {
+    pub item: TypeHierarchyItem,
+}
+
+/* ----------------- Inlay hints ----------------- */
+
+/// A `MarkupContent` literal represents a string value which content can be represented in different formats.
+/// Currently `plaintext` and `markdown` are supported formats.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct MarkupContent {
+    /// The type of the Markup, either `plaintext` or `markdown`.
+    pub kind: String,
+
+    /// The content itself.
+    pub value: String,
+}
+
+impl MarkupContent {
+    pub fn new_plaintext(value: String) -> MarkupContent {
+        MarkupContent { kind : "plaintext".into(), value : value }
+    }
+
+    pub fn new_markdown(value: String) -> MarkupContent {
+        MarkupContent { kind : "markdown".into(), value : value }
+    }
+}
+
+/// A tooltip, which can be either a plain string or a `MarkupContent`.
+//type tooltip = string | MarkupContent;
+#[derive(Debug, PartialEq, Clone)]
+pub enum Tooltip {
+    String(String),
+    MarkupContent(MarkupContent),
+}
+
+impl serde::Serialize for Tooltip {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        match *self {
+            Tooltip::String(ref string) => serializer.serialize_str(string),
+            Tooltip::MarkupContent(ref content) => content.serialize(serializer),
+        }
+    }
+}
+
+impl serde::Deserialize for Tooltip {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        match try!(Value::deserialize(deserializer)) {
+            Value::String(string) => Ok(Tooltip::String(string)),
+            value => Ok(Tooltip::MarkupContent(try!(from_value_de::<_, D>(value)))),
+        }
+    }
+}
+
+/**
+ The inlay hints request is sent from the client to the server to compute inlay hints for a given
+ [text document, range] tuple that may be rendered in the editor in place with other text.
+*/
+pub const REQUEST__InlayHint: &'static str = "textDocument/inlayHint";
+
+/// A parameter literal used in inlay hint requests.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct InlayHintParams {
+    /// The text document.
+    #[serde(rename="textDocument")]
+    pub text_document: TextDocumentIdentifier,
+
+    /// The visible document range for which inlay hints should be computed.
+    pub range: Range,
+}
+
+/// Inlay hint information.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct InlayHint {
+    /// The position of this hint.
+    pub position: Position,
+
+    /// The label of this hint. A human readable string or an array of `InlayHintLabelPart` label parts.
+    pub label: InlayHintLabel,
+
+    /// The kind of this hint. Can be omitted in which case the client should fall back to a reasonable default.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub kind: Option<InlayHintKind>,
+
+    /// Optional text edits that are performed when accepting this inlay hint.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="textEdits")]
+    pub text_edits: Option<Vec<TextEdit>>,
+
+    /// The tooltip text when you hover over this item.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub tooltip: Option<Tooltip>,
+
+    /// Render padding before the hint.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="paddingLeft")]
+    pub padding_left: Option<bool>,
+
+    /// Render padding after the hint.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="paddingRight")]
+    pub padding_right: Option<bool>,
+
+    /// A data entry field that is preserved on an inlay hint between
+    /// a `textDocument/inlayHint` and a `inlayHint/resolve` request.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl InlayHint {
+    /// Create an InlayHint with the minimum possible info (position, label and kind).
+    pub fn new_simple(position: Position, label: String, kind: InlayHintKind) -> InlayHint {
+        InlayHint {
+            position : position,
+            label : InlayHintLabel::String(label),
+            kind : Some(kind),
+            text_edits : None,
+            tooltip : None,
+            padding_left : None,
+            padding_right : None,
+            data : None,
+        }
+    }
+}
+
+impl ItemData for InlayHint {
+    fn data(&self) -> &Option<Value> { &self.data }
+    fn data_mut(&mut self) -> &mut Option<Value> { &mut self.data }
+}
+
+//label: string | InlayHintLabelPart[];
+#[derive(Debug, PartialEq, Clone)]
+pub enum InlayHintLabel {
+    String(String),
+    LabelParts(Vec<InlayHintLabelPart>),
+}
+
+impl serde::Serialize for InlayHintLabel {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        match *self {
+            InlayHintLabel::String(ref string) => serializer.serialize_str(string),
+            InlayHintLabel::LabelParts(ref parts) => parts.serialize(serializer),
+        }
+    }
+}
+
+impl serde::Deserialize for InlayHintLabel {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        match try!(Value::deserialize(deserializer)) {
+            Value::String(string) => Ok(InlayHintLabel::String(string)),
+            value => Ok(InlayHintLabel::LabelParts(try!(from_value_de::<_, D>(value)))),
+        }
+    }
+}
+
+/// An inlay hint label part allows for interactive and composite labels of inlay hints.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct InlayHintLabelPart {
+    /// The value of this label part.
+    pub value: String,
+
+    /// The tooltip text when you hover over this label part.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub tooltip: Option<Tooltip>,
+
+    /// An optional source code location that represents this label part.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub location: Option<Location>,
+
+    /// An optional command for this label part.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub command: Option<Command>,
+}
+
+/// Inlay hint kinds.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum InlayHintKind {
+    /// An inlay hint that for a type annotation.
+    Type = 1,
+    /// An inlay hint that is for a parameter.
+    Parameter = 2,
+}
+
+impl serde::Deserialize for InlayHintKind {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        Ok(match try!(u8::deserialize(deserializer)) {
+            1 => InlayHintKind::Type,
+            2 => InlayHintKind::Parameter,
+            _ => {
+                return Err(D::Error::invalid_value("Expected a value of 1 or 2 to deserialize to InlayHintKind"))
+            }
+        })
+    }
+}
+
+impl serde::Serialize for InlayHintKind {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+/// The request is sent from the client to the server to resolve additional information for a given inlay hint.
+pub const REQUEST__InlayHintResolve: &'static str = "inlayHint/resolve";
+
+/**
+ The `workspace/inlayHint/refresh` request is sent from the server to the client. Servers can use it to ask
+ clients to refresh the inlay hints currently shown in editors.
+*/
+pub const REQUEST__InlayHintRefresh: &'static str = "workspace/inlayHint/refresh";
+
+/* ----------------- Inline values ----------------- */
+
+/**
+ The inline value request is sent from the client to the server to compute inline values for a given
+ text document that may be rendered in the editor at the end of lines (typically during debugging).
+*/
+pub const REQUEST__InlineValue: &'static str = "textDocument/inlineValue";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct InlineValueParams {
+    /// The text document.
+    #[serde(rename="textDocument")]
+    pub text_document: TextDocumentIdentifier,
+
+    /// The document range for which inline values should be computed.
+    pub range: Range,
+
+    /// Additional information about the context in which inline values were requested.
+    pub context: InlineValueContext,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct InlineValueContext {
+    /// The stack frame (as a DAP Id) where the execution has stopped.
+    #[serde(rename="frameId")]
+    pub frame_id: i64,
+
+    /// The document range where execution has stopped.
+    /// Typically the end position of the range denotes the line where the inline values are shown.
+    #[serde(rename="stoppedLocation")]
+    pub stopped_location: Range,
+}
+
+//type InlineValue = InlineValueText | InlineValueVariableLookup | InlineValueEvaluatableExpression;
+#[derive(Debug, PartialEq, Clone)]
+pub enum InlineValue {
+    Text(InlineValueText),
+    VariableLookup(InlineValueVariableLookup),
+    EvaluatableExpression(InlineValueEvaluatableExpression),
+}
+
+/// Provide inline value as text.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct InlineValueText {
+    /// The document range for which the inline value applies.
+    pub range: Range,
+
+    /// The text of the inline value.
+    pub text: String,
+}
+
+/// Provide inline value through a variable lookup.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct InlineValueVariableLookup {
+    /// The document range for which the inline value applies.
+    /// The range is used to extract the variable name from the underlying document.
+    pub range: Range,
+
+    /// If specified the name of the variable to look up.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="variableName")]
+    pub variable_name: Option<String>,
+
+    /// How to perform the lookup.
+    #[serde(rename="caseSensitiveLookup")]
+    pub case_sensitive_lookup: bool,
+}
+
+/// Provide an inline value through an expression evaluation.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct InlineValueEvaluatableExpression {
+    /// The document range for which the inline value applies.
+    /// The range is used to extract the evaluatable expression from the underlying document.
+    pub range: Range,
+
+    /// If specified the expression overrides the extracted expression.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub expression: Option<String>,
+}
+
+impl serde::Serialize for InlineValue {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        match *self {
+            InlineValue::Text(ref value) => value.serialize(serializer),
+            InlineValue::VariableLookup(ref value) => value.serialize(serializer),
+            InlineValue::EvaluatableExpression(ref value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl serde::Deserialize for InlineValue {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        let value = try!(Value::deserialize(deserializer));
+        if value.find("text").is_some() {
+            Ok(InlineValue::Text(try!(from_value_de::<_, D>(value))))
+        } else if value.find("caseSensitiveLookup").is_some() {
+            Ok(InlineValue::VariableLookup(try!(from_value_de::<_, D>(value))))
+        } else {
+            Ok(InlineValue::EvaluatableExpression(try!(from_value_de::<_, D>(value))))
+        }
+    }
+}
+
+/**
+ The `workspace/inlineValue/refresh` request is sent from the server to the client. Servers can use it to ask
+ clients to refresh the inline values currently shown in editors.
+*/
+pub const REQUEST__InlineValueRefresh: &'static str = "workspace/inlineValue/refresh";
+
+
+/* ----------------- Pull diagnostics ----------------- */
+
+/**
+ The text document diagnostic request is sent from the client to the server to ask the server to compute
+ the diagnostics for a given document. As with other pull requests the server is asked to compute the
+ diagnostics for the currently synced version of the document.
+*/
+pub const REQUEST__DocumentDiagnostic: &'static str = "textDocument/diagnostic";
+
+/// Parameters of the document diagnostic request.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct DocumentDiagnosticParams {
+    /// The text document.
+    #[serde(rename="textDocument")]
+    pub text_document: TextDocumentIdentifier,
+
+    /// The additional identifier provided during registration.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub identifier: Option<String>,
+
+    /// The result id of a previous response if provided.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="previousResultId")]
+    pub previous_result_id: Option<String>,
+}
+
+/// A diagnostic report with a full set of problems.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct FullDocumentDiagnosticReport {
+    /// An optional result id. If provided it will be sent on the next diagnostic request for the same document.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="resultId")]
+    pub result_id: Option<String>,
+
+    /// The actual items.
+    pub items: Vec<Diagnostic>,
+}
+
+/// A diagnostic report indicating that the last returned report is still accurate.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct UnchangedDocumentDiagnosticReport {
+    /// A result id which will be sent on the next diagnostic request for the same document.
+    #[serde(rename="resultId")]
+    pub result_id: String,
+}
+
+/// The result of a document diagnostic pull request. A report can either be a full report
+/// containing all diagnostics for the requested document or an unchanged report indicating
+/// that nothing has changed in terms of diagnostics in comparison to the last pull request.
+///
+/// On the wire the two variants are distinguished by a `kind` property of `full` or `unchanged`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DocumentDiagnosticReport {
+    Full(FullDocumentDiagnosticReport),
+    Unchanged(UnchangedDocumentDiagnosticReport),
+}
+
+impl DocumentDiagnosticReport {
+    pub fn new_full(result_id: Option<String>, items: Vec<Diagnostic>) -> DocumentDiagnosticReport {
+        DocumentDiagnosticReport::Full(FullDocumentDiagnosticReport { result_id : result_id, items : items })
+    }
+
+    pub fn new_unchanged(result_id: String) -> DocumentDiagnosticReport {
+        DocumentDiagnosticReport::Unchanged(UnchangedDocumentDiagnosticReport { result_id : result_id })
+    }
+
+    fn to_object(&self) -> JsonObject {
+        match *self {
+            DocumentDiagnosticReport::Full(ref report) => to_object_with(report, "kind", Value::String("full".into())),
+            DocumentDiagnosticReport::Unchanged(ref report) => {
+                to_object_with(report, "kind", Value::String("unchanged".into()))
+            }
+        }
+    }
+
+    fn from_object<DE : serde::Deserializer>(mut object: JsonObject) -> Result<Self, DE::Error> {
+        match object.remove("kind") {
+            Some(Value::String(ref kind)) if kind == "full" => {
+                Ok(DocumentDiagnosticReport::Full(try!(from_value_de::<_, DE>(Value::Object(object)))))
+            }
+            Some(Value::String(ref kind)) if kind == "unchanged" => {
+                Ok(DocumentDiagnosticReport::Unchanged(try!(from_value_de::<_, DE>(Value::Object(object)))))
+            }
+            _ => Err(DE::Error::invalid_value(r#"Expected a `kind` of "full" or "unchanged""#)),
+        }
+    }
+}
+
+impl serde::Serialize for DocumentDiagnosticReport {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        self.to_object().serialize(serializer)
+    }
+}
+
+impl serde::Deserialize for DocumentDiagnosticReport {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        let object = try!(from_value_de::<JsonObject, D>(try!(Value::deserialize(deserializer))));
+        Self::from_object::<D>(object)
+    }
+}
+
+/**
+ The workspace diagnostic request is sent from the client to the server to ask the server to compute
+ workspace wide diagnostics which previously where pushed from the server to the client.
+*/
+pub const REQUEST__WorkspaceDiagnostic: &'static str = "workspace/diagnostic";
+
+/// Parameters of the workspace diagnostic request.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct WorkspaceDiagnosticParams {
+    /// The additional identifier provided during registration.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub identifier: Option<String>,
+
+    /// The currently known diagnostic reports with their previous result ids.
+    #[serde(rename="previousResultIds")]
+    pub previous_result_ids: Vec<PreviousResultId>,
+}
+
+/// A previous result id in a workspace pull request.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct PreviousResultId {
+    /// The URI for which the client knows a result id.
+    pub uri: Url,
+
+    /// The value of the previous result id.
+    pub value: String,
+}
+
+/// A workspace diagnostic report.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct WorkspaceDiagnosticReport {
+    pub items: Vec<WorkspaceDocumentDiagnosticReport>,
+}
+
+/// A full or unchanged document diagnostic report for a workspace diagnostic result.
+#[derive(Debug, PartialEq, Clone)]
+pub struct WorkspaceDocumentDiagnosticReport {
+    /// The URI for which diagnostic information is reported.
+    pub uri: Url,
+
+    /// The version number for which the diagnostics are reported.
+    /// If the document is not marked as open `None` can be provided.
+    pub version: Option<u64>,
+
+    pub report: DocumentDiagnosticReport,
+}
+
+impl serde::Serialize for WorkspaceDocumentDiagnosticReport {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        let mut object = self.report.to_object();
+        object.insert("uri".into(), serde_json::to_value(&self.uri));
+        object.insert("version".into(), serde_json::to_value(&self.version));
+        object.serialize(serializer)
+    }
+}
+
+impl serde::Deserialize for WorkspaceDocumentDiagnosticReport {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        let mut object = try!(from_value_de::<JsonObject, D>(try!(Value::deserialize(deserializer))));
+        let uri = try!(from_value_de::<_, D>(object.remove("uri").unwrap_or(Value::Null)));
+        let version = try!(from_value_de::<_, D>(object.remove("version").unwrap_or(Value::Null)));
+        let report = try!(DocumentDiagnosticReport::from_object::<D>(object));
+        Ok(WorkspaceDocumentDiagnosticReport { uri : uri, version : version, report : report })
+    }
+}
+
+/**
+ The `workspace/diagnostic/refresh` request is sent from the server to the client. Servers can use it to ask
+ clients to refresh all needed document and workspace diagnostics.
+*/
+pub const REQUEST__DiagnosticRefresh: &'static str = "workspace/diagnostic/refresh";
+
+
+/* ----------------- Rename preparation ----------------- */
+
+/**
+ The prepare rename request is sent from the client to the server to setup and test the validity of a
+ rename operation at a given location. A `null` result means no rename is valid at the given position.
+*/
+pub const REQUEST__PrepareRename: &'static str = "textDocument/prepareRename";
+
+/// The parameters of a `textDocument/prepareRename` request.
+pub type PrepareRenameParams = TextDocumentPositionParams;
+
+//type PrepareRenameResult = Range | { range: Range, placeholder: string } | { defaultBehavior: boolean };
+#[derive(Debug, PartialEq, Clone)]
+pub enum PrepareRenameResponse {
+    /// The range of the string to rename.
+    Range(Range),
+    /// The range of the string to rename, and a placeholder text of the string content to be renamed.
+    RangeWithPlaceholder { range: Range, placeholder: String },
+    /// The rename position is valid and the client should use its default behavior to compute the rename range.
+    DefaultBehavior { default_behavior: bool },
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+struct RangeWithPlaceholder {
+    range: Range,
+    placeholder: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+struct DefaultBehavior {
+    #[serde(rename="defaultBehavior")]
+    default_behavior: bool,
+}
+
+impl serde::Serialize for PrepareRenameResponse {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        match *self {
+            PrepareRenameResponse::Range(ref range) => range.serialize(serializer),
+            PrepareRenameResponse::RangeWithPlaceholder { ref range, ref placeholder } => {
+                RangeWithPlaceholder { range : range.clone(), placeholder : placeholder.clone() }
+                    .serialize(serializer)
+            }
+            PrepareRenameResponse::DefaultBehavior { default_behavior } => {
+                DefaultBehavior { default_behavior : default_behavior }.serialize(serializer)
+            }
+        }
+    }
+}
+
+impl serde::Deserialize for PrepareRenameResponse {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        let value = try!(Value::deserialize(deserializer));
+        if value.find("defaultBehavior").is_some() {
+            let value : DefaultBehavior = try!(from_value_de::<_, D>(value));
+            Ok(PrepareRenameResponse::DefaultBehavior { default_behavior : value.default_behavior })
+        } else if value.find("placeholder").is_some() {
+            let value : RangeWithPlaceholder = try!(from_value_de::<_, D>(value));
+            Ok(PrepareRenameResponse::RangeWithPlaceholder { range : value.range, placeholder : value.placeholder })
+        } else {
+            Ok(PrepareRenameResponse::Range(try!(from_value_de::<_, D>(value))))
+        }
+    }
+}
+
+/* ----------------- Linked editing range ----------------- */
+
+/**
+ The linked editing request is sent from the client to the server to return for a given position in a document
+ the range of the symbol at the position and all ranges that have the same content.
+*/
+pub const REQUEST__LinkedEditingRange: &'static str = "textDocument/linkedEditingRange";
+
+/// The parameters of a `textDocument/linkedEditingRange` request.
+pub type LinkedEditingRangeParams = TextDocumentPositionParams;
+
+/// The result of a linked editing range request.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct LinkedEditingRanges {
+    /// A list of ranges that can be renamed together. The ranges must have identical length
+    /// and contain identical text content. The ranges cannot overlap.
+    pub ranges: Vec<Range>,
+
+    /// An optional word pattern (regular expression) that describes valid contents for the given ranges.
+    /// If no pattern is provided, the client configuration's word pattern will be used.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="wordPattern")]
+    pub word_pattern: Option<String>,
+}
+
+
+/* ----------------- Workspace folders ----------------- */
+
+/// A workspace folder, as returned by `workspace/workspaceFolders`.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct WorkspaceFolder {
+    /// The associated URI for this workspace folder.
+    pub uri: Url,
+
+    /// The name of the workspace folder. Used to refer to this workspace folder in the user interface.
+    pub name: String,
+}
+
+/**
+ The `workspace/workspaceFolders` request is sent from the server to the client to fetch the current
+ open list of workspace folders. Returns `null` in the response if only a single file is open in the tool.
+ Returns an empty array if a workspace is open but no folders are configured.
+*/
+pub const REQUEST__WorkspaceFolders: &'static str = "workspace/workspaceFolders";
+
+/**
+ The `workspace/didChangeWorkspaceFolders` notification is sent from the client to the server to inform
+ the server about workspace folder configuration changes.
+*/
+pub const NOTIFICATION__DidChangeWorkspaceFolders: &'static str = "workspace/didChangeWorkspaceFolders";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct DidChangeWorkspaceFoldersParams {
+    /// The actual workspace folder change event.
+    pub event: WorkspaceFoldersChangeEvent,
+}
+
+/// The workspace folder change event.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct WorkspaceFoldersChangeEvent {
+    /// The array of added workspace folders.
+    pub added: Vec<WorkspaceFolder>,
+
+    /// The array of the removed workspace folders.
+    pub removed: Vec<WorkspaceFolder>,
+}
+
+/* ----------------- File operations ----------------- */
+
+/**
+ The will create files request is sent from the client to the server before files are actually created
+ as long as the creation is triggered from within the client. The request can return a `WorkspaceEdit`
+ which will be applied to the workspace before the files are created.
+*/
+pub const REQUEST__WillCreateFiles: &'static str = "workspace/willCreateFiles";
+
+/// The did create files notification is sent from the client to the server when files were created
+/// from within the client.
+pub const NOTIFICATION__DidCreateFiles: &'static str = "workspace/didCreateFiles";
+
+/// The parameters sent in notifications/requests for user-initiated creation of files.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CreateFilesParams {
+    /// An array of all files/folders created in this operation.
+    pub files: Vec<FileCreate>,
+}
+
+/// Represents information on a file/folder create.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct FileCreate {
+    /// A file:// URI for the location of the file/folder being created.
+    pub uri: Url,
+}
+
+/**
+ The will rename files request is sent from the client to the server before files are actually renamed
+ as long as the rename is triggered from within the client. The request can return a `WorkspaceEdit`
+ which will be applied to the workspace before the files are renamed.
+*/
+pub const REQUEST__WillRenameFiles: &'static str = "workspace/willRenameFiles";
+
+/// The did rename files notification is sent from the client to the server when files were renamed
+/// from within the client.
+pub const NOTIFICATION__DidRenameFiles: &'static str = "workspace/didRenameFiles";
+
+/// The parameters sent in notifications/requests for user-initiated renames of files.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RenameFilesParams {
+    /// An array of all files/folders renamed in this operation. When a folder is renamed,
+    /// only the folder will be included, and not its children.
+    pub files: Vec<FileRename>,
+}
+
+/// Represents information on a file/folder rename.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct FileRename {
+    /// A file:// URI for the original location of the file/folder being renamed.
+    #[serde(rename="oldUri")]
+    pub old_uri: Url,
+
+    /// A file:// URI for the new location of the file/folder being renamed.
+    #[serde(rename="newUri")]
+    pub new_uri: Url,
+}
+
+/**
+ The will delete files request is sent from the client to the server before files are actually deleted
+ as long as the deletion is triggered from within the client. The request can return a `WorkspaceEdit`
+ which will be applied to the workspace before the files are deleted.
+*/
+pub const REQUEST__WillDeleteFiles: &'static str = "workspace/willDeleteFiles";
+
+/// The did delete files notification is sent from the client to the server when files were deleted
+/// from within the client.
+pub const NOTIFICATION__DidDeleteFiles: &'static str = "workspace/didDeleteFiles";
+
+/// The parameters sent in notifications/requests for user-initiated deletes of files.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct DeleteFilesParams {
+    /// An array of all files/folders deleted in this operation.
+    pub files: Vec<FileDelete>,
+}
+
+/// Represents information on a file/folder delete.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct FileDelete {
+    /// A file:// URI for the location of the file/folder being deleted.
+    pub uri: Url,
+}
+
+
+/* ----------------- Configuration ----------------- */
+
+/**
+ The `workspace/configuration` request is sent from the server to the client to fetch configuration settings
+ from the client. The request can fetch several configuration settings in one roundtrip.
+ The order of the returned configuration settings correspond to the order of the passed `ConfigurationItem`s.
+*/
+pub const REQUEST__Configuration: &'static str = "workspace/configuration";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct ConfigurationParams {
+    pub items: Vec<ConfigurationItem>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize, Serialize)]
+pub struct ConfigurationItem {
+    /// The scope to get the configuration section for.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="scopeUri")]
+    pub scope_uri: Option<Url>,
+
+    /// The configuration section asked for.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub section: Option<String>,
+}
+
+impl ConfigurationItem {
+    pub fn new(scope_uri: Option<Url>, section: Option<String>) -> ConfigurationItem {
+        ConfigurationItem { scope_uri : scope_uri, section : section }
+    }
+}
+
+
+/* ----------------- Show document ----------------- */
+
+/**
+ The show document request is sent from a server to a client to ask the client to display a particular resource
+ referenced by a URI in the user interface.
+*/
+pub const REQUEST__ShowDocument: &'static str = "window/showDocument";
+
+/// Params to show a resource.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct ShowDocumentParams {
+    /// The uri to show.
+    pub uri: Url,
+
+    /// Indicates to show the resource in an external program.
+    /// To show, for example, `https://code.visualstudio.com/` in the default WEB browser set `external` to `true`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub external: Option<bool>,
+
+    /// An optional property to indicate whether the editor showing the document should take focus or not.
+    /// Clients might ignore this property if an external program is started.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="takeFocus")]
+    pub take_focus: Option<bool>,
+
+    /// An optional selection range if the document is a text document.
+    /// Clients might ignore the property if an external program is started or the file is not a text file.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub selection: Option<Range>,
+}
+
+impl ShowDocumentParams {
+    pub fn new(uri: Url, take_focus: bool, selection: Option<Range>) -> ShowDocumentParams {
+        ShowDocumentParams { uri : uri, external : None, take_focus : Some(take_focus), selection : selection }
+    }
+}
+
+/// The result of a show document request.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct ShowDocumentResult {
+    /// A boolean indicating if the show was successful.
+    pub success: bool,
+}
+
+/* ----------------- Work done progress ----------------- */
+
+pub type ProgressToken = NumberOrString;
+
+/**
+ The `window/workDoneProgress/create` request is sent from the server to the client to ask the client
+ to create a work done progress.
+*/
+pub const REQUEST__WorkDoneProgressCreate: &'static str = "window/workDoneProgress/create";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct WorkDoneProgressCreateParams {
+    /// The token to be used to report progress.
+    pub token: ProgressToken,
+}
+
+/**
+ The `window/workDoneProgress/cancel` notification is sent from the client to the server to cancel a progress
+ initiated on the server side using the `window/workDoneProgress/create`.
+*/
+pub const NOTIFICATION__WorkDoneProgressCancel: &'static str = "window/workDoneProgress/cancel";
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct WorkDoneProgressCancelParams {
+    /// The token to be used to report progress.
+    pub token: ProgressToken,
+}
+
+
+
+/* ----------------- Position encoding ----------------- */
+
+/**
+ The encoding of the `character` offsets of a `Position`.
+ The client lists the encodings it supports in the `general.positionEncodings` capability,
+ and the server picks one, returning it in the `positionEncoding` server capability.
+ If none is negotiated, `UTF16` must be used.
+*/
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum PositionEncodingKind {
+    /// Character offsets count UTF-8 code units (bytes).
+    UTF8,
+    /// Character offsets count UTF-16 code units. This is the default.
+    UTF16,
+    /// Character offsets count UTF-32 code units (Unicode scalar values).
+    UTF32,
+}
+
+impl PositionEncodingKind {
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            PositionEncodingKind::UTF8 => "utf-8",
+            PositionEncodingKind::UTF16 => "utf-16",
+            PositionEncodingKind::UTF32 => "utf-32",
+        }
+    }
+
+    pub fn from_str(kind: &str) -> Option<PositionEncodingKind> {
+        match kind {
+            "utf-8" => Some(PositionEncodingKind::UTF8),
+            "utf-16" => Some(PositionEncodingKind::UTF16),
+            "utf-32" => Some(PositionEncodingKind::UTF32),
+            _ => None,
+        }
+    }
+
+}
+
+impl Default for PositionEncodingKind {
+    fn default() -> Self {
+        PositionEncodingKind::UTF16
+    }
+}
+
+impl serde::Deserialize for PositionEncodingKind {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        let kind = try!(String::deserialize(deserializer));
+        PositionEncodingKind::from_str(&kind).ok_or_else(|| {
+            D::Error::invalid_value("Expected one of `utf-8`, `utf-16` or `utf-32` for PositionEncodingKind")
+        })
+    }
+}
+
+impl serde::Serialize for PositionEncodingKind {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+
+
+/* ----------------- Workspace edit document changes ----------------- */
+
+/**
+ The `workspace/applyEdit` request is sent from the server to the client to modify resources on the client side.
+*/
+pub const REQUEST__ApplyEdit: &'static str = "workspace/applyEdit";
+
+/// A text document identifier, with an optional version.
+/// A `None` version means the edit applies regardless of the document version.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct OptionalVersionedTextDocumentIdentifier {
+    /// The text document's URI.
+    pub uri: Url,
+
+    /// The version number of this document, or `None` if the version is not known.
+    pub version: Option<u64>,
+}
+
+/// Describes textual changes on a single text document.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct TextDocumentEdit {
+    /// The text document to change.
+    #[serde(rename="textDocument")]
+    pub text_document: OptionalVersionedTextDocumentIdentifier,
+
+    /// The edits to be applied.
+    pub edits: Vec<TextEdit>,
+}
+
+/// Options to create a file.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct CreateFileOptions {
+    /// Overwrite existing file. Overwrite wins over `ignoreIfExists`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub overwrite: Option<bool>,
+
+    /// Ignore if exists.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="ignoreIfExists")]
+    pub ignore_if_exists: Option<bool>,
+}
+
+/// Create file operation.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct CreateFile {
+    /// The resource to create.
+    pub uri: Url,
+
+    /// Additional options.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub options: Option<CreateFileOptions>,
+}
+
+/// Rename file options.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct RenameFileOptions {
+    /// Overwrite target if existing. Overwrite wins over `ignoreIfExists`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub overwrite: Option<bool>,
+
+    /// Ignores if target exists.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="ignoreIfExists")]
+    pub ignore_if_exists: Option<bool>,
+}
+
+/// Rename file operation.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct RenameFile {
+    /// The old (existing) location.
+    #[serde(rename="oldUri")]
+    pub old_uri: Url,
+
+    /// The new location.
+    #[serde(rename="newUri")]
+    pub new_uri: Url,
+
+    /// Rename options.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub options: Option<RenameFileOptions>,
+}
+
+/// Delete file options.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct DeleteFileOptions {
+    /// Delete the content recursively if a folder is denoted.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub recursive: Option<bool>,
+
+    /// Ignore the operation if the file doesn't exist.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="ignoreIfNotExists")]
+    pub ignore_if_not_exists: Option<bool>,
+}
+
+/// Delete file operation.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct DeleteFile {
+    /// The file to delete.
+    pub uri: Url,
+
+    /// Delete options.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub options: Option<DeleteFileOptions>,
+}
+
+/// An entry of `WorkspaceEditWithDocumentChanges::document_changes`.
+///
+/// On the wire the file operations are distinguished by a `kind` property of `create`, `rename` or `delete`.
+/// Text document edits have no `kind`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DocumentChange {
+    Edit(TextDocumentEdit),
+    Create(CreateFile),
+    Rename(RenameFile),
+    Delete(DeleteFile),
+}
+
+impl DocumentChange {
+    fn to_value(&self) -> Value {
+        match *self {
+            DocumentChange::Edit(ref edit) => serde_json::to_value(edit),
+            DocumentChange::Create(ref op) => Value::Object(to_object_with(op, "kind", Value::String("create".into()))),
+            DocumentChange::Rename(ref op) => Value::Object(to_object_with(op, "kind", Value::String("rename".into()))),
+            DocumentChange::Delete(ref op) => Value::Object(to_object_with(op, "kind", Value::String("delete".into()))),
+        }
+    }
+
+    fn from_object<DE : serde::Deserializer>(mut object: JsonObject) -> Result<Self, DE::Error> {
+        let kind = object.remove("kind");
+        let value = Value::Object(object);
+        match kind {
+            None => Ok(DocumentChange::Edit(try!(from_value_de::<_, DE>(value)))),
+            Some(Value::String(ref kind)) if kind == "create" => {
+                Ok(DocumentChange::Create(try!(from_value_de::<_, DE>(value))))
+            }
+            Some(Value::String(ref kind)) if kind == "rename" => {
+                Ok(DocumentChange::Rename(try!(from_value_de::<_, DE>(value))))
+            }
+            Some(Value::String(ref kind)) if kind == "delete" => {
+                Ok(DocumentChange::Delete(try!(from_value_de::<_, DE>(value))))
+            }
+            _ => Err(DE::Error::invalid_value(r#"Expected a `kind` of "create", "rename" or "delete""#)),
+        }
+    }
+}
+
+impl serde::Serialize for DocumentChange {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl serde::Deserialize for DocumentChange {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        let object = try!(from_value_de::<JsonObject, D>(try!(Value::deserialize(deserializer))));
+        Self::from_object::<D>(object)
+    }
+}
+
+/// A `WorkspaceEdit` that can also carry versioned text document edits and file operations,
+/// for clients that declare `workspace.workspaceEdit.documentChanges` support.
+///
+/// If `document_changes` is present, clients use it in preference to `changes`.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct WorkspaceEditWithDocumentChanges {
+    /// Holds changes to existing resources.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub changes: Option<HashMap<Url, Vec<TextEdit>>>,
+
+    /// Text document edits and file operations, to be applied in order.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="documentChanges")]
+    pub document_changes: Option<Vec<DocumentChange>>,
+}
+
+impl From<WorkspaceEdit> for WorkspaceEditWithDocumentChanges {
+    fn from(edit: WorkspaceEdit) -> Self {
+        WorkspaceEditWithDocumentChanges { changes : Some(edit.changes), document_changes : None }
+    }
+}
+
+
+
+/* ----------------- Error codes ----------------- */
+
+/// A request was received before the `initialize` request.
+pub const ERROR_CODE__ServerNotInitialized: i64 = -32002;
+
+/// The client has canceled a request and the server has detected the cancel.
+pub const ERROR_CODE__RequestCancelled: i64 = -32800;
+
+/// The server detected that the content of a document got modified outside normal conditions.
+/// A server should NOT send this error code if it detects a content change in its unprocessed messages.
+/// The result even computed on an older state might still be useful for the client.
+///
+/// If a client decides that a result is not of any use anymore the client should cancel the request.
+pub const ERROR_CODE__ContentModified: i64 = -32801;
+
+/* ----------------- Versioned diagnostics ----------------- */
+
+/// `PublishDiagnosticsParams` with the optional `version` field (LSP 3.15).
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct VersionedPublishDiagnosticsParams {
+    /// The URI for which diagnostic information is reported.
+    pub uri: Url,
+
+    /// The version number of the document the diagnostics are published for.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub version: Option<u64>,
+
+    /// An array of diagnostic information items.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl VersionedPublishDiagnosticsParams {
+    pub fn new(uri: Url, version: Option<u64>, diagnostics: Vec<Diagnostic>) -> VersionedPublishDiagnosticsParams {
+        VersionedPublishDiagnosticsParams { uri : uri, version : version, diagnostics : diagnostics }
+    }
+}
+
+/* ----------------- Trace ----------------- */
+
+/**
+ A notification that should be used by the client to modify the trace setting of the server.
+*/
+pub const NOTIFICATION__SetTrace: &'static str = "$/setTrace";
+
+/**
+ A notification to log the trace of the server's execution. The amount and content of these notifications
+ depends on the current trace configuration: if `off`, the server should not send any `logTrace` notification,
+ if `messages`, the server should not add the `verbose` field.
+*/
+pub const NOTIFICATION__LogTrace: &'static str = "$/logTrace";
+
+/// The level of verbosity with which the server systematically reports its execution trace.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum TraceValue {
+    Off,
+    Messages,
+    Verbose,
+}
+
+impl TraceValue {
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            TraceValue::Off => "off",
+            TraceValue::Messages => "messages",
+            TraceValue::Verbose => "verbose",
+        }
+    }
+
+    pub fn from_str(value: &str) -> Option<TraceValue> {
+        match value {
+            "off" => Some(TraceValue::Off),
+            "messages" => Some(TraceValue::Messages),
+            "verbose" => Some(TraceValue::Verbose),
+            _ => None,
+        }
+    }
+
+}
+
+impl Default for TraceValue {
+    fn default() -> Self {
+        TraceValue::Off
+    }
+}
+
+impl serde::Deserialize for TraceValue {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        let value = try!(String::deserialize(deserializer));
+        TraceValue::from_str(&value).ok_or_else(|| {
+            D::Error::invalid_value("Expected one of `off`, `messages` or `verbose` for TraceValue")
+        })
+    }
+}
+
+impl serde::Serialize for TraceValue {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct SetTraceParams {
+    /// The new value that should be assigned to the trace setting.
+    pub value: TraceValue,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct LogTraceParams {
+    /// The message to be logged.
+    pub message: String,
+
+    /// Additional information that can be computed if the `trace` configuration is set to `verbose`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub verbose: Option<String>,
+}
+
+
+
+/* ----------------- Tests ----------------- */
+
+#[cfg(test)]
+pub fn test_serialization<SER>(obj: &SER, expected: &str)
+where SER : serde::Serialize + serde::Deserialize + PartialEq + ::std::fmt::Debug
+{
+    let json_str = serde_json::to_string(obj).unwrap();
+    assert_eq!(&json_str, expected);
+    let deserialized : SER = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(&deserialized, obj);
+}
+
+#[test]
+fn test_CallHierarchyItem() {
+    let range = Range::new(Position::new(1, 0), Position::new(3, 1));
+    let selection_range = Range::new(Position::new(1, 3), Position::new(1, 6));
+    let mut item = CallHierarchyItem {
+        name : "foo".into(),
+        kind : SymbolKind::Function,
+        detail : None,
+        uri : Url::parse("file:///foo.rs").unwrap(),
+        range : range,
+        selection_range : selection_range,
+        data : None,
+    };
+
+    test_serialization(&item, concat!(
+        r#"{"name":"foo","kind":12,"uri":"file:///foo.rs","#,
+        r#""range":{"start":{"line":1,"character":0},"end":{"line":3,"character":1}},"#,
+        r#""selectionRange":{"start":{"line":1,"character":3},"end":{"line":1,"character":6}}}"#,
+    ));
+
+    assert!(item.get_data::<u64>().is_none());
+    item.set_data(&42u64);
+    assert_eq!(item.get_data::<u64>().unwrap().unwrap(), 42);
+    assert!(item.get_data::<String>().unwrap().is_err());
+}
+
+#[test]
+fn test_InlayHint() {
+    test_serialization(
+        &InlayHint::new_simple(Position::new(2, 10), ": u32".into(), InlayHintKind::Type),
+        r#"{"position":{"line":2,"character":10},"label":": u32","kind":1}"#
+    );
+
+    let part = InlayHintLabelPart { value : "u32".into(), tooltip : None, location : None, command : None };
+    test_serialization(
+        &InlayHintLabel::LabelParts(vec![part]),
+        r#"[{"value":"u32"}]"#
+    );
+
+    test_serialization(
+        &Tooltip::MarkupContent(MarkupContent::new_markdown("*x*".into())),
+        r#"{"kind":"markdown","value":"*x*"}"#
+    );
+}
+
+#[test]
+fn test_InlineValue() {
+    let range = Range::new(Position::new(0, 4), Position::new(0, 5));
+    test_serialization(
+        &InlineValue::Text(InlineValueText { range : range, text : "x = 1".into() }),
+        r#"{"range":{"start":{"line":0,"character":4},"end":{"line":0,"character":5}},"text":"x = 1"}"#
+    );
+    test_serialization(
+        &InlineValue::VariableLookup(InlineValueVariableLookup {
+            range : range, variable_name : None, case_sensitive_lookup : true
+        }),
+        r#"{"range":{"start":{"line":0,"character":4},"end":{"line":0,"character":5}},"caseSensitiveLookup":true}"#
+    );
+    test_serialization(
+        &InlineValue::EvaluatableExpression(InlineValueEvaluatableExpression { range : range, expression : None }),
+        r#"{"range":{"start":{"line":0,"character":4},"end":{"line":0,"character":5}}}"#
+    );
+}
+
+#[test]
+fn test_DocumentDiagnosticReport() {
+    test_serialization(
+        &DocumentDiagnosticReport::new_full(Some("1".into()), vec![]),
+        r#"{"items":[],"kind":"full","resultId":"1"}"#
+    );
+    test_serialization(
+        &DocumentDiagnosticReport::new_unchanged("1".into()),
+        r#"{"kind":"unchanged","resultId":"1"}"#
+    );
+    test_serialization(
+        &WorkspaceDocumentDiagnosticReport {
+            uri : Url::parse("file:///foo.rs").unwrap(),
+            version : None,
+            report : DocumentDiagnosticReport::new_unchanged("2".into()),
+        },
+        r#"{"kind":"unchanged","resultId":"2","uri":"file:///foo.rs","version":null}"#
+    );
+
+    let error = serde_json::from_str::<DocumentDiagnosticReport>(r#"{"kind":"blah","items":[]}"#).unwrap_err();
+    assert!(error.to_string().contains("kind"));
+}
+
+#[test]
+fn test_PrepareRenameResponse() {
+    let range = Range::new(Position::new(0, 4), Position::new(0, 7));
+    test_serialization(
+        &PrepareRenameResponse::Range(range),
+        r#"{"start":{"line":0,"character":4},"end":{"line":0,"character":7}}"#
+    );
+    test_serialization(
+        &PrepareRenameResponse::RangeWithPlaceholder { range : range, placeholder : "foo".into() },
+        r#"{"range":{"start":{"line":0,"character":4},"end":{"line":0,"character":7}},"placeholder":"foo"}"#
+    );
+    test_serialization(
+        &PrepareRenameResponse::DefaultBehavior { default_behavior : true },
+        r#"{"defaultBehavior":true}"#
+    );
+    test_serialization(&None::<PrepareRenameResponse>, r#"null"#);
+}
+
+#[test]
+fn test_WorkspaceFolders() {
+    let folder = |uri: &str, name: &str| WorkspaceFolder { uri : Url::parse(uri).unwrap(), name : name.into() };
+    test_serialization(
+        &DidChangeWorkspaceFoldersParams {
+            event : WorkspaceFoldersChangeEvent {
+                added : vec![folder("file:///foo", "foo")],
+                removed : vec![folder("file:///bar", "bar")],
+            }
+        },
+        concat!(
+            r#"{"event":{"added":[{"uri":"file:///foo","name":"foo"}],"#,
+            r#""removed":[{"uri":"file:///bar","name":"bar"}]}}"#,
+        )
+    );
+}
+
+#[test]
+fn test_FileRename() {
+    test_serialization(
+        &FileRename {
+            old_uri : Url::parse("file:///foo.rs").unwrap(),
+            new_uri : Url::parse("file:///bar.rs").unwrap(),
+        },
+        r#"{"oldUri":"file:///foo.rs","newUri":"file:///bar.rs"}"#
+    );
+}
+
+#[test]
+fn test_DocumentChange() {
+    let uri = Url::parse("file:///foo.rs").unwrap();
+    test_serialization(
+        &DocumentChange::Rename(RenameFile {
+            old_uri : uri.clone(),
+            new_uri : Url::parse("file:///bar.rs").unwrap(),
+            options : None,
+        }),
+        r#"{"kind":"rename","newUri":"file:///bar.rs","oldUri":"file:///foo.rs"}"#
+    );
+    test_serialization(
+        &DocumentChange::Edit(TextDocumentEdit {
+            text_document : OptionalVersionedTextDocumentIdentifier { uri : uri.clone(), version : Some(3) },
+            edits : vec![],
+        }),
+        r#"{"edits":[],"textDocument":{"uri":"file:///foo.rs","version":3}}"#
+    );
+}
@@ -7,6 +7,12 @@
 
 
 use std::io;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use util::core::*;
 
@@ -18,10 +24,13 @@ use jsonrpc::output_agent::OutputAgent;
 
 use jsonrpc::method_types::MethodError;
 use jsonrpc::jsonrpc_request::RequestParams;
+use jsonrpc::jsonrpc_response::ResponseResult;
 
 use lsp_transport::LSPMessageWriter;
 use lsp_transport::LSPMessageReader;
 use ls_types::*;
+use ls_types_ext::*;
+use serde;
 use serde_json::Value;
 
 /* -----------------  ----------------- */
@@ -113,7 +122,13 @@ impl LSPEndpoint {
 pub type LSResult<RET, ERR_DATA> = Result<RET, MethodError<ERR_DATA>>;
 pub type LSCompletable<RET> = MethodCompletable<RET, ()>;
 
+/// The whole number of milliseconds in given duration, as used in logs, timestamps and metrics.
+pub fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
 /// Trait for the handling of LSP server requests
+#[allow(unused_variables)]
 pub trait LanguageServerHandling {
     
     fn initialize(&mut self, params: InitializeParams, completable: MethodCompletable<InitializeResult, InitializeError>);
@@ -145,6 +160,76 @@ pub trait LanguageServerHandling {
     fn on_type_formatting(&mut self, params: DocumentOnTypeFormattingParams, completable: LSCompletable<Vec<TextEdit>>);
     fn rename(&mut self, params: RenameParams, completable: LSCompletable<WorkspaceEdit>);
     
+    fn prepare_call_hierarchy(&mut self, params: CallHierarchyPrepareParams, 
+        completable: LSCompletable<Option<Vec<CallHierarchyItem>>>) {
+        complete_method_not_found(completable)
+    }
+    fn call_hierarchy_incoming_calls(&mut self, params: CallHierarchyIncomingCallsParams, 
+        completable: LSCompletable<Option<Vec<CallHierarchyIncomingCall>>>) {
+        complete_method_not_found(completable)
+    }
+    fn call_hierarchy_outgoing_calls(&mut self, params: CallHierarchyOutgoingCallsParams, 
+        completable: LSCompletable<Option<Vec<CallHierarchyOutgoingCall>>>) {
+        complete_method_not_found(completable)
+    }
+    fn prepare_type_hierarchy(&mut self, params: TypeHierarchyPrepareParams, 
+        completable: LSCompletable<Option<Vec<TypeHierarchyItem>>>) {
+        complete_method_not_found(completable)
+    }
+    fn type_hierarchy_supertypes(&mut self, params: TypeHierarchySupertypesParams, 
+        completable: LSCompletable<Option<Vec<TypeHierarchyItem>>>) {
+        complete_method_not_found(completable)
+    }
+    fn type_hierarchy_subtypes(&mut self, params: TypeHierarchySubtypesParams, 
+        completable: LSCompletable<Option<Vec<TypeHierarchyItem>>>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn inlay_hint(&mut self, params: InlayHintParams, completable: LSCompletable<Option<Vec<InlayHint>>>) {
+        complete_method_not_found(completable)
+    }
+    fn inlay_hint_resolve(&mut self, params: InlayHint, completable: LSCompletable<InlayHint>) {
+        complete_method_not_found(completable)
+    }
+    fn inline_value(&mut self, params: InlineValueParams, completable: LSCompletable<Option<Vec<InlineValue>>>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn document_diagnostic(&mut self, params: DocumentDiagnosticParams, 
+        completable: LSCompletable<DocumentDiagnosticReport>) {
+        complete_method_not_found(completable)
+    }
+    fn workspace_diagnostic(&mut self, params: WorkspaceDiagnosticParams, 
+        completable: LSCompletable<WorkspaceDiagnosticReport>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn prepare_rename(&mut self, params: PrepareRenameParams, 
+        completable: LSCompletable<Option<PrepareRenameResponse>>) {
+        complete_method_not_found(completable)
+    }
+    fn linked_editing_range(&mut self, params: LinkedEditingRangeParams, 
+        completable: LSCompletable<Option<LinkedEditingRanges>>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn did_change_workspace_folders(&mut self, params: DidChangeWorkspaceFoldersParams) {}
+    fn will_create_files(&mut self, params: CreateFilesParams, completable: LSCompletable<Option<WorkspaceEdit>>) {
+        complete_method_not_found(completable)
+    }
+    fn did_create_files(&mut self, params: CreateFilesParams) {}
+    fn will_rename_files(&mut self, params: RenameFilesParams, completable: LSCompletable<Option<WorkspaceEdit>>) {
+        complete_method_not_found(completable)
+    }
+    fn did_rename_files(&mut self, params: RenameFilesParams) {}
+    fn will_delete_files(&mut self, params: DeleteFilesParams, completable: LSCompletable<Option<WorkspaceEdit>>) {
+        complete_method_not_found(completable)
+    }
+    fn did_delete_files(&mut self, params: DeleteFilesParams) {}
+    
+    fn work_done_progress_cancel(&mut self, params: WorkDoneProgressCancelParams) {}
+    
+    /// Handle a method not listed above. See `custom_methods` for typed handling of custom methods.
     #[allow(unused_variables)]
     fn handle_other_method(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable) {
         completable.complete_with_error(jsonrpc_common::error_JSON_RPC_MethodNotFound()); 
@@ -152,6 +237,166 @@ pub trait LanguageServerHandling {
 }
 
 
+/// Complete given completable with a `MethodNotFound` error.
+/// This is the default implementation of the handler methods added after the initial set of methods.
+pub fn complete_method_not_found<RET : serde::Serialize>(completable: LSCompletable<RET>) {
+    completable.complete_with_error(jsonrpc_common::error_JSON_RPC_MethodNotFound());
+}
+
+/// The error of the default implementation of the rpc methods added after the initial set of methods.
+fn error_not_implemented(method_name: &str) -> GError {
+    format!("`{}` is not implemented by this rpc handle", method_name).into()
+}
+
+/// Create a ResponseCompletable that, on completion, completes given `completable` with 
+/// the response result transformed by `map_fn`. Useful for request handlers that wrap other handlers.
+pub fn map_response<FN>(completable: ResponseCompletable, map_fn: FN) -> ResponseCompletable
+where 
+    FN : FnOnce(ResponseResult) -> ResponseResult + Send + 'static
+{
+    let mut completable = Some(completable);
+    let mut map_fn = Some(map_fn);
+    
+    // Note: the id is not used, only the response result is passed on to the original completable.
+    ResponseCompletable::new(Some(jsonrpc_common::Id::Null), new(move |response: Option<jsonrpc_response::Response>| {
+        let completable = completable.take().expect("ResponseCompletable completed twice");
+        let map_fn = map_fn.take().expect("ResponseCompletable completed twice");
+        
+        completable.complete(response.map(|response| map_fn(response.result_or_error)));
+    }))
+}
+
+
+/* ----------------- Handler panics ----------------- */
+
+/// Run `function`, catching a panic, and returning its message.
+fn catch_panic<FN : FnOnce()>(function: FN) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(function)).map_err(|payload| {
+        match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.clone(),
+            _ => "<non-string panic payload>".to_string(),
+        }
+    })
+}
+
+/// How a `CatchPanicsHandler` reports handlers that panic.
+#[derive(Clone, Default)]
+pub struct HandlerPanics {
+    /// The endpoint to report panics to the client on, with `window/logMessage`, and to shut down.
+    pub endpoint: Option<Endpoint>,
+    /// The number of panics after which `endpoint` is shut down. If `None`, it is never shut down.
+    pub shutdown_after: Option<u32>,
+    /// The number of handlers that panicked so far, shared by the clones of this `HandlerPanics`.
+    count: Arc<AtomicUsize>,
+}
+
+impl HandlerPanics {
+
+    /// The number of handlers that panicked so far, in this `HandlerPanics` and its clones.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    fn on_panic(&self, method_name: &str, message: &str) {
+        let count = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        let message = format!("Handler of `{}` panicked: {}", method_name, message);
+        error!("{}", message);
+
+        let endpoint = match self.endpoint {
+            Some(ref endpoint) => endpoint,
+            None => return,
+        };
+        if endpoint.is_shutdown() {
+            return;
+        }
+        let params = LogMessageParams { typ : MessageType::Error, message : message };
+        if let Err(error) = endpoint.send_notification(NOTIFICATION__LogMessage, params) {
+            warn!("Failed to send `{}`: {}", NOTIFICATION__LogMessage, error);
+        }
+
+        if self.shutdown_after.map_or(false, |shutdown_after| count >= shutdown_after as usize) {
+            error!("{} handlers panicked, shutting down the endpoint.", count);
+            endpoint.request_shutdown();
+        }
+    }
+
+}
+
+/// Handle given message with `handler`, catching a panic of the handler. The panic is reported as configured
+/// in `panics`, and if the message is a request that was not answered yet, it is answered with an `InternalError`.
+pub fn handle_request_catching_panics<HANDLER : RequestHandler + ?Sized>(
+    handler: &mut HANDLER, panics: &HandlerPanics,
+    method_name: &str, params: RequestParams, completable: ResponseCompletable
+) {
+    // The handler gets a proxy completable, so that `completable` can still be answered after a panic.
+    // Note: as in `map_response`, the id is not used.
+    let completable = newArcMutex(Some(completable));
+    let handler_completable = {
+        let completable = completable.clone();
+        let on_response = move |response: Option<jsonrpc_response::Response>| {
+            let completable = completable.lock().unwrap().take();
+            if let Some(completable) = completable {
+                completable.complete(response.map(|response| response.result_or_error));
+            }
+        };
+        ResponseCompletable::new(Some(jsonrpc_common::Id::Null), new(on_response))
+    };
+
+    let result = catch_panic(|| handler.handle_request(method_name, params, handler_completable));
+    if let Err(message) = result {
+        panics.on_panic(method_name, &message);
+
+        let completable = completable.lock().unwrap().take();
+        if let Some(completable) = completable {
+            let mut error = jsonrpc_common::error_JSON_RPC_InternalError();
+            error.message = format!("Handler of `{}` panicked: {}", method_name, message);
+            completable.complete_with_error(error);
+        }
+    }
+}
+
+/// A request handler that catches the panics of `handler`: see `handle_request_catching_panics`.
+///
+/// The default panic hook still prints the panic message (and the backtrace, if `RUST_BACKTRACE` is set).
+pub struct CatchPanicsHandler<HANDLER> {
+    pub handler: HANDLER,
+    pub panics: HandlerPanics,
+}
+
+impl<HANDLER : RequestHandler> CatchPanicsHandler<HANDLER> {
+
+    pub fn new(handler: HANDLER) -> CatchPanicsHandler<HANDLER> {
+        CatchPanicsHandler { handler : handler, panics : HandlerPanics::default() }
+    }
+
+    /// Report handler panics to the client, with `window/logMessage` notifications sent on `endpoint`,
+    /// and shut down `endpoint` after `shutdown_after` panics, if given.
+    pub fn report_panics_to(mut self, endpoint: Endpoint, shutdown_after: Option<u32>) -> CatchPanicsHandler<HANDLER> {
+        self.panics.endpoint = Some(endpoint);
+        self.panics.shutdown_after = shutdown_after;
+        self
+    }
+
+}
+
+impl<HANDLER : RequestHandler> RequestHandler for CatchPanicsHandler<HANDLER> {
+
+    fn handle_request(
+        &mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable
+    ) {
+        handle_request_catching_panics(&mut self.handler, &self.panics, method_name, params, completable)
+    }
+
+}
+
+/* -----------------  ----------------- */
+
+/// Dispatches incoming requests and notifications to the methods of a `LanguageServerHandling`.
+///
+/// Note: handler methods are called on the message read loop thread, and panics are not isolated:
+/// a panicking handler terminates the message read loop. Wrap this handler in a `CatchPanicsHandler`
+/// to answer the requests of panicking handlers with an error, and keep serving.
 pub struct ServerRequestHandler<LS : ?Sized>(pub LS);
 
 impl<LS : LanguageServerHandling + ?Sized> RequestHandler for ServerRequestHandler<LS> {
@@ -293,6 +538,111 @@ impl<LS : LanguageServerHandling + ?Sized> RequestHandler for ServerRequestHandl
                     |params, completable| self.0.rename(params, completable)
                 ) 
             }
+            REQUEST__PrepareCallHierarchy => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.prepare_call_hierarchy(params, completable)
+                ) 
+            }
+            REQUEST__CallHierarchyIncomingCalls => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.call_hierarchy_incoming_calls(params, completable)
+                ) 
+            }
+            REQUEST__CallHierarchyOutgoingCalls => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.call_hierarchy_outgoing_calls(params, completable)
+                ) 
+            }
+            REQUEST__PrepareTypeHierarchy => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.prepare_type_hierarchy(params, completable)
+                ) 
+            }
+            REQUEST__TypeHierarchySupertypes => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.type_hierarchy_supertypes(params, completable)
+                ) 
+            }
+            REQUEST__TypeHierarchySubtypes => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.type_hierarchy_subtypes(params, completable)
+                ) 
+            }
+            REQUEST__InlayHint => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.inlay_hint(params, completable)
+                ) 
+            }
+            REQUEST__InlayHintResolve => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.inlay_hint_resolve(params, completable)
+                ) 
+            }
+            REQUEST__InlineValue => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.inline_value(params, completable)
+                ) 
+            }
+            REQUEST__DocumentDiagnostic => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.document_diagnostic(params, completable)
+                ) 
+            }
+            REQUEST__WorkspaceDiagnostic => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.workspace_diagnostic(params, completable)
+                ) 
+            }
+            REQUEST__PrepareRename => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.prepare_rename(params, completable)
+                ) 
+            }
+            REQUEST__LinkedEditingRange => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.linked_editing_range(params, completable)
+                ) 
+            }
+            NOTIFICATION__DidChangeWorkspaceFolders => {
+                completable.handle_notification_with(params, 
+                    |params| self.0.did_change_workspace_folders(params)
+                ) 
+            }
+            REQUEST__WillCreateFiles => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.will_create_files(params, completable)
+                ) 
+            }
+            NOTIFICATION__DidCreateFiles => {
+                completable.handle_notification_with(params, 
+                    |params| self.0.did_create_files(params)
+                ) 
+            }
+            REQUEST__WillRenameFiles => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.will_rename_files(params, completable)
+                ) 
+            }
+            NOTIFICATION__DidRenameFiles => {
+                completable.handle_notification_with(params, 
+                    |params| self.0.did_rename_files(params)
+                ) 
+            }
+            REQUEST__WillDeleteFiles => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.will_delete_files(params, completable)
+                ) 
+            }
+            NOTIFICATION__DidDeleteFiles => {
+                completable.handle_notification_with(params, 
+                    |params| self.0.did_delete_files(params)
+                ) 
+            }
+            NOTIFICATION__WorkDoneProgressCancel => {
+                completable.handle_notification_with(params, 
+                    |params| self.0.work_done_progress_cancel(params)
+                ) 
+            }
             _ => {
                 self.0.handle_other_method(method_name, params, completable);
             }
@@ -303,6 +653,7 @@ impl<LS : LanguageServerHandling + ?Sized> RequestHandler for ServerRequestHandl
 }
 
 
+#[allow(unused_variables)]
 pub trait LspClientRpc {
     
     fn show_message(&mut self, params: ShowMessageParams) 
@@ -319,6 +670,48 @@ pub trait LspClientRpc {
     
     fn publish_diagnostics(&mut self, params: PublishDiagnosticsParams) 
         -> GResult<()>;
+    
+    fn inlay_hint_refresh(&mut self) 
+        -> GResult<RequestFuture<(), ()>>
+    {
+        Err(error_not_implemented("inlay_hint_refresh"))
+    }
+    
+    fn inline_value_refresh(&mut self) 
+        -> GResult<RequestFuture<(), ()>>
+    {
+        Err(error_not_implemented("inline_value_refresh"))
+    }
+    
+    fn diagnostic_refresh(&mut self) 
+        -> GResult<RequestFuture<(), ()>>
+    {
+        Err(error_not_implemented("diagnostic_refresh"))
+    }
+    
+    fn workspace_folders(&mut self) 
+        -> GResult<RequestFuture<Option<Vec<WorkspaceFolder>>, ()>>
+    {
+        Err(error_not_implemented("workspace_folders"))
+    }
+    
+    fn configuration(&mut self, params: ConfigurationParams) 
+        -> GResult<RequestFuture<Vec<Value>, ()>>
+    {
+        Err(error_not_implemented("configuration"))
+    }
+    
+    fn show_document(&mut self, params: ShowDocumentParams) 
+        -> GResult<RequestFuture<ShowDocumentResult, ()>>
+    {
+        Err(error_not_implemented("show_document"))
+    }
+    
+    fn work_done_progress_create(&mut self, params: WorkDoneProgressCreateParams) 
+        -> GResult<RequestFuture<(), ()>>
+    {
+        Err(error_not_implemented("work_done_progress_create"))
+    }
 
 }
 
@@ -362,10 +755,53 @@ impl<'a> LspClientRpc for LspClientRpc_<'a> {
         self.endpoint.send_notification(NOTIFICATION__PublishDiagnostics, params)
     }
     
+    fn inlay_hint_refresh(&mut self) 
+        -> GResult<RequestFuture<(), ()>> 
+    {
+        self.endpoint.send_request(REQUEST__InlayHintRefresh, ())
+    }
+    
+    fn inline_value_refresh(&mut self) 
+        -> GResult<RequestFuture<(), ()>> 
+    {
+        self.endpoint.send_request(REQUEST__InlineValueRefresh, ())
+    }
+    
+    fn diagnostic_refresh(&mut self) 
+        -> GResult<RequestFuture<(), ()>> 
+    {
+        self.endpoint.send_request(REQUEST__DiagnosticRefresh, ())
+    }
+    
+    fn workspace_folders(&mut self) 
+        -> GResult<RequestFuture<Option<Vec<WorkspaceFolder>>, ()>> 
+    {
+        self.endpoint.send_request(REQUEST__WorkspaceFolders, ())
+    }
+    
+    fn configuration(&mut self, params: ConfigurationParams) 
+        -> GResult<RequestFuture<Vec<Value>, ()>> 
+    {
+        self.endpoint.send_request(REQUEST__Configuration, params)
+    }
+    
+    fn show_document(&mut self, params: ShowDocumentParams) 
+        -> GResult<RequestFuture<ShowDocumentResult, ()>> 
+    {
+        self.endpoint.send_request(REQUEST__ShowDocument, params)
+    }
+    
+    fn work_done_progress_create(&mut self, params: WorkDoneProgressCreateParams) 
+        -> GResult<RequestFuture<(), ()>> 
+    {
+        self.endpoint.send_request(REQUEST__WorkDoneProgressCreate, params)
+    }
+    
 }
 
 /* ----------------- LSP Client: ----------------- */
 
+#[allow(unused_variables)]
 pub trait LSPServerRpc {
     
     fn initialize(&mut self, params: InitializeParams)
@@ -442,6 +878,132 @@ pub trait LSPServerRpc {
         
     fn rename(&mut self, params: RenameParams)
         -> GResult<RequestFuture<WorkspaceEdit, ()>>;
+        
+    fn prepare_call_hierarchy(&mut self, params: CallHierarchyPrepareParams)
+        -> GResult<RequestFuture<Option<Vec<CallHierarchyItem>>, ()>>
+    {
+        Err(error_not_implemented("prepare_call_hierarchy"))
+    }
+        
+    fn call_hierarchy_incoming_calls(&mut self, params: CallHierarchyIncomingCallsParams)
+        -> GResult<RequestFuture<Option<Vec<CallHierarchyIncomingCall>>, ()>>
+    {
+        Err(error_not_implemented("call_hierarchy_incoming_calls"))
+    }
+        
+    fn call_hierarchy_outgoing_calls(&mut self, params: CallHierarchyOutgoingCallsParams)
+        -> GResult<RequestFuture<Option<Vec<CallHierarchyOutgoingCall>>, ()>>
+    {
+        Err(error_not_implemented("call_hierarchy_outgoing_calls"))
+    }
+        
+    fn prepare_type_hierarchy(&mut self, params: TypeHierarchyPrepareParams)
+        -> GResult<RequestFuture<Option<Vec<TypeHierarchyItem>>, ()>>
+    {
+        Err(error_not_implemented("prepare_type_hierarchy"))
+    }
+        
+    fn type_hierarchy_supertypes(&mut self, params: TypeHierarchySupertypesParams)
+        -> GResult<RequestFuture<Option<Vec<TypeHierarchyItem>>, ()>>
+    {
+        Err(error_not_implemented("type_hierarchy_supertypes"))
+    }
+        
+    fn type_hierarchy_subtypes(&mut self, params: TypeHierarchySubtypesParams)
+        -> GResult<RequestFuture<Option<Vec<TypeHierarchyItem>>, ()>>
+    {
+        Err(error_not_implemented("type_hierarchy_subtypes"))
+    }
+        
+    fn inlay_hint(&mut self, params: InlayHintParams)
+        -> GResult<RequestFuture<Option<Vec<InlayHint>>, ()>>
+    {
+        Err(error_not_implemented("inlay_hint"))
+    }
+        
+    fn inlay_hint_resolve(&mut self, params: InlayHint)
+        -> GResult<RequestFuture<InlayHint, ()>>
+    {
+        Err(error_not_implemented("inlay_hint_resolve"))
+    }
+        
+    fn inline_value(&mut self, params: InlineValueParams)
+        -> GResult<RequestFuture<Option<Vec<InlineValue>>, ()>>
+    {
+        Err(error_not_implemented("inline_value"))
+    }
+        
+    fn document_diagnostic(&mut self, params: DocumentDiagnosticParams)
+        -> GResult<RequestFuture<DocumentDiagnosticReport, ()>>
+    {
+        Err(error_not_implemented("document_diagnostic"))
+    }
+        
+    fn workspace_diagnostic(&mut self, params: WorkspaceDiagnosticParams)
+        -> GResult<RequestFuture<WorkspaceDiagnosticReport, ()>>
+    {
+        Err(error_not_implemented("workspace_diagnostic"))
+    }
+        
+    fn prepare_rename(&mut self, params: PrepareRenameParams)
+        -> GResult<RequestFuture<Option<PrepareRenameResponse>, ()>>
+    {
+        Err(error_not_implemented("prepare_rename"))
+    }
+        
+    fn linked_editing_range(&mut self, params: LinkedEditingRangeParams)
+        -> GResult<RequestFuture<Option<LinkedEditingRanges>, ()>>
+    {
+        Err(error_not_implemented("linked_editing_range"))
+    }
+        
+    fn did_change_workspace_folders(&mut self, params: DidChangeWorkspaceFoldersParams)
+        -> GResult<()>
+    {
+        Err(error_not_implemented("did_change_workspace_folders"))
+    }
+        
+    fn will_create_files(&mut self, params: CreateFilesParams)
+        -> GResult<RequestFuture<Option<WorkspaceEdit>, ()>>
+    {
+        Err(error_not_implemented("will_create_files"))
+    }
+        
+    fn did_create_files(&mut self, params: CreateFilesParams)
+        -> GResult<()>
+    {
+        Err(error_not_implemented("did_create_files"))
+    }
+        
+    fn will_rename_files(&mut self, params: RenameFilesParams)
+        -> GResult<RequestFuture<Option<WorkspaceEdit>, ()>>
+    {
+        Err(error_not_implemented("will_rename_files"))
+    }
+        
+    fn did_rename_files(&mut self, params: RenameFilesParams)
+        -> GResult<()>
+    {
+        Err(error_not_implemented("did_rename_files"))
+    }
+        
+    fn will_delete_files(&mut self, params: DeleteFilesParams)
+        -> GResult<RequestFuture<Option<WorkspaceEdit>, ()>>
+    {
+        Err(error_not_implemented("will_delete_files"))
+    }
+        
+    fn did_delete_files(&mut self, params: DeleteFilesParams)
+        -> GResult<()>
+    {
+        Err(error_not_implemented("did_delete_files"))
+    }
+        
+    fn work_done_progress_cancel(&mut self, params: WorkDoneProgressCancelParams)
+        -> GResult<()>
+    {
+        Err(error_not_implemented("work_done_progress_cancel"))
+    }
     
 }
 
@@ -606,11 +1168,138 @@ impl<'a> LSPServerRpc for LspServerRpc_<'a> {
         self.endpoint.send_request(REQUEST__Rename, params)
     }
     
+    fn prepare_call_hierarchy(&mut self, params: CallHierarchyPrepareParams)
+        -> GResult<RequestFuture<Option<Vec<CallHierarchyItem>>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__PrepareCallHierarchy, params)
+    }
+    
+    fn call_hierarchy_incoming_calls(&mut self, params: CallHierarchyIncomingCallsParams)
+        -> GResult<RequestFuture<Option<Vec<CallHierarchyIncomingCall>>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__CallHierarchyIncomingCalls, params)
+    }
+    
+    fn call_hierarchy_outgoing_calls(&mut self, params: CallHierarchyOutgoingCallsParams)
+        -> GResult<RequestFuture<Option<Vec<CallHierarchyOutgoingCall>>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__CallHierarchyOutgoingCalls, params)
+    }
+    
+    fn prepare_type_hierarchy(&mut self, params: TypeHierarchyPrepareParams)
+        -> GResult<RequestFuture<Option<Vec<TypeHierarchyItem>>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__PrepareTypeHierarchy, params)
+    }
+    
+    fn type_hierarchy_supertypes(&mut self, params: TypeHierarchySupertypesParams)
+        -> GResult<RequestFuture<Option<Vec<TypeHierarchyItem>>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__TypeHierarchySupertypes, params)
+    }
+    
+    fn type_hierarchy_subtypes(&mut self, params: TypeHierarchySubtypesParams)
+        -> GResult<RequestFuture<Option<Vec<TypeHierarchyItem>>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__TypeHierarchySubtypes, params)
+    }
+    
+    fn inlay_hint(&mut self, params: InlayHintParams)
+        -> GResult<RequestFuture<Option<Vec<InlayHint>>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__InlayHint, params)
+    }
+    
+    fn inlay_hint_resolve(&mut self, params: InlayHint)
+        -> GResult<RequestFuture<InlayHint, ()>>
+    {
+        self.endpoint.send_request(REQUEST__InlayHintResolve, params)
+    }
+    
+    fn inline_value(&mut self, params: InlineValueParams)
+        -> GResult<RequestFuture<Option<Vec<InlineValue>>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__InlineValue, params)
+    }
+    
+    fn document_diagnostic(&mut self, params: DocumentDiagnosticParams)
+        -> GResult<RequestFuture<DocumentDiagnosticReport, ()>>
+    {
+        self.endpoint.send_request(REQUEST__DocumentDiagnostic, params)
+    }
+    
+    fn workspace_diagnostic(&mut self, params: WorkspaceDiagnosticParams)
+        -> GResult<RequestFuture<WorkspaceDiagnosticReport, ()>>
+    {
+        self.endpoint.send_request(REQUEST__WorkspaceDiagnostic, params)
+    }
+    
+    fn prepare_rename(&mut self, params: PrepareRenameParams)
+        -> GResult<RequestFuture<Option<PrepareRenameResponse>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__PrepareRename, params)
+    }
+    
+    fn linked_editing_range(&mut self, params: LinkedEditingRangeParams)
+        -> GResult<RequestFuture<Option<LinkedEditingRanges>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__LinkedEditingRange, params)
+    }
+    
+    fn did_change_workspace_folders(&mut self, params: DidChangeWorkspaceFoldersParams)
+        -> GResult<()>
+    {
+        self.endpoint.send_notification(NOTIFICATION__DidChangeWorkspaceFolders, params)
+    }
+    
+    fn will_create_files(&mut self, params: CreateFilesParams)
+        -> GResult<RequestFuture<Option<WorkspaceEdit>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__WillCreateFiles, params)
+    }
+    
+    fn did_create_files(&mut self, params: CreateFilesParams)
+        -> GResult<()>
+    {
+        self.endpoint.send_notification(NOTIFICATION__DidCreateFiles, params)
+    }
+    
+    fn will_rename_files(&mut self, params: RenameFilesParams)
+        -> GResult<RequestFuture<Option<WorkspaceEdit>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__WillRenameFiles, params)
+    }
+    
+    fn did_rename_files(&mut self, params: RenameFilesParams)
+        -> GResult<()>
+    {
+        self.endpoint.send_notification(NOTIFICATION__DidRenameFiles, params)
+    }
+    
+    fn will_delete_files(&mut self, params: DeleteFilesParams)
+        -> GResult<RequestFuture<Option<WorkspaceEdit>, ()>>
+    {
+        self.endpoint.send_request(REQUEST__WillDeleteFiles, params)
+    }
+    
+    fn did_delete_files(&mut self, params: DeleteFilesParams)
+        -> GResult<()>
+    {
+        self.endpoint.send_notification(NOTIFICATION__DidDeleteFiles, params)
+    }
+    
+    fn work_done_progress_cancel(&mut self, params: WorkDoneProgressCancelParams)
+        -> GResult<()>
+    {
+        self.endpoint.send_notification(NOTIFICATION__WorkDoneProgressCancel, params)
+    }
+    
 }
 
 
 /// Trait for the handling of LSP client requests.
 /// (An LSP server can act as a JSON-RPC Client and request to the LSP client)
+#[allow(unused_variables)]
 pub trait LanguageClientHandling {
     
     fn show_message(&mut self, params: ShowMessageParams);
@@ -624,6 +1313,35 @@ pub trait LanguageClientHandling {
     
     fn publish_diagnostics(&mut self, params: PublishDiagnosticsParams);
 	
+    fn inlay_hint_refresh(&mut self, params: (), completable: LSCompletable<()>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn inline_value_refresh(&mut self, params: (), completable: LSCompletable<()>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn diagnostic_refresh(&mut self, params: (), completable: LSCompletable<()>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn workspace_folders(&mut self, params: (), completable: LSCompletable<Option<Vec<WorkspaceFolder>>>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn configuration(&mut self, params: ConfigurationParams, completable: LSCompletable<Vec<Value>>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn show_document(&mut self, params: ShowDocumentParams, completable: LSCompletable<ShowDocumentResult>) {
+        complete_method_not_found(completable)
+    }
+    
+    fn work_done_progress_create(&mut self, params: WorkDoneProgressCreateParams, completable: LSCompletable<()>) {
+        complete_method_not_found(completable)
+    }
+    
+    /// Handle a method not listed above. See `custom_methods` for typed handling of custom methods.
     #[allow(unused_variables)]
     fn handle_other_method(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable) {
         completable.complete_with_error(jsonrpc_common::error_JSON_RPC_MethodNotFound()); 
@@ -662,10 +1380,52 @@ impl<LS : LanguageClientHandling + ?Sized> RequestHandler for ClientRequestHandl
                     |params| self.0.publish_diagnostics(params)
                 ) 
             }
+            REQUEST__InlayHintRefresh => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.inlay_hint_refresh(params, completable)
+                ) 
+            }
+            REQUEST__InlineValueRefresh => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.inline_value_refresh(params, completable)
+                ) 
+            }
+            REQUEST__DiagnosticRefresh => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.diagnostic_refresh(params, completable)
+                ) 
+            }
+            REQUEST__WorkspaceFolders => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.workspace_folders(params, completable)
+                ) 
+            }
+            REQUEST__Configuration => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.configuration(params, completable)
+                ) 
+            }
+            REQUEST__ShowDocument => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.show_document(params, completable)
+                ) 
+            }
+            REQUEST__WorkDoneProgressCreate => {
+                completable.handle_request_with(params, 
+                    |params, completable| self.0.work_done_progress_create(params, completable)
+                ) 
+            }
             _ => {
                 self.0.handle_other_method(method_name, params, completable);
             }
         }
     }
     
+}
+
+
+#[test]
+fn test_duration_millis() {
+    assert_eq!(duration_millis(Duration::new(2, 5_000_000)), 2005);
+    assert_eq!(duration_millis(Duration::new(0, 999_999)), 0);
 }
\ No newline at end of file
@@ -0,0 +1,235 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+In-memory transport, to connect a server and a client in the same process (typically in tests).
+
+`pipe` creates a byte stream whose `PipeWriter` and `PipeReader` ends are the `io::Write` and `io::BufRead`
+used by `LSPMessageWriter` and `LSPMessageReader`. `connect_in_process` uses a pair of pipes to run
+a `LanguageServerHandling` and a `LanguageClientHandling` connected to each other, each on its own thread.
+
+*/
+
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+
+use jsonrpc::*;
+
+use lsp::*;
+
+/* ----------------- Pipe ----------------- */
+
+/// Create an in-memory byte stream. The reader reaches end of stream once all writers are dropped.
+pub fn pipe() -> (PipeWriter, PipeReader) {
+    let (sender, receiver) = mpsc::channel();
+    (PipeWriter { sender : sender }, PipeReader { receiver : receiver, buffer : vec![], position : 0 })
+}
+
+/// The write end of a `pipe`. Clones write to the same stream.
+#[derive(Clone)]
+pub struct PipeWriter {
+    sender: mpsc::Sender<Vec<u8>>,
+}
+
+impl io::Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.sender.send(buf.to_vec()) {
+            Ok(_) => Ok(buf.len()),
+            Err(_) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "Pipe reader was dropped")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The read end of a `pipe`.
+pub struct PipeReader {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl io::Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = {
+            let available = try!(io::BufRead::fill_buf(self));
+            let len = ::std::cmp::min(available.len(), buf.len());
+            buf[..len].copy_from_slice(&available[..len]);
+            len
+        };
+        io::BufRead::consume(self, len);
+        Ok(len)
+    }
+}
+
+impl io::BufRead for PipeReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.position >= self.buffer.len() {
+            match self.receiver.recv() {
+                Ok(data) => {
+                    self.buffer = data;
+                    self.position = 0;
+                }
+                // All writers dropped: end of stream
+                Err(_) => return Ok(&[]),
+            }
+        }
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt;
+    }
+}
+
+/* ----------------- In-process connection ----------------- */
+
+/// A server and a client connected in-process. See `connect_in_process`.
+///
+/// When dropped (or with `shutdown_and_join`), the client endpoint is shut down, which ends the input of
+/// the server, and so the server and then the client message loops.
+pub struct InProcessConnection {
+    /// The endpoint of the client, to send messages to the server.
+    pub client_endpoint: Endpoint,
+    server_thread: Option<thread::JoinHandle<()>>,
+    client_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl InProcessConnection {
+
+    /// The handle for the client to call the server methods.
+    pub fn server_rpc(&mut self) -> LspServerRpc_ {
+        server_rpc_handle(&mut self.client_endpoint)
+    }
+
+    /// Shut down the connection, and wait for the server and client message loops to end.
+    /// Panics if one of them panicked.
+    pub fn shutdown_and_join(mut self) {
+        if let Err(error) = self.join_threads() {
+            panic!("{}", error);
+        }
+    }
+
+    fn join_threads(&mut self) -> Result<(), &'static str> {
+        self.client_endpoint.request_shutdown();
+
+        let server_result = self.server_thread.take().map_or(Ok(()), |thread| thread.join());
+        let client_result = self.client_thread.take().map_or(Ok(()), |thread| thread.join());
+        match (server_result, client_result) {
+            (Err(_), _) => Err("Server thread panicked"),
+            (_, Err(_)) => Err("Client thread panicked"),
+            _ => Ok(()),
+        }
+    }
+
+}
+
+impl Drop for InProcessConnection {
+    fn drop(&mut self) {
+        if let Err(error) = self.join_threads() {
+            error!("{}", error);
+        }
+    }
+}
+
+/// Run the server created by `create_server` and the client created by `create_client` connected to each other,
+/// through in-memory pipes. Each function is given the endpoint it must use to send messages to the other side.
+pub fn connect_in_process<LS, LC, LS_FN, LC_FN>(create_server: LS_FN, create_client: LC_FN) -> InProcessConnection
+where
+    LS : LanguageServerHandling + 'static,
+    LC : LanguageClientHandling + 'static,
+    LS_FN : FnOnce(Endpoint) -> LS + Send + 'static,
+    LC_FN : FnOnce(Endpoint) -> LC + Send + 'static,
+{
+    let (server_writer, mut client_reader) = pipe();
+    let (client_writer, mut server_reader) = pipe();
+
+    let server_endpoint = LSPEndpoint::create_lsp_output_with_output_stream(move || server_writer);
+    let client_endpoint = LSPEndpoint::create_lsp_output_with_output_stream(move || client_writer);
+
+    let server_thread = thread::spawn(move || {
+        let ls = create_server(server_endpoint.clone());
+        LSPEndpoint::run_server_from_input(&mut server_reader, server_endpoint, ls);
+    });
+
+    let endpoint = client_endpoint.clone();
+    let client_thread = thread::spawn(move || {
+        let lc = create_client(endpoint.clone());
+        LSPEndpoint::run_client_from_input(&mut client_reader, endpoint, lc);
+    });
+
+    InProcessConnection {
+        client_endpoint : client_endpoint,
+        server_thread : Some(server_thread),
+        client_thread : Some(client_thread),
+    }
+}
+
+/* ----------------- Test messages ----------------- */
+
+/// Message writers and readers over in-memory queues, for the tests of this crate.
+#[cfg(test)]
+pub mod test_messages {
+
+    use std::collections::VecDeque;
+    use std::sync::mpsc;
+
+    use util::core::*;
+
+    use lsp::MessageReader;
+    use lsp::MessageWriter;
+
+    /// A `MessageWriter` sending each message to a channel.
+    pub struct ChannelMessageWriter(pub mpsc::Sender<String>);
+
+    impl MessageWriter for ChannelMessageWriter {
+        fn write_message(&mut self, msg: &str) -> GResult<()> {
+            self.0.send(msg.to_string()).unwrap();
+            Ok(())
+        }
+    }
+
+    /// A `MessageReader` returning the messages of a queue, then an error.
+    pub struct QueueMessageReader(pub VecDeque<String>);
+
+    impl MessageReader for QueueMessageReader {
+        fn read_next(&mut self) -> GResult<String> {
+            self.0.pop_front().ok_or_else(|| "No more messages".into())
+        }
+    }
+
+}
+
+
+#[test]
+fn test_pipe() {
+    use std::io::{BufRead, Write};
+    use lsp_transport::*;
+
+    let (mut writer, mut reader) = pipe();
+    let mut writer2 = writer.clone();
+    let handle = thread::spawn(move || {
+        write_transport_message("abc", &mut writer).unwrap();
+        write_transport_message("12\n3", &mut writer2).unwrap();
+        writer2.write_all(b"tail\n").unwrap();
+    });
+
+    assert_eq!(parse_transport_message(&mut reader).unwrap(), "abc");
+    assert_eq!(parse_transport_message(&mut reader).unwrap(), "12\n3");
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "tail\n");
+    handle.join().unwrap();
+
+    // End of stream, once writers are dropped
+    line.clear();
+    assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+}
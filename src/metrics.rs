@@ -0,0 +1,381 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Per-method metrics of the requests handled by a server.
+
+`MetricsLayer` is a `middleware::Middleware` that records, in a `MetricsRegistry`, the number of requests
+of each method, how many of them were answered with an error, and a histogram of the time taken to answer them.
+Notifications are not recorded, since their completion does not tell when they were handled.
+
+```ignore
+let registry = MetricsRegistry::new();
+let handler = MiddlewareHandler::new(ServerRequestHandler(ls))
+    .layer(registry.layer());
+let _reporter = MetricsReporter::start(registry.clone(), MetricsSink::TelemetryEvent(endpoint.clone()),
+    Duration::from_secs(60));
+LSPEndpoint::run_endpoint_loop(&mut reader, endpoint, new(handler));
+```
+
+The registry can be queried at any time with `MetricsRegistry::snapshot`, and a `MetricsReporter` emits
+the metrics periodically, as a `telemetry/event` notification or by appending them to a file.
+
+*/
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use util::core::*;
+
+use jsonrpc::*;
+use jsonrpc::jsonrpc_request::RequestParams;
+use jsonrpc::jsonrpc_response::ResponseResult;
+
+use serde_json;
+use serde_json::Value;
+use serde_json::builder::ObjectBuilder;
+
+use lsp::*;
+use middleware::*;
+
+/* ----------------- Metrics ----------------- */
+
+/// The upper bounds, in milliseconds, of the buckets of a `LatencyHistogram`.
+pub const LATENCY_BUCKETS_MS: [u64; 12] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// A histogram of latencies, in milliseconds.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct LatencyHistogram {
+    /// The inclusive upper bound of each bucket but the last, which holds the latencies above all bounds.
+    #[serde(rename="boundsMs")]
+    pub bounds_ms: Vec<u64>,
+    /// The number of latencies in each bucket. One more element than `bounds_ms`.
+    pub counts: Vec<u64>,
+    #[serde(rename="totalMs")]
+    pub total_ms: u64,
+    #[serde(rename="maxMs")]
+    pub max_ms: u64,
+}
+
+impl LatencyHistogram {
+
+    pub fn new(bounds_ms: &[u64]) -> LatencyHistogram {
+        LatencyHistogram {
+            bounds_ms : bounds_ms.to_vec(),
+            counts : vec![0; bounds_ms.len() + 1],
+            total_ms : 0,
+            max_ms : 0,
+        }
+    }
+
+    pub fn record(&mut self, latency_ms: u64) {
+        let bucket = self.bounds_ms.iter().position(|&bound| latency_ms <= bound).unwrap_or(self.bounds_ms.len());
+        self.counts[bucket] += 1;
+        self.total_ms += latency_ms;
+        if latency_ms > self.max_ms {
+            self.max_ms = latency_ms;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().fold(0, |sum, count| sum + count)
+    }
+
+    pub fn mean_ms(&self) -> Option<f64> {
+        match self.count() {
+            0 => None,
+            count => Some(self.total_ms as f64 / count as f64),
+        }
+    }
+
+    /// An upper bound of given percentile (between 0 and 100) of the latencies: the bound of the bucket
+    /// that contains it, or the maximum latency for the last bucket. `None` if there are no latencies.
+    pub fn percentile_ms(&self, percentile: f64) -> Option<u64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((percentile / 100.0) * count as f64).ceil().max(1.0) as u64;
+        let mut cumulative_count = 0;
+        for (bucket, bucket_count) in self.counts.iter().enumerate() {
+            cumulative_count += *bucket_count;
+            if cumulative_count >= rank {
+                let bound = self.bounds_ms.get(bucket).map_or(self.max_ms, |&bound| bound);
+                return Some(::std::cmp::min(bound, self.max_ms));
+            }
+        }
+        Some(self.max_ms)
+    }
+
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> LatencyHistogram {
+        LatencyHistogram::new(&LATENCY_BUCKETS_MS)
+    }
+}
+
+/// The metrics of a method.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct MethodMetrics {
+    /// The number of requests answered.
+    pub count: u64,
+    /// The number of requests answered with an error.
+    #[serde(rename="errorCount")]
+    pub error_count: u64,
+    pub latency: LatencyHistogram,
+}
+
+/// The metrics of each method, shared by the `MetricsLayer`s and `MetricsReporter`s created from it.
+#[derive(Clone)]
+pub struct MetricsRegistry {
+    methods: Arc<Mutex<BTreeMap<String, MethodMetrics>>>,
+}
+
+impl MetricsRegistry {
+
+    pub fn new() -> MetricsRegistry {
+        MetricsRegistry { methods : newArcMutex(BTreeMap::new()) }
+    }
+
+    /// A middleware layer that records the requests it sees in this registry.
+    pub fn layer(&self) -> MetricsLayer {
+        MetricsLayer { registry : self.clone() }
+    }
+
+    pub fn record(&self, method_name: &str, latency: Duration, is_error: bool) {
+        let mut methods = self.methods.lock().unwrap();
+        if !methods.contains_key(method_name) {
+            methods.insert(method_name.to_string(), MethodMetrics::default());
+        }
+        let metrics = methods.get_mut(method_name).unwrap();
+        metrics.count += 1;
+        if is_error {
+            metrics.error_count += 1;
+        }
+        metrics.latency.record(duration_millis(latency));
+    }
+
+    pub fn method(&self, method_name: &str) -> Option<MethodMetrics> {
+        self.methods.lock().unwrap().get(method_name).cloned()
+    }
+
+    /// The metrics of all methods seen so far, by method name.
+    pub fn snapshot(&self) -> BTreeMap<String, MethodMetrics> {
+        self.methods.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        self.methods.lock().unwrap().clear();
+    }
+
+    /// The metrics of all methods, as a JSON object with the method names as keys.
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(&self.snapshot())
+    }
+
+}
+
+/// Records the requests it sees in a `MetricsRegistry`. See `MetricsRegistry::layer`.
+pub struct MetricsLayer {
+    registry: MetricsRegistry,
+}
+
+impl Middleware for MetricsLayer {
+    fn handle(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable, next: Next) {
+        let start = Instant::now();
+        let registry = self.registry.clone();
+        let name = method_name.to_string();
+        let completable = on_response(completable, move |result| {
+            // Notifications are not recorded, see module docs
+            if let Some(result) = result {
+                let is_error = match *result {
+                    ResponseResult::Error(_) => true,
+                    ResponseResult::Result(_) => false,
+                };
+                registry.record(&name, start.elapsed(), is_error);
+            }
+        });
+        next.handle(method_name, params, completable);
+    }
+}
+
+/* ----------------- Reporting ----------------- */
+
+/// Where a `MetricsReporter` emits metrics.
+pub enum MetricsSink {
+    /// As the params of a `telemetry/event` notification to the client:
+    /// `{"type":"methodMetrics","methods":{..}}`, with `methods` as in `MetricsRegistry::to_json`.
+    TelemetryEvent(Endpoint),
+    /// Appended to given file, one JSON line per report: `{"timestamp":..,"methods":{..}}`,
+    /// with the timestamp in milliseconds since the Unix epoch.
+    File(PathBuf),
+}
+
+impl MetricsSink {
+
+    pub fn emit(&self, registry: &MetricsRegistry) -> GResult<()> {
+        match *self {
+            MetricsSink::TelemetryEvent(ref endpoint) => {
+                // Sending on a shut down endpoint panics
+                if endpoint.is_shutdown() {
+                    return Err("Endpoint is shut down".into());
+                }
+                let params = ObjectBuilder::new()
+                    .insert("type", "methodMetrics")
+                    .insert("methods", registry.to_json())
+                    .build();
+                client_rpc_handle(&mut endpoint.clone()).telemetry_event(params)
+            }
+            MetricsSink::File(ref path) => {
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(duration_millis).unwrap_or(0);
+                let report = ObjectBuilder::new()
+                    .insert("timestamp", timestamp)
+                    .insert("methods", registry.to_json())
+                    .build();
+                let mut file = try!(OpenOptions::new().create(true).append(true).open(path));
+                try!(writeln!(file, "{}", try!(serde_json::to_string(&report))));
+                Ok(())
+            }
+        }
+    }
+
+}
+
+/// Emits the metrics of a registry periodically to a `MetricsSink`, from a thread of its own,
+/// and once more when dropped.
+pub struct MetricsReporter {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MetricsReporter {
+
+    pub fn start(registry: MetricsRegistry, sink: MetricsSink, interval: Duration) -> MetricsReporter {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || run_reporting_loop(&stop, &registry, &sink, interval))
+        };
+
+        MetricsReporter { stop : stop, thread : Some(thread) }
+    }
+
+}
+
+impl Drop for MetricsReporter {
+    fn drop(&mut self) {
+        *self.stop.0.lock().unwrap() = true;
+        self.stop.1.notify_all();
+        if let Some(thread) = self.thread.take() {
+            if let Err(_) = thread.join() {
+                error!("Metrics reporter thread panicked");
+            }
+        }
+    }
+}
+
+fn run_reporting_loop(
+    stop: &(Mutex<bool>, Condvar), registry: &MetricsRegistry, sink: &MetricsSink, interval: Duration
+) {
+    let emit = || {
+        if let Err(error) = sink.emit(registry) {
+            error!("Failed to emit metrics: {}", error);
+        }
+    };
+
+    let (ref mutex, ref condvar) = *stop;
+    let mut stopped = mutex.lock().unwrap();
+    let mut deadline = Instant::now() + interval;
+    while !*stopped {
+        let now = Instant::now();
+        if now >= deadline {
+            emit();
+            deadline = now + interval;
+        } else {
+            stopped = condvar.wait_timeout(stopped, deadline - now).unwrap().0;
+        }
+    }
+    emit();
+}
+
+
+#[test]
+fn test_MetricsRegistry() {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use std::io::{BufRead, BufReader};
+    use jsonrpc::jsonrpc_common::*;
+    use jsonrpc::jsonrpc_response::Response;
+
+    /// Answers requests with an error if their method name starts with "fail".
+    struct TestHandler;
+
+    impl RequestHandler for TestHandler {
+        fn handle_request(&mut self, method_name: &str, _: RequestParams, completable: ResponseCompletable) {
+            if method_name.starts_with("fail") {
+                completable.complete_with_error(error_JSON_RPC_MethodNotFound());
+            } else {
+                completable.complete(Some(ResponseResult::Result(Value::Null)));
+            }
+        }
+    }
+
+    let mut histogram = LatencyHistogram::new(&[10, 100]);
+    assert_eq!(histogram.percentile_ms(50.0), None);
+    for &latency in &[1, 2, 3, 50, 500] {
+        histogram.record(latency);
+    }
+    assert_eq!(histogram.counts, vec![3, 1, 1]);
+    assert_eq!(histogram.mean_ms(), Some(111.2));
+    assert_eq!(histogram.percentile_ms(50.0), Some(10));
+    assert_eq!(histogram.percentile_ms(80.0), Some(100));
+    assert_eq!(histogram.percentile_ms(100.0), Some(500));
+
+    let registry = MetricsRegistry::new();
+    let mut handler = MiddlewareHandler::new(TestHandler).layer(registry.layer());
+    let completable = |id| ResponseCompletable::new(id, new(|_: Option<Response>| {}));
+    handler.handle_request("hover", RequestParams::None, completable(Some(Id::Number(1))));
+    handler.handle_request("hover", RequestParams::None, completable(Some(Id::Number(2))));
+    handler.handle_request("fail", RequestParams::None, completable(Some(Id::Number(3))));
+
+    let hover = registry.method("hover").unwrap();
+    assert_eq!((hover.count, hover.error_count, hover.latency.count()), (2, 0, 2));
+    let fail = registry.method("fail").unwrap();
+    assert_eq!((fail.count, fail.error_count), (1, 1));
+    assert_eq!(registry.snapshot().len(), 2);
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+    let path = env::temp_dir().join(format!("rust_lsp_test_metrics_{}_{}.jsonl", process::id(), nanos));
+    let _ = fs::remove_file(&path);
+    MetricsSink::File(path.clone()).emit(&registry).unwrap();
+    let line = BufReader::new(fs::File::open(&path).unwrap()).lines().next().unwrap().unwrap();
+    let report : Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(report.find_path(&["methods", "hover", "count"]).and_then(Value::as_u64), Some(2));
+    assert_eq!(report.find_path(&["methods", "fail", "errorCount"]).and_then(Value::as_u64), Some(1));
+    fs::remove_file(&path).unwrap();
+
+    let endpoint = LSPEndpoint::create_lsp_output_with_output_stream(|| Vec::<u8>::new());
+    endpoint.request_shutdown();
+    assert!(MetricsSink::TelemetryEvent(endpoint).emit(&registry).is_err());
+
+    registry.reset();
+    assert_eq!(registry.method("hover"), None);
+}
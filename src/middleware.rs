@@ -0,0 +1,279 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Middleware layers around a request handler.
+
+A `MiddlewareHandler` wraps a `RequestHandler` (typically a `ServerRequestHandler` or a `ClientRequestHandler`)
+in a chain of `Middleware` layers. Each layer sees the incoming requests and notifications before the layers
+added after it and the handler, with their method name and raw `RequestParams`, and can:
+
+- answer the message itself, without passing it on to the rest of the chain (for example with an error),
+- pass on different params, with `Next::handle`,
+- pass on a proxy completable, to transform the result (see `lsp::map_response`) or to observe it
+(see `on_response`), for example to measure how long the rest of the chain took to complete the message.
+
+```ignore
+let handler = MiddlewareHandler::new(ServerRequestHandler(ls))
+    .layer(LoggingLayer)
+    .layer(RejectMethodsLayer::new(&[REQUEST__Formatting]))
+    .layer_fn(|method_name, params, completable, next| {
+        next.handle(method_name, params, map_response(completable, |result| result))
+    });
+LSPEndpoint::run_endpoint_loop(&mut reader, endpoint, new(handler));
+```
+
+Note: handlers may complete messages after `Next::handle` has returned (for example on another thread),
+so timings must be measured on completion, not around the call to `Next::handle`.
+A layer cannot tell notifications from requests, other than by their method name: a notification answered
+with an error is reported to the other side the same way as an unknown notification is.
+
+*/
+
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use util::core::*;
+
+use jsonrpc::*;
+use jsonrpc::jsonrpc_common::*;
+use jsonrpc::jsonrpc_request::RequestParams;
+use jsonrpc::jsonrpc_response::Response;
+use jsonrpc::jsonrpc_response::ResponseResult;
+
+use lsp::duration_millis;
+
+/* -----------------  ----------------- */
+
+/// A layer of a `MiddlewareHandler`. See module docs.
+pub trait Middleware {
+    /// Handle an incoming message, either by completing `completable`, or by passing the message on to `next`.
+    fn handle(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable, next: Next);
+}
+
+/// The rest of a middleware chain: the inner layers, then the handler.
+pub struct Next<'a> {
+    layers: &'a mut [Box<Middleware>],
+    handler: &'a mut RequestHandler,
+}
+
+impl<'a> Next<'a> {
+
+    pub fn handle(self, method_name: &str, params: RequestParams, completable: ResponseCompletable) {
+        let Next { layers, handler } = self;
+        match layers.split_first_mut() {
+            Some((layer, layers)) => {
+                layer.handle(method_name, params, completable, Next { layers : layers, handler : handler });
+            }
+            None => handler.handle_request(method_name, params, completable),
+        }
+    }
+
+}
+
+/// A request handler that passes incoming messages through a chain of `Middleware` layers
+/// before `handler`.
+pub struct MiddlewareHandler<HANDLER> {
+    pub handler: HANDLER,
+    layers: Vec<Box<Middleware>>,
+}
+
+impl<HANDLER : RequestHandler> MiddlewareHandler<HANDLER> {
+
+    pub fn new(handler: HANDLER) -> MiddlewareHandler<HANDLER> {
+        MiddlewareHandler { handler : handler, layers : vec![] }
+    }
+
+    /// Add a layer, inside the layers added before: these see messages before the new layer does.
+    pub fn layer<MW : Middleware + 'static>(mut self, layer: MW) -> MiddlewareHandler<HANDLER> {
+        self.layers.push(new(layer));
+        self
+    }
+
+    /// Add a layer given as a function. See `layer`.
+    pub fn layer_fn<FN>(self, layer_fn: FN) -> MiddlewareHandler<HANDLER>
+    where
+        FN : FnMut(&str, RequestParams, ResponseCompletable, Next) + 'static
+    {
+        self.layer(FnMiddleware(layer_fn))
+    }
+
+}
+
+impl<HANDLER : RequestHandler> RequestHandler for MiddlewareHandler<HANDLER> {
+
+    fn handle_request(
+        &mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable
+    ) {
+        let next = Next { layers : &mut self.layers[..], handler : &mut self.handler };
+        next.handle(method_name, params, completable);
+    }
+
+}
+
+struct FnMiddleware<FN>(FN);
+
+impl<FN> Middleware for FnMiddleware<FN>
+where
+    FN : FnMut(&str, RequestParams, ResponseCompletable, Next)
+{
+    fn handle(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable, next: Next) {
+        (self.0)(method_name, params, completable, next)
+    }
+}
+
+/// Return a proxy completable that calls `on_response_fn` with the result given to it (`None` for notifications),
+/// and then completes `completable` with that result.
+pub fn on_response<FN>(completable: ResponseCompletable, on_response_fn: FN) -> ResponseCompletable
+where
+    FN : FnOnce(Option<&ResponseResult>) + Send + 'static
+{
+    let mut completable = Some(completable);
+    let mut on_response_fn = Some(on_response_fn);
+
+    // Note: as in `map_response`, the id is not used.
+    ResponseCompletable::new(Some(Id::Null), new(move |response: Option<Response>| {
+        let completable = completable.take().expect("ResponseCompletable completed twice");
+        let on_response_fn = on_response_fn.take().expect("ResponseCompletable completed twice");
+
+        let result = response.map(|response| response.result_or_error);
+        on_response_fn(result.as_ref());
+        completable.complete(result);
+    }))
+}
+
+/* ----------------- Layers ----------------- */
+
+/// Calls a function with the method name, the time the rest of the chain took to complete each message,
+/// and its result (`None` for notifications).
+pub struct TimingLayer<FN> {
+    on_complete: Arc<FN>,
+}
+
+impl<FN> TimingLayer<FN>
+where
+    FN : Fn(&str, Duration, Option<&ResponseResult>) + Send + Sync + 'static
+{
+    pub fn new(on_complete: FN) -> TimingLayer<FN> {
+        TimingLayer { on_complete : Arc::new(on_complete) }
+    }
+}
+
+impl<FN> Middleware for TimingLayer<FN>
+where
+    FN : Fn(&str, Duration, Option<&ResponseResult>) + Send + Sync + 'static
+{
+    fn handle(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable, next: Next) {
+        let start = Instant::now();
+        let on_complete = self.on_complete.clone();
+        let name = method_name.to_string();
+        let completable = on_response(completable, move |result| {
+            on_complete(&name, start.elapsed(), result);
+        });
+        next.handle(method_name, params, completable);
+    }
+}
+
+/// Logs each message, and how long it took to complete.
+pub struct LoggingLayer;
+
+impl Middleware for LoggingLayer {
+    fn handle(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable, next: Next) {
+        info!("Handling `{}`", method_name);
+        let start = Instant::now();
+        let name = method_name.to_string();
+        let completable = on_response(completable, move |result| {
+            let outcome = match result {
+                Some(&ResponseResult::Error(ref error)) => format!("error {}: {}", error.code, error.message),
+                Some(&ResponseResult::Result(_)) => "result".to_string(),
+                None => "notification".to_string(),
+            };
+            info!("Completed `{}` in {} ms ({})", name, duration_millis(start.elapsed()), outcome);
+        });
+        next.handle(method_name, params, completable);
+    }
+}
+
+/// Answers messages with given method names with a `MethodNotFound` error, without passing them on.
+/// Use to disable some of the methods of a handler.
+pub struct RejectMethodsLayer {
+    pub method_names: Vec<String>,
+}
+
+impl RejectMethodsLayer {
+    pub fn new(method_names: &[&str]) -> RejectMethodsLayer {
+        RejectMethodsLayer { method_names : method_names.iter().map(|name| name.to_string()).collect() }
+    }
+}
+
+impl Middleware for RejectMethodsLayer {
+    fn handle(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable, next: Next) {
+        if self.method_names.iter().any(|name| name == method_name) {
+            completable.complete_with_error(error_JSON_RPC_MethodNotFound());
+        } else {
+            next.handle(method_name, params, completable);
+        }
+    }
+}
+
+
+#[test]
+fn test_MiddlewareHandler() {
+    use std::sync::mpsc;
+    use serde_json::Value;
+    use lsp::map_response;
+
+    /// Answers requests with their params.
+    struct EchoHandler;
+
+    impl RequestHandler for EchoHandler {
+        fn handle_request(&mut self, _: &str, params: RequestParams, completable: ResponseCompletable) {
+            completable.complete(Some(ResponseResult::Result(params.into_value())));
+        }
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let timing_sender = ::std::sync::Mutex::new(sender.clone());
+    let mut handler = MiddlewareHandler::new(EchoHandler)
+        .layer(TimingLayer::new(move |method_name: &str, _, result: Option<&ResponseResult>| {
+            let result = result.cloned();
+            timing_sender.lock().unwrap().send((format!("timed:{}", method_name), result)).unwrap();
+        }))
+        .layer(RejectMethodsLayer::new(&["rejected"]))
+        .layer_fn(|method_name, params, completable, next| {
+            // Transform params and results
+            let params = match params {
+                RequestParams::None => RequestParams::Array(vec![Value::String(method_name.to_string())]),
+                params => params,
+            };
+            let completable = map_response(completable, |result| match result {
+                ResponseResult::Result(value) => ResponseResult::Result(Value::Array(vec![value])),
+                error => error,
+            });
+            next.handle(method_name, params, completable);
+        });
+
+    let new_completable = |id| {
+        let sender = sender.clone();
+        ResponseCompletable::new(Some(Id::Number(id)), new(move |response: Option<Response>| {
+            let response = response.unwrap();
+            sender.send((format!("response:{:?}", response.id), Some(response.result_or_error))).unwrap();
+        }))
+    };
+
+    handler.handle_request("echo", RequestParams::None, new_completable(1));
+    let expected = ResponseResult::Result(Value::Array(vec![Value::Array(vec![Value::String("echo".into())])]));
+    assert_eq!(receiver.recv().unwrap(), ("timed:echo".to_string(), Some(expected.clone())));
+    assert_eq!(receiver.recv().unwrap(), ("response:Number(1)".to_string(), Some(expected)));
+
+    handler.handle_request("rejected", RequestParams::None, new_completable(2));
+    let expected = ResponseResult::Error(error_JSON_RPC_MethodNotFound());
+    assert_eq!(receiver.recv().unwrap(), ("timed:rejected".to_string(), Some(expected.clone())));
+    assert_eq!(receiver.recv().unwrap(), ("response:Number(2)".to_string(), Some(expected)));
+}
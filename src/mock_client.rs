@@ -0,0 +1,421 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+A scriptable `LanguageClientHandling`, for driving servers in integration tests.
+
+`MockLanguageClient` records all the messages it receives from the server, and answers server requests
+with the responses scripted with `respond_once` / `respond_always`, or with a default response:
+
+* `window/showMessageRequest`: the first action, if any.
+* `workspace/configuration`: `null` for each item.
+* `workspace/workspaceFolders`: `null`.
+* `window/showDocument`: success.
+* `workspace/applyEdit`: applied.
+* refresh requests and `window/workDoneProgress/create`: an empty result.
+* other methods: a `MethodNotFound` error.
+
+Unknown methods prefixed with `$/`, and those marked with `handle_as_notification` (unless they have
+a scripted response), are handled as notifications: they are recorded as such, and not answered.
+
+Clones share the same recordings and script, so a test keeps a clone to check what the client received
+while the original runs in the client message loop:
+
+```ignore
+let client = MockLanguageClient::new();
+let test_client = client.clone();
+let mut connection = connect_in_process(|endpoint| MyLanguageServer::new(endpoint), move |_| client);
+// ... open a document ...
+let diagnostics = test_client.wait_for_diagnostics(&uri, Duration::from_secs(5));
+```
+
+*/
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use jsonrpc::*;
+use jsonrpc::jsonrpc_common::*;
+use jsonrpc::jsonrpc_request::RequestParams;
+use jsonrpc::jsonrpc_response::ResponseResult;
+use jsonrpc::method_types::MethodError;
+use jsonrpc::method_types::MethodResult;
+
+use serde;
+use serde_json;
+use serde_json::Value;
+use serde_json::builder::ObjectBuilder;
+
+use url::Url;
+
+use ls_types::*;
+use ls_types_ext::*;
+use lsp::*;
+
+/* -----------------  ----------------- */
+
+/// A scripted response to a server request.
+#[derive(Debug, PartialEq, Clone)]
+pub enum MockResponse {
+    Result(Value),
+    /// An error with given code and message.
+    Error(i64, String),
+}
+
+/// A message received from the server.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReceivedMessage {
+    pub method: String,
+    pub params: Value,
+    /// Whether the message was a request (as opposed to a notification).
+    pub is_request: bool,
+}
+
+#[derive(Default)]
+struct MockState {
+    received: Vec<ReceivedMessage>,
+    scripted_responses: HashMap<String, VecDeque<MockResponse>>,
+    default_responses: HashMap<String, MockResponse>,
+    notification_methods: HashSet<String>,
+}
+
+/// A mock language client. See module docs.
+#[derive(Clone)]
+pub struct MockLanguageClient {
+    state: Arc<(Mutex<MockState>, Condvar)>,
+}
+
+impl MockLanguageClient {
+
+    pub fn new() -> MockLanguageClient {
+        MockLanguageClient { state : Arc::new((Mutex::new(MockState::default()), Condvar::new())) }
+    }
+
+    /* ----------------- Scripting ----------------- */
+
+    /// Answer the next request of given method with given response.
+    /// Several responses scripted for the same method are used in order.
+    pub fn respond_once(&self, method_name: &str, response: MockResponse) {
+        let mut state = self.state.0.lock().unwrap();
+        state.scripted_responses.entry(method_name.to_string()).or_insert_with(VecDeque::new).push_back(response);
+    }
+
+    /// Answer the requests of given method with given response, once the responses scripted with `respond_once`
+    /// are used up.
+    pub fn respond_always(&self, method_name: &str, response: MockResponse) {
+        self.state.0.lock().unwrap().default_responses.insert(method_name.to_string(), response);
+    }
+
+    /// Handle the unknown method of given name as a notification, when it has no scripted response.
+    /// Methods prefixed with `$/` are always handled as notifications.
+    pub fn handle_as_notification(&self, method_name: &str) {
+        self.state.0.lock().unwrap().notification_methods.insert(method_name.to_string());
+    }
+
+    fn is_notification_method(&self, method_name: &str) -> bool {
+        self.state.0.lock().unwrap().notification_methods.contains(method_name)
+    }
+
+    fn scripted_response(&self, method_name: &str) -> Option<MockResponse> {
+        let mut state = self.state.0.lock().unwrap();
+        let scripted = state.scripted_responses.get_mut(method_name).and_then(|responses| responses.pop_front());
+        scripted.or_else(|| state.default_responses.get(method_name).cloned())
+    }
+
+    /* ----------------- Recordings ----------------- */
+
+    fn record<PARAMS : serde::Serialize>(&self, method_name: &str, params: &PARAMS, is_request: bool) {
+        let message = ReceivedMessage {
+            method : method_name.to_string(), params : serde_json::to_value(params), is_request : is_request,
+        };
+        self.state.0.lock().unwrap().received.push(message);
+        self.state.1.notify_all();
+    }
+
+    /// All the messages received, in order.
+    pub fn received(&self) -> Vec<ReceivedMessage> {
+        self.state.0.lock().unwrap().received.clone()
+    }
+
+    /// The params of the notifications of given method received, in order.
+    pub fn notifications(&self, method_name: &str) -> Vec<Value> {
+        self.received().into_iter()
+            .filter(|message| !message.is_request && message.method == method_name)
+            .map(|message| message.params)
+            .collect()
+    }
+
+    /// The params of the requests of given method received, in order.
+    pub fn requests(&self, method_name: &str) -> Vec<Value> {
+        self.received().into_iter()
+            .filter(|message| message.is_request && message.method == method_name)
+            .map(|message| message.params)
+            .collect()
+    }
+
+    /// The diagnostics last published for given document.
+    pub fn diagnostics(&self, uri: &Url) -> Option<PublishDiagnosticsParams> {
+        self.notifications(NOTIFICATION__PublishDiagnostics).into_iter()
+            .filter_map(|params| serde_json::from_value::<PublishDiagnosticsParams>(params).ok())
+            .filter(|params| &params.uri == uri)
+            .last()
+    }
+
+    /// Forget the messages received so far, so that the `wait_for` methods only consider new messages.
+    pub fn clear_received(&self) {
+        self.state.0.lock().unwrap().received.clear();
+    }
+
+    /* ----------------- Assertion helpers ----------------- */
+
+    /// Wait until a message matching `predicate` has been received, and return the first such message.
+    /// Returns `None` on timeout.
+    pub fn wait_for<FN>(&self, timeout: Duration, predicate: FN) -> Option<ReceivedMessage>
+    where
+        FN : Fn(&ReceivedMessage) -> bool
+    {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.0.lock().unwrap();
+        loop {
+            if let Some(message) = state.received.iter().find(|message| predicate(message)) {
+                return Some(message.clone());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            state = self.state.1.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Wait until a notification of given method has been received, and return its params.
+    /// Panics on timeout.
+    pub fn wait_for_notification(&self, method_name: &str, timeout: Duration) -> Value {
+        let message = self.wait_for(timeout, |message| !message.is_request && message.method == method_name);
+        match message {
+            Some(message) => message.params,
+            None => panic!("No `{}` notification received after {:?}", method_name, timeout),
+        }
+    }
+
+    /// Wait until diagnostics have been published for given document, and return the last ones.
+    /// Panics on timeout.
+    pub fn wait_for_diagnostics(&self, uri: &Url, timeout: Duration) -> PublishDiagnosticsParams {
+        let uri_value = Value::String(uri.as_str().to_string());
+        let message = self.wait_for(timeout, |message| {
+            message.method == NOTIFICATION__PublishDiagnostics && message.params.find("uri") == Some(&uri_value)
+        });
+        if message.is_none() {
+            panic!("No diagnostics published for `{}` after {:?}", uri, timeout);
+        }
+        self.diagnostics(uri).expect("Invalid publishDiagnostics params")
+    }
+
+    /* ----------------- Responses ----------------- */
+
+    /// Complete with the scripted response for given method, if any, or with the result of `default` otherwise.
+    fn complete_typed<RET, DEFAULT>(&self, method_name: &str, completable: LSCompletable<RET>, default: DEFAULT)
+    where
+        RET : serde::Serialize + serde::Deserialize,
+        DEFAULT : FnOnce() -> MethodResult<RET, ()>,
+    {
+        let result = match self.scripted_response(method_name) {
+            Some(MockResponse::Result(value)) => {
+                serde_json::from_value::<RET>(value).map_err(|error| {
+                    MethodError::new(1, format!("Invalid scripted response for `{}`: {}", method_name, error), ())
+                })
+            }
+            // Not a `MethodError`, as these have unsigned codes
+            Some(MockResponse::Error(code, message)) => {
+                return completable.complete_with_error(RequestError::new(code, message));
+            }
+            None => default(),
+        };
+        completable.complete(result)
+    }
+
+}
+
+impl LanguageClientHandling for MockLanguageClient {
+
+    fn show_message(&mut self, params: ShowMessageParams) {
+        self.record(NOTIFICATION__ShowMessage, &params, false);
+    }
+
+    fn show_message_request(&mut self, params: ShowMessageRequestParams,
+        completable: LSCompletable<MessageActionItem>)
+    {
+        self.record(REQUEST__ShowMessageRequest, &params, true);
+        let first_action = params.actions.and_then(|actions| actions.into_iter().next());
+        self.complete_typed(REQUEST__ShowMessageRequest, completable, || {
+            // The result can't be null, so without actions the default is an error
+            first_action.ok_or_else(|| MethodError::new(1, "No action to select".into(), ()))
+        })
+    }
+
+    fn log_message(&mut self, params: LogMessageParams) {
+        self.record(NOTIFICATION__LogMessage, &params, false);
+    }
+
+    fn telemetry_event(&mut self, params: Value) {
+        self.record(NOTIFICATION__TelemetryEvent, &params, false);
+    }
+
+    fn publish_diagnostics(&mut self, params: PublishDiagnosticsParams) {
+        self.record(NOTIFICATION__PublishDiagnostics, &params, false);
+    }
+
+    fn inlay_hint_refresh(&mut self, params: (), completable: LSCompletable<()>) {
+        self.record(REQUEST__InlayHintRefresh, &params, true);
+        self.complete_typed(REQUEST__InlayHintRefresh, completable, || Ok(()))
+    }
+
+    fn inline_value_refresh(&mut self, params: (), completable: LSCompletable<()>) {
+        self.record(REQUEST__InlineValueRefresh, &params, true);
+        self.complete_typed(REQUEST__InlineValueRefresh, completable, || Ok(()))
+    }
+
+    fn diagnostic_refresh(&mut self, params: (), completable: LSCompletable<()>) {
+        self.record(REQUEST__DiagnosticRefresh, &params, true);
+        self.complete_typed(REQUEST__DiagnosticRefresh, completable, || Ok(()))
+    }
+
+    fn workspace_folders(&mut self, params: (), completable: LSCompletable<Option<Vec<WorkspaceFolder>>>) {
+        self.record(REQUEST__WorkspaceFolders, &params, true);
+        self.complete_typed(REQUEST__WorkspaceFolders, completable, || Ok(None))
+    }
+
+    fn configuration(&mut self, params: ConfigurationParams, completable: LSCompletable<Vec<Value>>) {
+        self.record(REQUEST__Configuration, &params, true);
+        let item_count = params.items.len();
+        self.complete_typed(REQUEST__Configuration, completable, || Ok(vec![Value::Null; item_count]))
+    }
+
+    fn show_document(&mut self, params: ShowDocumentParams, completable: LSCompletable<ShowDocumentResult>) {
+        self.record(REQUEST__ShowDocument, &params, true);
+        self.complete_typed(REQUEST__ShowDocument, completable, || Ok(ShowDocumentResult { success : true }))
+    }
+
+    fn work_done_progress_create(&mut self, params: WorkDoneProgressCreateParams, completable: LSCompletable<()>) {
+        self.record(REQUEST__WorkDoneProgressCreate, &params, true);
+        self.complete_typed(REQUEST__WorkDoneProgressCreate, completable, || Ok(()))
+    }
+
+    fn handle_other_method(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable) {
+        let params = params.into_value();
+        if method_name.starts_with("$/") {
+            self.record(method_name, &params, false);
+            return completable.complete(None);
+        }
+
+        let response = self.scripted_response(method_name);
+        if response.is_none() && self.is_notification_method(method_name) {
+            self.record(method_name, &params, false);
+            return completable.complete(None);
+        }
+        self.record(method_name, &params, true);
+
+        let response = response.or_else(|| {
+            if method_name == REQUEST__ApplyEdit {
+                Some(MockResponse::Result(ObjectBuilder::new().insert("applied", true).build()))
+            } else {
+                None
+            }
+        });
+        match response {
+            Some(MockResponse::Result(value)) => completable.complete(Some(ResponseResult::Result(value))),
+            Some(MockResponse::Error(code, message)) => {
+                completable.complete_with_error(RequestError::new(code, message))
+            }
+            None => completable.complete_with_error(error_JSON_RPC_MethodNotFound()),
+        }
+    }
+
+}
+
+
+#[test]
+fn test_MockLanguageClient() {
+    use std::sync::mpsc;
+    use std::thread;
+    use jsonrpc::jsonrpc_response::Response;
+
+    fn handle(client: &MockLanguageClient, method_name: &str, params: Value) -> Option<ResponseResult> {
+        let (sender, receiver) = mpsc::channel();
+        let completable = ResponseCompletable::new(Some(Id::Number(1)), new(move |response: Option<Response>| {
+            sender.send(response.map(|response| response.result_or_error)).unwrap();
+        }));
+        let params = match params {
+            Value::Object(object) => RequestParams::Object(object),
+            _ => panic!("Expected an object"),
+        };
+        ClientRequestHandler(client.clone()).handle_request(method_name, params, completable);
+        receiver.recv().unwrap()
+    }
+
+    let client = MockLanguageClient::new();
+    let uri = Url::parse("file:///foo.rs").unwrap();
+
+    let mut background_client = client.clone();
+    let params = PublishDiagnosticsParams::new(uri.clone(), vec![Diagnostic::new_simple(Range::default(), "E".into())]);
+    let expected_params = serde_json::to_value(&params);
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        background_client.publish_diagnostics(params);
+    });
+    let diagnostics = client.wait_for_diagnostics(&uri, Duration::from_secs(5));
+    assert_eq!(serde_json::to_value(&diagnostics), expected_params);
+    assert!(client.wait_for(Duration::from_millis(1), |message| message.method == "foo").is_none());
+
+    // Default and scripted responses
+    let configuration_params = ObjectBuilder::new()
+        .insert_array("items", |builder| builder.push_object(|builder| builder.insert("section", "foo")))
+        .build();
+    assert_eq!(handle(&client, REQUEST__Configuration, configuration_params.clone()),
+        Some(ResponseResult::Result(Value::Array(vec![Value::Null]))));
+
+    client.respond_once(REQUEST__Configuration, MockResponse::Result(Value::Array(vec![Value::Bool(true)])));
+    assert_eq!(handle(&client, REQUEST__Configuration, configuration_params.clone()),
+        Some(ResponseResult::Result(Value::Array(vec![Value::Bool(true)]))));
+    assert_eq!(handle(&client, REQUEST__Configuration, configuration_params.clone()),
+        Some(ResponseResult::Result(Value::Array(vec![Value::Null]))));
+    assert_eq!(client.requests(REQUEST__Configuration).len(), 3);
+
+    let edit_params = ObjectBuilder::new().insert_object("edit", |builder| builder).build();
+    assert_eq!(handle(&client, REQUEST__ApplyEdit, edit_params.clone()),
+        Some(ResponseResult::Result(ObjectBuilder::new().insert("applied", true).build())));
+    client.respond_always(REQUEST__ApplyEdit, MockResponse::Error(5, "Rejected".into()));
+    assert_eq!(handle(&client, REQUEST__ApplyEdit, edit_params.clone()),
+        Some(ResponseResult::Error(RequestError::new(5, "Rejected".into()))));
+
+    client.respond_once(REQUEST__ApplyEdit, MockResponse::Error(-32800, "Cancelled".into()));
+    assert_eq!(handle(&client, REQUEST__ApplyEdit, edit_params.clone()),
+        Some(ResponseResult::Error(RequestError::new(-32800, "Cancelled".into()))));
+    client.respond_once(REQUEST__Configuration, MockResponse::Error(-32800, "Cancelled".into()));
+    assert_eq!(handle(&client, REQUEST__Configuration, configuration_params.clone()),
+        Some(ResponseResult::Error(RequestError::new(-32800, "Cancelled".into()))));
+
+    // Notifications of unknown methods are not answered
+    client.clear_received();
+    let progress_params = ObjectBuilder::new().insert("token", 1).build();
+    assert_eq!(handle(&client, "$/progress", progress_params.clone()), None);
+    client.handle_as_notification("custom/notification");
+    assert_eq!(handle(&client, "custom/notification", progress_params.clone()), None);
+    assert_eq!(client.notifications("$/progress"), vec![progress_params.clone()]);
+    assert_eq!(client.notifications("custom/notification"), vec![progress_params]);
+    assert!(client.requests("$/progress").is_empty());
+
+    client.clear_received();
+    assert!(client.received().is_empty());
+}
@@ -0,0 +1,552 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Protocol version negotiation.
+
+The base protocol methods are those of `ls_types`, which every peer supports.
+The newer methods of `ls_types_ext` are only used when the peer declared support for them
+in the capabilities exchanged during `initialize`:
+
+* `NegotiatingRequestHandler` wraps a `LanguageServerHandling`, records the client capabilities,
+  rejects newer requests the client did not declare, and advertises the `ServerCapabilitiesExt`
+  the client can make use of, along with the negotiated `positionEncoding`.
+* `NegotiatedClientRpc` is an `LspClientRpc` that refuses to send newer requests to a client
+  that does not support them.
+
+*/
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use util::core::*;
+
+use jsonrpc::*;
+use jsonrpc::jsonrpc_common::*;
+use jsonrpc::jsonrpc_request::RequestParams;
+use jsonrpc::jsonrpc_response::ResponseResult;
+
+use serde_json;
+use serde_json::Value;
+
+use ls_types::*;
+use ls_types_ext::*;
+use lsp::*;
+
+/* ----------------- Client features ----------------- */
+
+/// For each newer method, the path of the client capability that declares support for it.
+const METHOD_CAPABILITIES: &'static [(&'static str, &'static [&'static str])] = &[
+    // Client to server
+    (REQUEST__PrepareCallHierarchy, &["textDocument", "callHierarchy"]),
+    (REQUEST__CallHierarchyIncomingCalls, &["textDocument", "callHierarchy"]),
+    (REQUEST__CallHierarchyOutgoingCalls, &["textDocument", "callHierarchy"]),
+    (REQUEST__PrepareTypeHierarchy, &["textDocument", "typeHierarchy"]),
+    (REQUEST__TypeHierarchySupertypes, &["textDocument", "typeHierarchy"]),
+    (REQUEST__TypeHierarchySubtypes, &["textDocument", "typeHierarchy"]),
+    (REQUEST__InlayHint, &["textDocument", "inlayHint"]),
+    (REQUEST__InlayHintResolve, &["textDocument", "inlayHint"]),
+    (REQUEST__InlineValue, &["textDocument", "inlineValue"]),
+    (REQUEST__DocumentDiagnostic, &["textDocument", "diagnostic"]),
+    (REQUEST__WorkspaceDiagnostic, &["textDocument", "diagnostic"]),
+    (REQUEST__PrepareRename, &["textDocument", "rename", "prepareSupport"]),
+    (REQUEST__LinkedEditingRange, &["textDocument", "linkedEditingRange"]),
+    (NOTIFICATION__DidChangeWorkspaceFolders, &["workspace", "workspaceFolders"]),
+    (REQUEST__WillCreateFiles, &["workspace", "fileOperations", "willCreate"]),
+    (NOTIFICATION__DidCreateFiles, &["workspace", "fileOperations", "didCreate"]),
+    (REQUEST__WillRenameFiles, &["workspace", "fileOperations", "willRename"]),
+    (NOTIFICATION__DidRenameFiles, &["workspace", "fileOperations", "didRename"]),
+    (REQUEST__WillDeleteFiles, &["workspace", "fileOperations", "willDelete"]),
+    (NOTIFICATION__DidDeleteFiles, &["workspace", "fileOperations", "didDelete"]),
+    (NOTIFICATION__WorkDoneProgressCancel, &["window", "workDoneProgress"]),
+    // Server to client
+    (REQUEST__InlayHintRefresh, &["workspace", "inlayHint", "refreshSupport"]),
+    (REQUEST__InlineValueRefresh, &["workspace", "inlineValue", "refreshSupport"]),
+    (REQUEST__DiagnosticRefresh, &["workspace", "diagnostics", "refreshSupport"]),
+    (REQUEST__WorkspaceFolders, &["workspace", "workspaceFolders"]),
+    (REQUEST__Configuration, &["workspace", "configuration"]),
+    (REQUEST__ShowDocument, &["window", "showDocument", "support"]),
+    (REQUEST__WorkDoneProgressCreate, &["window", "workDoneProgress"]),
+];
+
+/// The notifications among the `METHOD_CAPABILITIES` methods.
+const NEWER_NOTIFICATIONS: &'static [&'static str] = &[
+    NOTIFICATION__DidChangeWorkspaceFolders,
+    NOTIFICATION__DidCreateFiles,
+    NOTIFICATION__DidRenameFiles,
+    NOTIFICATION__DidDeleteFiles,
+    NOTIFICATION__WorkDoneProgressCancel,
+];
+
+/// The protocol features a client declared support for, in its `initialize` capabilities.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClientFeatures {
+    capabilities: ClientCapabilities,
+}
+
+impl ClientFeatures {
+
+    pub fn from_capabilities(capabilities: &ClientCapabilities) -> ClientFeatures {
+        ClientFeatures { capabilities : capabilities.clone() }
+    }
+
+    /// The features of a client that has not sent `initialize` yet: only the base protocol.
+    pub fn base_protocol() -> ClientFeatures {
+        Self::from_capabilities(&Value::Null)
+    }
+
+    pub fn capabilities(&self) -> &ClientCapabilities {
+        &self.capabilities
+    }
+
+    /// Whether the client supports given method. Base protocol methods are always supported.
+    pub fn supports_method(&self, method_name: &str) -> bool {
+        let entry = METHOD_CAPABILITIES.iter().find(|&&(method, _)| method == method_name);
+        match entry {
+            None => true,
+            Some(&(_, path)) => {
+                match self.capabilities.find_path(path) {
+                    None | Some(&Value::Null) | Some(&Value::Bool(false)) => false,
+                    Some(_) => true,
+                }
+            }
+        }
+    }
+
+    /// The position encoding to use with this client: the first of UTF-8, UTF-32 and UTF-16 that the client
+    /// lists in `general.positionEncodings`. UTF-16 if the client lists none, as the spec mandates.
+    pub fn position_encoding(&self) -> PositionEncodingKind {
+        let client_encodings : Vec<PositionEncodingKind> =
+            match self.capabilities.find_path(&["general", "positionEncodings"]).and_then(Value::as_array) {
+                Some(encodings) => encodings.iter().filter_map(Value::as_str)
+                    .filter_map(PositionEncodingKind::from_str).collect(),
+                None => vec![],
+            };
+
+        let preferred = [PositionEncodingKind::UTF8, PositionEncodingKind::UTF32];
+        preferred.iter().cloned()
+            .find(|encoding| client_encodings.contains(encoding))
+            .unwrap_or(PositionEncodingKind::UTF16)
+    }
+
+    /// Return an error if the client does not support given method.
+    pub fn check_method(&self, method_name: &str) -> GResult<()> {
+        if self.supports_method(method_name) {
+            Ok(())
+        } else {
+            Err(format!("Method `{}` not supported by the client.", method_name).into())
+        }
+    }
+
+}
+
+/// Client features shared between a `NegotiatingRequestHandler` and the rest of the server.
+pub type SharedClientFeatures = Arc<Mutex<ClientFeatures>>;
+
+pub fn new_shared_client_features() -> SharedClientFeatures {
+    newArcMutex(ClientFeatures::base_protocol())
+}
+
+/* ----------------- Server capabilities ----------------- */
+
+/// Server capabilities for the methods of `ls_types_ext`.
+/// These are added to the `ServerCapabilities` returned by `initialize`.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct ServerCapabilitiesExt {
+    /// The server provides call hierarchy support.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="callHierarchyProvider")]
+    pub call_hierarchy_provider: Option<bool>,
+
+    /// The server provides type hierarchy support.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="typeHierarchyProvider")]
+    pub type_hierarchy_provider: Option<bool>,
+
+    /// The server provides inlay hints.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="inlayHintProvider")]
+    pub inlay_hint_provider: Option<InlayHintOptions>,
+
+    /// The server provides inline values.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="inlineValueProvider")]
+    pub inline_value_provider: Option<bool>,
+
+    /// The server has support for pull model diagnostics.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="diagnosticProvider")]
+    pub diagnostic_provider: Option<DiagnosticOptions>,
+
+    /// The server provides linked editing range support.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="linkedEditingRangeProvider")]
+    pub linked_editing_range_provider: Option<bool>,
+
+    /// The server provides rename support, with rename preparation.
+    /// If present, overrides `ServerCapabilities::rename_provider`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="renameProvider")]
+    pub rename_provider: Option<RenameOptions>,
+
+    /// Workspace specific server capabilities.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub workspace: Option<WorkspaceServerCapabilities>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct InlayHintOptions {
+    /// The server provides support to resolve additional information for an inlay hint item.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="resolveProvider")]
+    pub resolve_provider: Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct DiagnosticOptions {
+    /// An optional identifier under which the diagnostics are managed by the client.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub identifier: Option<String>,
+
+    /// Whether the language has inter file dependencies meaning that editing code in one file can result in
+    /// a different diagnostic set in another file.
+    #[serde(rename="interFileDependencies")]
+    pub inter_file_dependencies: bool,
+
+    /// The server provides support for workspace diagnostics as well.
+    #[serde(rename="workspaceDiagnostics")]
+    pub workspace_diagnostics: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct RenameOptions {
+    /// Renames should be checked and tested before being executed.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="prepareProvider")]
+    pub prepare_provider: Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct WorkspaceServerCapabilities {
+    /// The server supports workspace folder.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="workspaceFolders")]
+    pub workspace_folders: Option<WorkspaceFoldersServerCapabilities>,
+
+    /// The server is interested in file notifications/requests.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="fileOperations")]
+    pub file_operations: Option<FileOperationsServerCapabilities>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct WorkspaceFoldersServerCapabilities {
+    /// The server has support for workspace folders.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub supported: Option<bool>,
+
+    /// Whether the server wants to receive workspace folder change notifications.
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="changeNotifications")]
+    pub change_notifications: Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct FileOperationsServerCapabilities {
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="didCreate")]
+    pub did_create: Option<FileOperationRegistrationOptions>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="willCreate")]
+    pub will_create: Option<FileOperationRegistrationOptions>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="didRename")]
+    pub did_rename: Option<FileOperationRegistrationOptions>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="willRename")]
+    pub will_rename: Option<FileOperationRegistrationOptions>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="didDelete")]
+    pub did_delete: Option<FileOperationRegistrationOptions>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    #[serde(rename="willDelete")]
+    pub will_delete: Option<FileOperationRegistrationOptions>,
+}
+
+/// The options to register for file operations.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct FileOperationRegistrationOptions {
+    /// The actual filters.
+    pub filters: Vec<FileOperationFilter>,
+}
+
+/// A filter to describe in which file operation requests or notifications the server is interested in.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct FileOperationFilter {
+    /// A Uri like `file` or `untitled`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub scheme: Option<String>,
+
+    /// The actual file operation pattern.
+    pub pattern: FileOperationPattern,
+}
+
+/// A pattern to describe in which file operation requests or notifications the server is interested in.
+#[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize)]
+pub struct FileOperationPattern {
+    /// The glob pattern to match.
+    pub glob: String,
+}
+
+/// For each capability of `ServerCapabilitiesExt`, the method the client must support for it to be advertised.
+const CAPABILITY_METHODS: &'static [(&'static str, &'static str)] = &[
+    ("callHierarchyProvider", REQUEST__PrepareCallHierarchy),
+    ("typeHierarchyProvider", REQUEST__PrepareTypeHierarchy),
+    ("inlayHintProvider", REQUEST__InlayHint),
+    ("inlineValueProvider", REQUEST__InlineValue),
+    ("diagnosticProvider", REQUEST__DocumentDiagnostic),
+    ("linkedEditingRangeProvider", REQUEST__LinkedEditingRange),
+    ("renameProvider", REQUEST__PrepareRename),
+];
+
+impl ServerCapabilitiesExt {
+
+    /// Add the capabilities supported by given client to the given (serialized) `ServerCapabilities`.
+    /// This includes the negotiated `positionEncoding`.
+    pub fn add_negotiated_to(&self, server_capabilities: &mut Value, client: &ClientFeatures) {
+        let capabilities = match *server_capabilities {
+            Value::Object(ref mut capabilities) => capabilities,
+            _ => return,
+        };
+
+        if let Value::Object(extensions) = serde_json::to_value(self) {
+            for (key, value) in extensions {
+                let method = CAPABILITY_METHODS.iter().find(|&&(capability, _)| capability == key);
+                let supported = method.map_or(true, |&(_, method)| client.supports_method(method));
+                if supported {
+                    capabilities.insert(key, value);
+                }
+            }
+        }
+
+        capabilities.insert("positionEncoding".into(), serde_json::to_value(&client.position_encoding()));
+    }
+
+}
+
+/* ----------------- Negotiating handler ----------------- */
+
+/// A server request handler that negotiates the protocol features with the client.
+///
+/// On `initialize`, the client capabilities are recorded in the `SharedClientFeatures`,
+/// and the server capabilities `ServerCapabilitiesExt` that the client supports are added to the
+/// `initialize` result. Afterwards, newer requests or notifications the client did not declare support for
+/// are not passed on to the `LanguageServerHandling`.
+///
+/// Use with `LSPEndpoint::run_endpoint_loop`.
+pub struct NegotiatingRequestHandler<LS : ?Sized> {
+    pub client_features: SharedClientFeatures,
+    pub capabilities_ext: ServerCapabilitiesExt,
+    pub handler: ServerRequestHandler<LS>,
+}
+
+impl<LS : LanguageServerHandling> NegotiatingRequestHandler<LS> {
+
+    pub fn new(ls: LS, capabilities_ext: ServerCapabilitiesExt, client_features: SharedClientFeatures)
+        -> NegotiatingRequestHandler<LS>
+    {
+        NegotiatingRequestHandler {
+            client_features : client_features,
+            capabilities_ext : capabilities_ext,
+            handler : ServerRequestHandler(ls),
+        }
+    }
+
+}
+
+impl<LS : LanguageServerHandling + ?Sized> RequestHandler for NegotiatingRequestHandler<LS> {
+
+    fn handle_request(
+        &mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable
+    ) {
+        if method_name == REQUEST__Initialize {
+            let capabilities = params.clone().into_value().find("capabilities").cloned().unwrap_or(Value::Null);
+            let client_features = ClientFeatures::from_capabilities(&capabilities);
+            *self.client_features.lock().unwrap() = client_features.clone();
+
+            let capabilities_ext = self.capabilities_ext.clone();
+            let completable = map_response(completable, move |response_result| {
+                match response_result {
+                    ResponseResult::Result(mut result) => {
+                        if let Some(capabilities) = result.as_object_mut().and_then(|r| r.get_mut("capabilities")) {
+                            capabilities_ext.add_negotiated_to(capabilities, &client_features);
+                        }
+                        ResponseResult::Result(result)
+                    }
+                    error => error,
+                }
+            });
+            return self.handler.handle_request(method_name, params, completable);
+        }
+
+        let supported = self.client_features.lock().unwrap().supports_method(method_name);
+        if supported {
+            self.handler.handle_request(method_name, params, completable);
+        } else if NEWER_NOTIFICATIONS.contains(&method_name) {
+            info!("Dropping notification `{}`, not declared in client capabilities.", method_name);
+            completable.complete(None);
+        } else {
+            completable.complete_with_error(error_JSON_RPC_MethodNotFound());
+        }
+    }
+
+}
+
+/* ----------------- Negotiated client RPC ----------------- */
+
+/// An `LspClientRpc` that fails to send methods the client has not declared support for.
+pub struct NegotiatedClientRpc<'a> {
+    pub rpc: LspClientRpc_<'a>,
+    pub client_features: ClientFeatures,
+}
+
+pub fn negotiated_client_rpc_handle<'a>(endpoint: &'a mut Endpoint, client_features: &SharedClientFeatures)
+    -> NegotiatedClientRpc<'a>
+{
+    let client_features = client_features.lock().unwrap().clone();
+    NegotiatedClientRpc { rpc : client_rpc_handle(endpoint), client_features : client_features }
+}
+
+impl<'a> LspClientRpc for NegotiatedClientRpc<'a> {
+
+    fn show_message(&mut self, params: ShowMessageParams)
+        -> GResult<()>
+    {
+        self.rpc.show_message(params)
+    }
+
+    fn show_message_request(&mut self, params: ShowMessageRequestParams)
+        -> GResult<RequestFuture<MessageActionItem, ()>>
+    {
+        self.rpc.show_message_request(params)
+    }
+
+    fn log_message(&mut self, params: LogMessageParams)
+        -> GResult<()>
+    {
+        self.rpc.log_message(params)
+    }
+
+    fn telemetry_event(&mut self, params: Value)
+        -> GResult<()>
+    {
+        self.rpc.telemetry_event(params)
+    }
+
+    fn publish_diagnostics(&mut self, params: PublishDiagnosticsParams)
+        -> GResult<()>
+    {
+        self.rpc.publish_diagnostics(params)
+    }
+
+    fn inlay_hint_refresh(&mut self)
+        -> GResult<RequestFuture<(), ()>>
+    {
+        try!(self.client_features.check_method(REQUEST__InlayHintRefresh));
+        self.rpc.inlay_hint_refresh()
+    }
+
+    fn inline_value_refresh(&mut self)
+        -> GResult<RequestFuture<(), ()>>
+    {
+        try!(self.client_features.check_method(REQUEST__InlineValueRefresh));
+        self.rpc.inline_value_refresh()
+    }
+
+    fn diagnostic_refresh(&mut self)
+        -> GResult<RequestFuture<(), ()>>
+    {
+        try!(self.client_features.check_method(REQUEST__DiagnosticRefresh));
+        self.rpc.diagnostic_refresh()
+    }
+
+    fn workspace_folders(&mut self)
+        -> GResult<RequestFuture<Option<Vec<WorkspaceFolder>>, ()>>
+    {
+        try!(self.client_features.check_method(REQUEST__WorkspaceFolders));
+        self.rpc.workspace_folders()
+    }
+
+    fn configuration(&mut self, params: ConfigurationParams)
+        -> GResult<RequestFuture<Vec<Value>, ()>>
+    {
+        try!(self.client_features.check_method(REQUEST__Configuration));
+        self.rpc.configuration(params)
+    }
+
+    fn show_document(&mut self, params: ShowDocumentParams)
+        -> GResult<RequestFuture<ShowDocumentResult, ()>>
+    {
+        try!(self.client_features.check_method(REQUEST__ShowDocument));
+        self.rpc.show_document(params)
+    }
+
+    fn work_done_progress_create(&mut self, params: WorkDoneProgressCreateParams)
+        -> GResult<RequestFuture<(), ()>>
+    {
+        try!(self.client_features.check_method(REQUEST__WorkDoneProgressCreate));
+        self.rpc.work_done_progress_create(params)
+    }
+
+}
+
+
+#[test]
+fn test_ClientFeatures() {
+    use serde_json::builder::ObjectBuilder;
+
+    let features = ClientFeatures::base_protocol();
+    assert!(features.supports_method(REQUEST__Hover));
+    assert!(!features.supports_method(REQUEST__InlayHint));
+    assert!(features.check_method(REQUEST__ShowDocument).is_err());
+
+    let capabilities = ObjectBuilder::new()
+        .insert_object("textDocument", |builder| builder
+            .insert_object("inlayHint", |builder| builder)
+            .insert_object("rename", |builder| builder.insert("prepareSupport", false))
+        )
+        .build();
+    let features = ClientFeatures::from_capabilities(&capabilities);
+    assert!(features.supports_method(REQUEST__InlayHint));
+    assert!(!features.supports_method(REQUEST__PrepareRename));
+    assert!(!features.supports_method(REQUEST__PrepareCallHierarchy));
+
+    let capabilities_ext = ServerCapabilitiesExt {
+        inlay_hint_provider : Some(InlayHintOptions { resolve_provider : Some(true) }),
+        call_hierarchy_provider : Some(true),
+        .. ServerCapabilitiesExt::default()
+    };
+    let mut server_capabilities = serde_json::to_value(&ServerCapabilities::default());
+    capabilities_ext.add_negotiated_to(&mut server_capabilities, &features);
+    assert_eq!(serde_json::to_string(&server_capabilities).unwrap(),
+        r#"{"inlayHintProvider":{"resolveProvider":true},"positionEncoding":"utf-16"}"#);
+
+    let capabilities = ObjectBuilder::new()
+        .insert_object("general", |builder| builder
+            .insert_array("positionEncodings", |builder| builder.push("utf-16").push("utf-32").push("utf-8"))
+        )
+        .build();
+    let features = ClientFeatures::from_capabilities(&capabilities);
+    assert_eq!(features.position_encoding(), PositionEncodingKind::UTF8);
+    assert_eq!(ClientFeatures::base_protocol().position_encoding(), PositionEncodingKind::UTF16);
+}
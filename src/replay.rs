@@ -0,0 +1,352 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Replay of recorded LSP sessions.
+
+A `Replay` takes a trace recorded with `trace::TraceRecorder` (on the server or on the client side),
+feeds the client-to-server messages of the trace to a `LanguageServerHandling`, using `LSPEndpoint::run_server`,
+and compares the responses of the server with the recorded ones. Example, in a test:
+
+```ignore
+let replay = Replay::from_trace_file("tests/traces/hover.log", RecordingSide::Server).unwrap()
+    .ignore_field("timestamp");
+replay.run(|endpoint| MyLanguageServer::new(endpoint)).assert_success();
+```
+
+Only responses are compared, not the notifications or requests sent by the server.
+Messages are replayed without waiting for the responses of previous requests, so servers that answer requests
+asynchronously may answer them in a different order than the recorded one. Responses are matched by request id,
+so this does not affect the comparison. The responses the client sent to server requests are replayed
+with their recorded ids, which match only if the server numbers its requests in the same way as in the recording.
+
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::vec;
+
+use util::core::*;
+
+use jsonrpc::*;
+use jsonrpc::service_util::MessageReader;
+use jsonrpc::service_util::MessageWriter;
+
+use serde_json;
+use serde_json::Value;
+
+use lsp::*;
+use trace::*;
+
+/* -----------------  ----------------- */
+
+/// The side of the session on which a trace was recorded.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum RecordingSide {
+    Server,
+    Client,
+}
+
+/// A difference between the responses of the server and the recorded responses.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Divergence {
+    /// The server did not respond to a request that has a recorded response.
+    MissingResponse { method: String, expected: Value },
+    /// The server responded to a request that has no recorded response.
+    UnexpectedResponse { actual: Value },
+    /// The server response differs from the recorded response.
+    DifferentResponse { method: String, expected: Value, actual: Value },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Divergence::MissingResponse { ref method, ref expected } => {
+                write!(f, "Missing response to `{}`, expected:\n  {}", method, expected)
+            }
+            Divergence::UnexpectedResponse { ref actual } => {
+                write!(f, "Unexpected response:\n  {}", actual)
+            }
+            Divergence::DifferentResponse { ref method, ref expected, ref actual } => {
+                write!(f, "Different response to `{}`, expected:\n  {}\nactual:\n  {}", method, expected, actual)
+            }
+        }
+    }
+}
+
+/// The result of a replay.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReplayReport {
+    /// The number of recorded responses compared.
+    pub compared_responses: usize,
+    pub divergences: Vec<Divergence>,
+}
+
+impl ReplayReport {
+
+    pub fn is_success(&self) -> bool {
+        self.divergences.is_empty()
+    }
+
+    /// Panic, with the divergences as message, if there are any.
+    pub fn assert_success(&self) {
+        if !self.is_success() {
+            panic!("{}", self);
+        }
+    }
+
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "Replay: {} responses compared, {} divergences",
+            self.compared_responses, self.divergences.len()));
+        for divergence in &self.divergences {
+            try!(write!(f, "\n\n{}", divergence));
+        }
+        Ok(())
+    }
+}
+
+/* -----------------  ----------------- */
+
+/// A recorded session, to replay against a server. See module docs.
+pub struct Replay {
+    pub entries: Vec<TraceEntry>,
+    pub recorded_by: RecordingSide,
+    /// Names of object fields removed from the responses, at any depth, before comparing them.
+    /// Use for fields whose value is expected to change between runs.
+    pub ignored_fields: Vec<String>,
+    /// How long to wait for the responses, once all messages are sent to the server.
+    pub response_timeout: Duration,
+}
+
+impl Replay {
+
+    pub fn new(entries: Vec<TraceEntry>, recorded_by: RecordingSide) -> Replay {
+        Replay {
+            entries : entries,
+            recorded_by : recorded_by,
+            ignored_fields : vec![],
+            response_timeout : Duration::from_secs(10),
+        }
+    }
+
+    pub fn from_trace_file<P : AsRef<Path>>(path: P, recorded_by: RecordingSide) -> GResult<Replay> {
+        let entries = try!(read_trace_entries(io::BufReader::new(try!(File::open(path)))));
+        Ok(Replay::new(entries, recorded_by))
+    }
+
+    pub fn ignore_field(mut self, field_name: &str) -> Replay {
+        self.ignored_fields.push(field_name.to_string());
+        self
+    }
+
+    fn is_client_message(&self, entry: &TraceEntry) -> bool {
+        match (self.recorded_by, entry.direction()) {
+            (RecordingSide::Server, MessageDirection::Received) => true,
+            (RecordingSide::Client, MessageDirection::Sent) => true,
+            _ => false,
+        }
+    }
+
+    /// Replay the session against the server created by `create_server`, with the endpoint it must use
+    /// to send messages to the client.
+    pub fn run<LS, FN>(&self, create_server: FN) -> ReplayReport
+    where
+        LS : LanguageServerHandling + 'static,
+        FN : FnOnce(Endpoint) -> LS,
+    {
+        let mut client_messages = vec![];
+        let mut request_methods = HashMap::new();
+        let mut expected_responses = vec![];
+
+        for entry in &self.entries {
+            let message = &entry.message;
+            let id = message_id(message);
+            let method = message.find("method").and_then(Value::as_str);
+            if self.is_client_message(entry) {
+                if let (Some(method), Some(id)) = (method, id) {
+                    request_methods.insert(id_key(id), method.to_string());
+                }
+                client_messages.push(serde_json::to_string(message).expect("Failed to serialize message"));
+            } else if let (None, Some(id)) = (method, id) {
+                expected_responses.push((id_key(id), message.clone()));
+            }
+        }
+
+        let output = Arc::new(ReplayOutput { state : Mutex::new((vec![], false)), condvar : Condvar::new() });
+
+        let writer_output = output.clone();
+        let endpoint = LSPEndpoint::create_lsp_output(move || ReplayMessageWriter(writer_output));
+        let server = create_server(endpoint.clone());
+
+        let mut reader = ReplayMessageReader {
+            messages : client_messages.into_iter(),
+            output : output.clone(),
+            expected_responses : request_methods.len(),
+            response_timeout : self.response_timeout,
+        };
+        LSPEndpoint::run_server(&mut reader, endpoint.clone(), server);
+        endpoint.request_shutdown();
+
+        let server_messages = output.wait_until(self.response_timeout, |&(_, writer_dropped)| writer_dropped).0;
+        self.compare_responses(request_methods, expected_responses, server_messages)
+    }
+
+    fn compare_responses(
+        &self, request_methods: HashMap<String, String>, expected_responses: Vec<(String, Value)>,
+        server_messages: Vec<Value>,
+    ) -> ReplayReport {
+        let mut actual_responses = HashMap::new();
+        for message in server_messages {
+            if message.find("method").is_some() {
+                continue;
+            }
+            if let Some(id) = message_id(&message).map(id_key) {
+                actual_responses.insert(id, message);
+            }
+        }
+
+        let mut divergences = vec![];
+        let compared_responses = expected_responses.len();
+
+        for (id, mut expected) in expected_responses {
+            let method = request_methods.get(&id).cloned().unwrap_or_else(|| "<unknown>".to_string());
+            match actual_responses.remove(&id) {
+                None => {
+                    divergences.push(Divergence::MissingResponse { method : method, expected : expected });
+                }
+                Some(mut actual) => {
+                    remove_fields(&mut expected, &self.ignored_fields);
+                    remove_fields(&mut actual, &self.ignored_fields);
+                    if actual != expected {
+                        divergences.push(Divergence::DifferentResponse {
+                            method : method, expected : expected, actual : actual
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut unexpected : Vec<_> = actual_responses.into_iter().collect();
+        unexpected.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, actual) in unexpected {
+            divergences.push(Divergence::UnexpectedResponse { actual : actual });
+        }
+
+        ReplayReport { compared_responses : compared_responses, divergences : divergences }
+    }
+
+}
+
+/// The id of given message, if it has a non-null one.
+fn message_id(message: &Value) -> Option<&Value> {
+    message.find("id").and_then(|id| if id.is_null() { None } else { Some(id) })
+}
+
+/// A key for a request id, so that ids `1` and `"1"` are different.
+fn id_key(id: &Value) -> String {
+    serde_json::to_string(id).expect("Failed to serialize id")
+}
+
+fn remove_fields(value: &mut Value, field_names: &[String]) {
+    match *value {
+        Value::Object(ref mut object) => {
+            for field_name in field_names {
+                object.remove(field_name);
+            }
+            for (_, field_value) in object.iter_mut() {
+                remove_fields(field_value, field_names);
+            }
+        }
+        Value::Array(ref mut array) => {
+            for element in array.iter_mut() {
+                remove_fields(element, field_names);
+            }
+        }
+        _ => {}
+    }
+}
+
+/* ----------------- Replay transport ----------------- */
+
+/// The messages written by the server, and whether the writer was dropped (the endpoint output has ended).
+struct ReplayOutput {
+    state: Mutex<(Vec<Value>, bool)>,
+    condvar: Condvar,
+}
+
+impl ReplayOutput {
+
+    fn wait_until<FN>(&self, timeout: Duration, condition: FN) -> (Vec<Value>, bool)
+    where
+        FN : Fn(&(Vec<Value>, bool)) -> bool
+    {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            if condition(&state) || now >= deadline {
+                return state.clone();
+            }
+            state = self.condvar.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+}
+
+struct ReplayMessageWriter(Arc<ReplayOutput>);
+
+impl MessageWriter for ReplayMessageWriter {
+    fn write_message(&mut self, msg: &str) -> GResult<()> {
+        let message = try!(serde_json::from_str::<Value>(msg));
+        self.0.state.lock().unwrap().0.push(message);
+        self.0.condvar.notify_all();
+        Ok(())
+    }
+}
+
+impl Drop for ReplayMessageWriter {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().1 = true;
+        self.0.condvar.notify_all();
+    }
+}
+
+/// Reads the recorded client messages. Once these are exhausted, waits for the responses to the replayed
+/// requests before reporting the end of the stream, since that shuts down the endpoint.
+struct ReplayMessageReader {
+    messages: vec::IntoIter<String>,
+    output: Arc<ReplayOutput>,
+    expected_responses: usize,
+    response_timeout: Duration,
+}
+
+impl MessageReader for ReplayMessageReader {
+    fn read_next(&mut self) -> GResult<String> {
+        if let Some(message) = self.messages.next() {
+            return Ok(message);
+        }
+
+        let expected_responses = self.expected_responses;
+        self.output.wait_until(self.response_timeout, |&(ref messages, writer_dropped)| {
+            let responses = messages.iter().filter(|message| message.find("method").is_none()).count();
+            writer_dropped || responses >= expected_responses
+        });
+        Err("End of replayed messages.".into())
+    }
+}
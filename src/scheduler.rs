@@ -0,0 +1,270 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Concurrent handling of server requests.
+
+`SchedulingRequestHandler` classifies each incoming method as a read or a write:
+
+* Writes (all notifications, plus `initialize`, `shutdown`, and any method not known to be a read) are handled
+  on the message read loop thread, one at a time and in the order they arrive, so document changes are applied
+  strictly in order.
+* Reads (the requests that only query the server state, such as `hover`, `completion` or the symbol requests)
+  are handled on a `ThreadPool`, each on a snapshot of the server
+  (a clone of the `LanguageServerHandling`) taken when the request arrives. A read thus sees all the writes
+  received before it, and never a write that is only partially applied.
+
+For this to be efficient, the server state should be cheap to clone, for example by holding it in `Arc`s
+and having writes replace the state rather than mutate it in place.
+
+*/
+
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+
+use util::core::*;
+
+use jsonrpc::*;
+use jsonrpc::jsonrpc_request::RequestParams;
+
+use ls_types::*;
+use ls_types_ext::*;
+use lsp::*;
+
+/* ----------------- Thread pool ----------------- */
+
+type Job = Box<FnMut() + Send>;
+
+/// A fixed size pool of worker threads. A job that panics is logged, and does not terminate its worker.
+/// When dropped, the jobs already submitted are run, and the worker threads are joined.
+pub struct ThreadPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl ThreadPool {
+
+    pub fn new(size: usize) -> ThreadPool {
+        assert!(size > 0);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = newArcMutex(receiver);
+
+        let workers = (0..size).map(|_| {
+            let receiver : Arc<Mutex<mpsc::Receiver<Job>>> = receiver.clone();
+            thread::spawn(move || {
+                loop {
+                    let job = receiver.lock().unwrap().recv();
+                    let mut job = match job {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    // A panicking job must not terminate the worker
+                    if panic::catch_unwind(AssertUnwindSafe(|| job())).is_err() {
+                        error!("Job panicked in worker thread");
+                    }
+                }
+            })
+        }).collect();
+
+        ThreadPool { sender : Some(sender), workers : workers }
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn execute<FN>(&self, job: FN)
+    where
+        FN : FnOnce() + Send + 'static
+    {
+        let mut job = Some(job);
+        let job : Job = new(move || {
+            if let Some(job) = job.take() {
+                job()
+            }
+        });
+        if self.sender.as_ref().unwrap().send(job).is_err() {
+            error!("Worker threads have terminated, dropping job");
+        }
+    }
+
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            if let Err(_) = worker.join() {
+                error!("Worker thread panicked");
+            }
+        }
+    }
+}
+
+/* ----------------- Scheduling ----------------- */
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MethodKind {
+    /// The method only reads the server state. It can run concurrently with other reads.
+    Read,
+    /// The method modifies the server state. It runs alone, in message order.
+    Write,
+}
+
+/// The default classification: the requests that only query the server state are reads, all other methods
+/// (including custom and unknown methods) are writes.
+pub fn default_method_kind(method_name: &str) -> MethodKind {
+    match method_name {
+        REQUEST__Completion |
+        REQUEST__ResolveCompletionItem |
+        REQUEST__Hover |
+        REQUEST__SignatureHelp |
+        REQUEST__GotoDefinition |
+        REQUEST__References |
+        REQUEST__DocumentHighlight |
+        REQUEST__DocumentSymbols |
+        REQUEST__WorkspaceSymbols |
+        REQUEST__CodeAction |
+        REQUEST__CodeLens |
+        REQUEST__CodeLensResolve |
+        REQUEST__DocumentLink |
+        REQUEST__DocumentLinkResolve |
+        REQUEST__Formatting |
+        REQUEST__RangeFormatting |
+        REQUEST__OnTypeFormatting |
+        REQUEST__Rename |
+        REQUEST__PrepareCallHierarchy |
+        REQUEST__CallHierarchyIncomingCalls |
+        REQUEST__CallHierarchyOutgoingCalls |
+        REQUEST__PrepareTypeHierarchy |
+        REQUEST__TypeHierarchySupertypes |
+        REQUEST__TypeHierarchySubtypes |
+        REQUEST__InlayHint |
+        REQUEST__InlayHintResolve |
+        REQUEST__InlineValue |
+        REQUEST__DocumentDiagnostic |
+        REQUEST__WorkspaceDiagnostic |
+        REQUEST__PrepareRename |
+        REQUEST__LinkedEditingRange => MethodKind::Read,
+        _ => MethodKind::Write,
+    }
+}
+
+/// A server request handler that runs read requests concurrently, on a thread pool. See module docs.
+///
+/// Use with `LSPEndpoint::run_endpoint_loop`.
+pub struct SchedulingRequestHandler<LS> {
+    pub handler: ServerRequestHandler<LS>,
+    /// How the panics of handlers are reported. A panicking request is answered with an error.
+    pub panics: HandlerPanics,
+    pub pool: ThreadPool,
+    /// The classification of methods. `default_method_kind` unless changed.
+    pub method_kind: fn(&str) -> MethodKind,
+}
+
+impl<LS : LanguageServerHandling + Clone + Send + 'static> SchedulingRequestHandler<LS> {
+
+    pub fn new(ls: LS, pool_size: usize) -> SchedulingRequestHandler<LS> {
+        SchedulingRequestHandler {
+            handler : ServerRequestHandler(ls),
+            panics : HandlerPanics::default(),
+            pool : ThreadPool::new(pool_size),
+            method_kind : default_method_kind,
+        }
+    }
+
+}
+
+impl<LS : LanguageServerHandling + Clone + Send + 'static> RequestHandler for SchedulingRequestHandler<LS> {
+
+    fn handle_request(
+        &mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable
+    ) {
+        match (self.method_kind)(method_name) {
+            MethodKind::Write => {
+                handle_request_catching_panics(&mut self.handler, &self.panics, method_name, params, completable)
+            }
+            MethodKind::Read => {
+                let mut snapshot = ServerRequestHandler(self.handler.0.clone());
+                let panics = self.panics.clone();
+                let method_name = String::from(method_name);
+                self.pool.execute(move || {
+                    let method_name = &method_name;
+                    handle_request_catching_panics(&mut snapshot, &panics, method_name, params, completable)
+                });
+            }
+        }
+    }
+
+}
+
+
+#[test]
+fn test_ThreadPool() {
+    let (sender, receiver) = mpsc::channel();
+    {
+        let pool = ThreadPool::new(3);
+        assert_eq!(pool.size(), 3);
+        for ix in 0..10 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(ix).unwrap());
+        }
+    }
+    drop(sender);
+
+    let mut results : Vec<u32> = receiver.iter().collect();
+    results.sort();
+    assert_eq!(results, (0..10).collect::<Vec<_>>());
+
+    // Workers survive panicking jobs
+    let (sender, receiver) = mpsc::channel();
+    {
+        let pool = ThreadPool::new(1);
+        pool.execute(|| panic!("Test panic"));
+        let sender = sender.clone();
+        pool.execute(move || sender.send("after panic").unwrap());
+    }
+    assert_eq!(receiver.recv().unwrap(), "after panic");
+
+    assert_eq!(default_method_kind(NOTIFICATION__DidChangeTextDocument), MethodKind::Write);
+    assert_eq!(default_method_kind(REQUEST__Hover), MethodKind::Read);
+    assert_eq!(default_method_kind(REQUEST__WillRenameFiles), MethodKind::Write);
+    assert_eq!(default_method_kind("custom/reloadWorkspace"), MethodKind::Write);
+}
+
+#[test]
+fn test_HandlerPanics_in_pool() {
+    use jsonrpc::jsonrpc_response::Response;
+
+    struct PanickingHandler;
+
+    impl RequestHandler for PanickingHandler {
+        fn handle_request(&mut self, _: &str, _: RequestParams, _: ResponseCompletable) {
+            panic!("Test panic");
+        }
+    }
+
+    // The panics of pool jobs are counted by the clones of the same HandlerPanics, as for snapshots
+    let panics = HandlerPanics::default();
+    {
+        let pool = ThreadPool::new(2);
+        for _ in 0..3 {
+            let panics = panics.clone();
+            pool.execute(move || {
+                let completable = ResponseCompletable::new(None, new(|_: Option<Response>| {}));
+                handle_request_catching_panics(&mut PanickingHandler, &panics, "test/panic", RequestParams::None,
+                    completable)
+            });
+        }
+    }
+    assert_eq!(panics.count(), 3);
+}
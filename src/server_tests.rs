@@ -5,37 +5,24 @@ use lsp::*;
 use jsonrpc::method_types::MethodError;
 use jsonrpc::*;
 use ls_types::*;
+use ls_types_ext::*;
 
 use jsonrpc::json_util::JsonObject;
+use jsonrpc::jsonrpc_common::error_JSON_RPC_MethodNotFound;
+use jsonrpc::jsonrpc_request::RequestParams;
 use serde_json::Value;
 
-use std::io;
 use std::thread;
-use std::net::TcpListener;
-use std::net::TcpStream;
 
 
 #[test]
 pub fn test_run_lsp_server() {
+    use memory_transport::connect_in_process;
     
-    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
-    let local_addr = listener.local_addr().unwrap();
-    
-    let server_listener = thread::spawn(|| {
-        tcp_server(listener)
-    });
-    
-    let stream = TcpStream::connect(local_addr).unwrap();
-    let out_stream = stream.try_clone().expect("Failed to clone stream");
-    let mut endpoint = LSPEndpoint::create_lsp_output_with_output_stream(|| { out_stream });
-    
-    let ls_client = TestsLanguageClient { counter: 0, endpoint : endpoint.clone() };
-    
-    let client_handler = thread::spawn(|| {
-        let mut input = io::BufReader::new(stream);
-        let endpoint = ls_client.endpoint.clone();
-        LSPEndpoint::run_client_from_input(&mut input, endpoint, ls_client);
-    });
+    let mut connection = connect_in_process(
+        |endpoint| TestsLanguageServer { counter : 0, endpoint : endpoint },
+        |endpoint| TestsLanguageClient { counter : 0, endpoint : endpoint },
+    );
     
     let init_params = InitializeParams { 
         process_id: None, 
@@ -44,45 +31,219 @@ pub fn test_run_lsp_server() {
         capabilities: Value::Object(JsonObject::new()),
     };
     
-    // Create an rpc handle to the server methods
-    let mut server_handle = server_rpc_handle(&mut endpoint);
+    {
+        // Create an rpc handle to the server methods
+        let mut server_handle = connection.server_rpc();
+        
+        server_handle.initialize(init_params).unwrap();
+        
+        server_handle.shutdown().unwrap();
+        
+        server_handle.exit().unwrap();
+    }
+    
+    connection.shutdown_and_join();
+}
+
+#[test]
+fn test_SchedulingRequestHandler() {
+    use std::sync::mpsc;
+    use jsonrpc::jsonrpc_common::Id;
+    use jsonrpc::jsonrpc_request::RequestParams;
+    use jsonrpc::jsonrpc_response::Response;
+    use jsonrpc::jsonrpc_response::ResponseResult;
+    use scheduler::SchedulingRequestHandler;
+    
+    let endpoint = LSPEndpoint::create_lsp_output_with_output_stream(|| Vec::<u8>::new());
+    let ls = TestsLanguageServer { counter : 0, endpoint : endpoint.clone() };
+    let mut handler = SchedulingRequestHandler::new(ls, 2);
+    
+    let (sender, receiver) = mpsc::channel();
+    let completable = ResponseCompletable::new(Some(Id::Number(1)), Box::new(move |response: Option<Response>| {
+        sender.send(response).unwrap();
+    }));
+    
+    let params = TextDocumentPositionParams::new(
+        TextDocumentIdentifier::new("file:///foo.rs".parse().unwrap()), Position::new(0, 0));
+    let params = match ::serde_json::to_value(&params) {
+        Value::Object(object) => RequestParams::Object(object),
+        _ => panic!("Expected an object"),
+    };
+    handler.handle_request(REQUEST__Hover, params, completable);
+    
+    let response = receiver.recv().unwrap().unwrap();
+    match response.result_or_error {
+        ResponseResult::Result(hover) => assert!(hover.find("contents").is_some()),
+        ResponseResult::Error(error) => panic!("Unexpected error: {}", error.message),
+    }
+    
+    drop(handler);
+    endpoint.request_shutdown();
+}
+
+#[test]
+fn test_Replay() {
+    use replay::*;
+    use trace::*;
+    use trace::MessageDirection::*;
     
-    server_handle.initialize(init_params).unwrap();
+    let hover = Hover { contents : vec![MarkedString::String("hover_text".into())], range : None };
+    let hover_response = format!(r#"{{"jsonrpc":"2.0","id":2,"result":{}}}"#, ::serde_json::to_string(&hover).unwrap());
     
-    server_handle.shutdown().unwrap();
+    let entries = |hover_response: &str| vec![
+        TraceEntry::new(Received, r#"{"jsonrpc":"2.0","id":1,"method":"initialize",
+            "params":{"processId":null,"rootPath":null,"initializationOptions":null,"capabilities":{}}}"#),
+        TraceEntry::new(Sent, r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"hoverProvider":true}}}"#),
+        TraceEntry::new(Received, r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":
+            {"uri":"file:///foo.rs","languageId":"rust","version":1,"text":""}}}"#),
+        TraceEntry::new(Received, r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/hover",
+            "params":{"textDocument":{"uri":"file:///foo.rs"},"position":{"line":0,"character":0}}}"#),
+        TraceEntry::new(Sent, r#"{"jsonrpc":"2.0","method":"telemetry/event","params":null}"#),
+        TraceEntry::new(Sent, hover_response),
+    ];
+    fn create_server(endpoint: Endpoint) -> TestsLanguageServer {
+        TestsLanguageServer { counter : 0, endpoint : endpoint }
+    }
     
-    server_handle.exit().unwrap();
+    // The capabilities differ from the recorded ones, but are ignored
+    let report = Replay::new(entries(&hover_response), RecordingSide::Server)
+        .ignore_field("capabilities")
+        .run(create_server);
+    report.assert_success();
+    assert_eq!(report.compared_responses, 2);
     
-    client_handler.join().unwrap();
-    server_listener.join().unwrap();
+    let different_hover = hover_response.replace("hover_text", "other_text");
+    let report = Replay::new(entries(&different_hover), RecordingSide::Server)
+        .ignore_field("capabilities")
+        .run(create_server);
+    assert_eq!(report.divergences.len(), 1);
+    match report.divergences[0] {
+        Divergence::DifferentResponse { ref method, .. } => assert_eq!(method, "textDocument/hover"),
+        ref divergence => panic!("Unexpected divergence: {}", divergence),
+    }
 }
 
-fn tcp_server(listener: TcpListener) {
+#[test]
+fn test_ConformanceKit() {
+    use conformance::*;
     
-    for stream in listener.incoming() {
-        let stream = stream.expect("Failed to open incoming stream");
-        let conn_handler = thread::spawn(move|| {
-            handle_connection(stream)
-        });
-        
-        // Only listen to first connection, so that this example can be run as a test
-        conn_handler.join().unwrap();
-        break; 
+    let mut kit = ConformanceKit::new();
+    kit.quiet_period = ::std::time::Duration::from_millis(50);
+    let report = kit.run(|endpoint| TestsLanguageServer { counter : 0, endpoint : endpoint });
+    
+    let outcome = |check: &str| report.result(check).expect(check).outcome.clone();
+    assert_eq!(outcome("initialize returns the server capabilities"), CheckOutcome::Passed);
+    assert_eq!(outcome("an unknown request fails with MethodNotFound"), CheckOutcome::Passed);
+    assert_eq!(outcome("shutdown returns a null result"), CheckOutcome::Passed);
+    assert_eq!(outcome("exit ends the message loop"), CheckOutcome::Passed);
+    // No capabilities are advertised
+    match outcome("hoverProvider returns a well-formed `textDocument/hover` result") {
+        CheckOutcome::Skipped(_) => {}
+        outcome => panic!("Unexpected outcome: {:?}", outcome),
+    }
+    // TestsLanguageServer answers requests before initialize
+    match outcome("a request before initialize fails with ServerNotInitialized") {
+        CheckOutcome::Failed(_) => {}
+        outcome => panic!("Unexpected outcome: {:?}", outcome),
     }
+    assert!(!report.is_success());
+    assert!(report.to_string().contains("[FAIL] lifecycle: a request before initialize"));
+}
+
+#[test]
+fn test_default_handler_methods() {
+    use std::sync::mpsc;
+    use jsonrpc::jsonrpc_common::*;
+    use jsonrpc::jsonrpc_request::RequestParams;
+    use jsonrpc::jsonrpc_response::*;
+    use serde_json;
+    use util::core::new;
+    
+    let endpoint = LSPEndpoint::create_lsp_output_with_output_stream(|| Vec::<u8>::new());
+    let mut handler = ServerRequestHandler(TestsLanguageServer { counter : 0, endpoint : endpoint.clone() });
+    
+    let (sender, receiver) = mpsc::channel();
+    let completable = ResponseCompletable::new(Some(Id::Number(1)), new(move |response: Option<Response>| {
+        sender.send(response.map(|response| response.result_or_error)).unwrap();
+    }));
+    let params = WorkspaceDiagnosticParams { identifier : None, previous_result_ids : vec![] };
+    let params = match serde_json::to_value(&params) {
+        Value::Object(object) => RequestParams::Object(object),
+        _ => panic!("Expected an object"),
+    };
+    // Not implemented by TestsLanguageServer
+    handler.handle_request(REQUEST__WorkspaceDiagnostic, params, completable);
+    assert_eq!(receiver.recv().unwrap(), Some(ResponseResult::Error(error_JSON_RPC_MethodNotFound())));
     
-    drop(listener);
+    endpoint.request_shutdown();
 }
 
-fn handle_connection(stream: TcpStream) {
-    let out_stream = stream.try_clone().expect("Failed to clone stream");
-    let endpoint = LSPEndpoint::create_lsp_output_with_output_stream(|| { out_stream });
+#[test]
+fn test_handler_panics() {
+    use std::sync::mpsc;
+    use jsonrpc::jsonrpc_common::*;
+    use jsonrpc::jsonrpc_response::*;
+    use serde_json;
+    use util::core::*;
+    use memory_transport::test_messages::ChannelMessageWriter;
     
+    let (sender, receiver) = mpsc::channel();
+    let endpoint = LSPEndpoint::create_lsp_output(move || ChannelMessageWriter(sender));
     let ls = TestsLanguageServer { counter : 0, endpoint : endpoint.clone() };
+    let mut handler = CatchPanicsHandler::new(ServerRequestHandler(ls)).report_panics_to(endpoint.clone(), Some(3));
+    let notification = || ResponseCompletable::new(None, new(|response: Option<Response>| {
+        assert!(response.is_none());
+    }));
+    let (response_sender, responses) = mpsc::channel();
+    let request = |id: u64| {
+        let response_sender = response_sender.clone();
+        ResponseCompletable::new(Some(Id::Number(id)), new(move |response: Option<Response>| {
+            response_sender.send(response.unwrap()).unwrap();
+        }))
+    };
+    let log_message = || -> String {
+        let message : Value = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+        assert_eq!(message.find("method").and_then(Value::as_str), Some(NOTIFICATION__LogMessage));
+        message.find_path(&["params", "message"]).and_then(Value::as_str).unwrap().to_string()
+    };
+    
+    // The panic is caught, and reported to the client
+    handler.handle_request("test/panic", RequestParams::None, notification());
+    assert_eq!(handler.panics.count(), 1);
+    let text = log_message();
+    assert!(text.contains("`test/panic`") && text.contains("Test panic"));
+    assert!(!endpoint.is_shutdown());
     
-    let mut input = io::BufReader::new(stream);
-    LSPEndpoint::run_server_from_input(&mut input, endpoint, ls);
+    // A panicking request is answered with an error, and the next requests are still handled
+    let params = TextDocumentPositionParams::new(
+        TextDocumentIdentifier::new("file:///panic.rs".parse().unwrap()), Position::new(0, 0));
+    let params = match serde_json::to_value(&params) {
+        Value::Object(object) => RequestParams::Object(object),
+        _ => panic!("Expected an object"),
+    };
+    handler.handle_request(REQUEST__Hover, params, request(1));
+    let response = responses.recv().unwrap();
+    assert_eq!(response.id, Id::Number(1));
+    match response.result_or_error {
+        ResponseResult::Error(error) => {
+            assert_eq!(error.code, error_JSON_RPC_InternalError().code);
+            assert!(error.message.contains("Test hover panic"));
+        }
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    assert_eq!(handler.panics.count(), 2);
+    assert!(log_message().contains(REQUEST__Hover));
+    
+    handler.handle_request(REQUEST__Shutdown, RequestParams::None, request(2));
+    assert_eq!(responses.recv().unwrap().result_or_error, ResponseResult::Result(Value::Null));
+    
+    // The endpoint is shut down after 3 panics
+    handler.handle_request("test/panic", RequestParams::None, notification());
+    assert_eq!(handler.panics.count(), 3);
+    assert!(endpoint.is_shutdown());
 }
 
+#[derive(Clone)]
 pub struct TestsLanguageServer {
     counter: u32,
     endpoint: Endpoint,
@@ -125,7 +286,10 @@ impl LanguageServerHandling for TestsLanguageServer {
     fn resolve_completion_item(&mut self, _: CompletionItem, completable: LSCompletable<CompletionItem>) {
         completable.complete(Err(Self::error_not_available(())));
     }
-    fn hover(&mut self, _: TextDocumentPositionParams, completable: LSCompletable<Hover>) {
+    fn hover(&mut self, params: TextDocumentPositionParams, completable: LSCompletable<Hover>) {
+        if params.text_document.uri.as_str() == "file:///panic.rs" {
+            panic!("Test hover panic");
+        }
         let mut endpoint = self.endpoint.clone();
         thread::spawn(move || {
             client_rpc_handle(&mut endpoint).telemetry_event(Value::Null)
@@ -182,6 +346,13 @@ impl LanguageServerHandling for TestsLanguageServer {
     fn rename(&mut self, _: RenameParams, completable: LSCompletable<WorkspaceEdit>) {
         completable.complete(Err(Self::error_not_available(())));
     }
+    
+    fn handle_other_method(&mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable) {
+        match method_name {
+            "test/panic" => completable.handle_notification_with(params, |_: ()| panic!("Test panic")),
+            _ => completable.complete_with_error(error_JSON_RPC_MethodNotFound()),
+        }
+    }
 }
 
 /* -----------------  ----------------- */
@@ -216,4 +387,32 @@ impl LanguageClientHandling for TestsLanguageClient {
         
     }
     
+    fn inlay_hint_refresh(&mut self, params: (), completable: LSCompletable<()>) {
+        completable.complete(Ok(()));
+    }
+    
+    fn inline_value_refresh(&mut self, params: (), completable: LSCompletable<()>) {
+        completable.complete(Ok(()));
+    }
+    
+    fn diagnostic_refresh(&mut self, params: (), completable: LSCompletable<()>) {
+        completable.complete(Ok(()));
+    }
+    
+    fn workspace_folders(&mut self, params: (), completable: LSCompletable<Option<Vec<WorkspaceFolder>>>) {
+        completable.complete(Ok(None));
+    }
+    
+    fn configuration(&mut self, params: ConfigurationParams, completable: LSCompletable<Vec<Value>>) {
+        completable.complete(Ok(params.items.iter().map(|_| Value::Null).collect()));
+    }
+    
+    fn show_document(&mut self, params: ShowDocumentParams, completable: LSCompletable<ShowDocumentResult>) {
+        completable.complete(Ok(ShowDocumentResult { success : false }));
+    }
+    
+    fn work_done_progress_create(&mut self, params: WorkDoneProgressCreateParams, completable: LSCompletable<()>) {
+        completable.complete(Ok(()));
+    }
+    
 }
\ No newline at end of file
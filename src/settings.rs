@@ -0,0 +1,254 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+
+use util::core::*;
+
+use jsonrpc::futures;
+use jsonrpc::futures::Future;
+use jsonrpc::futures::BoxFuture;
+use jsonrpc::method_types::RequestResult;
+
+use serde;
+use serde_json;
+use serde_json::Value;
+
+use url::Url;
+
+use ls_types::*;
+use ls_types_ext::*;
+use lsp::LspClientRpc;
+
+/* -----------------  ----------------- */
+
+/// A cache of client configuration settings, for use by a language server.
+///
+/// Sections are requested from the client with `workspace/configuration` (see `fetch`), and
+/// can then be obtained as user-defined serde types with `get`. On `workspace/didChangeConfiguration`
+/// all previously fetched sections are fetched again. Settings pushed in `didChangeConfiguration`
+/// itself are also kept, as a fallback for clients that do not support `workspace/configuration`.
+///
+/// This type has handle semantics: it can be cloned freely and used from multiple threads.
+#[derive(Clone)]
+pub struct SettingsCache {
+    inner: Arc<Mutex<SettingsCacheData>>,
+    /// The queue of the thread that waits for the background fetches, started on the first one.
+    background_fetches: Arc<Mutex<Option<mpsc::Sender<FetchFuture>>>>,
+}
+
+type FetchFuture = BoxFuture<(), futures::Canceled>;
+
+struct SettingsCacheData {
+    sections: HashMap<ConfigurationItem, Value>,
+    pushed_settings: Value,
+}
+
+impl SettingsCache {
+
+    pub fn new() -> SettingsCache {
+        let data = SettingsCacheData { sections : HashMap::new(), pushed_settings : Value::Null };
+        SettingsCache { inner : newArcMutex(data), background_fetches : newArcMutex(None) }
+    }
+
+    /// Request given configuration items from the client, storing them in the cache once the client responds.
+    ///
+    /// The returned future completes once the cache has been updated.
+    /// Note: do not wait on it in the message read loop thread, since that is where the response is read.
+    pub fn fetch(&self, client: &mut LspClientRpc, items: Vec<ConfigurationItem>)
+        -> GResult<FetchFuture>
+    {
+        let future = try!(client.configuration(ConfigurationParams { items : items.clone() }));
+        let inner = self.inner.clone();
+
+        let future = future.map(move |result| {
+            match result {
+                RequestResult::MethodResult(Ok(values)) => {
+                    let mut data = inner.lock().unwrap();
+                    for (item, value) in items.into_iter().zip(values) {
+                        data.sections.insert(item, value);
+                    }
+                }
+                RequestResult::MethodResult(Err(error)) => {
+                    warn!("workspace/configuration request failed: {}", error.message);
+                }
+                RequestResult::RequestError(error) => {
+                    warn!("workspace/configuration request failed: {}", error.message);
+                }
+            }
+        });
+        Ok(future.boxed())
+    }
+
+    /// Handle a `workspace/didChangeConfiguration` notification.
+    /// Previously fetched sections are fetched again in the background.
+    pub fn did_change_configuration(&self, client: &mut LspClientRpc, params: &DidChangeConfigurationParams)
+        -> GResult<()>
+    {
+        let items : Vec<ConfigurationItem> = {
+            let mut data = self.inner.lock().unwrap();
+            data.pushed_settings = params.settings.clone();
+            data.sections.keys().cloned().collect()
+        };
+
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        let future = try!(self.fetch(client, items));
+        self.fetch_in_background(future);
+        Ok(())
+    }
+
+    /// Have the background thread wait for given fetch, so that the cache is updated when the client responds.
+    fn fetch_in_background(&self, future: FetchFuture) {
+        let mut sender = self.background_fetches.lock().unwrap();
+        if sender.is_none() {
+            let (new_sender, receiver) = mpsc::channel::<FetchFuture>();
+            // The thread ends once all clones of the cache are dropped
+            thread::spawn(move || {
+                for future in receiver {
+                    let _ = future.wait();
+                }
+            });
+            *sender = Some(new_sender);
+        }
+        if let Some(ref sender) = *sender {
+            if let Err(_) = sender.send(future) {
+                error!("Settings fetch thread has terminated");
+            }
+        }
+    }
+
+    /// Get the raw value of given section, for given scope.
+    /// If the section was not fetched, falls back to the last settings pushed by the client.
+    pub fn get_value(&self, scope_uri: Option<&Url>, section: Option<&str>) -> Option<Value> {
+        let data = self.inner.lock().unwrap();
+
+        let item = ConfigurationItem::new(scope_uri.cloned(), section.map(String::from));
+        if let Some(value) = data.sections.get(&item) {
+            return Some(value.clone());
+        }
+
+        let pushed = match section {
+            Some(section) => {
+                let path : Vec<&str> = section.split('.').collect();
+                data.pushed_settings.find_path(&path)
+            }
+            None => Some(&data.pushed_settings),
+        };
+        pushed.and_then(|value| if value.is_null() { None } else { Some(value.clone()) })
+    }
+
+    /// Get given section, for given scope, deserialized as `SETTINGS`.
+    pub fn get<SETTINGS : serde::Deserialize>(&self, scope_uri: Option<&Url>, section: Option<&str>)
+        -> Option<Result<SETTINGS, serde_json::Error>>
+    {
+        self.get_value(scope_uri, section).map(serde_json::from_value)
+    }
+
+}
+
+
+#[test]
+fn test_SettingsCache() {
+    use serde_json::builder::ObjectBuilder;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct FormatSettings {
+        width: u64,
+    }
+
+    let cache = SettingsCache::new();
+    assert!(cache.get::<FormatSettings>(None, Some("rust.format")).is_none());
+
+    let mut endpoint = ::lsp::LSPEndpoint::create_lsp_output_with_output_stream(|| Vec::<u8>::new());
+
+    let settings = ObjectBuilder::new()
+        .insert_object("rust", |builder| builder.insert_object("format", |builder| builder.insert("width", 100)))
+        .build();
+    let params = DidChangeConfigurationParams { settings : settings };
+    cache.did_change_configuration(&mut ::lsp::client_rpc_handle(&mut endpoint), &params).unwrap();
+
+    let format_settings = cache.get::<FormatSettings>(None, Some("rust.format")).unwrap().unwrap();
+    assert_eq!(format_settings, FormatSettings { width : 100 });
+    assert!(cache.get::<FormatSettings>(None, Some("rust")).unwrap().is_err());
+    assert!(cache.get_value(None, Some("other")).is_none());
+
+    endpoint.request_shutdown();
+}
+
+#[test]
+fn test_SettingsCache_fetch() {
+    use std::time::Duration;
+    use std::time::Instant;
+    use serde_json::builder::ObjectBuilder;
+    use jsonrpc::RequestFuture;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct FormatSettings {
+        width: u64,
+    }
+
+    /// Answers `workspace/configuration` with `{"width":<width>}` for every item.
+    struct MockClient {
+        width: u64,
+        requests: Vec<ConfigurationParams>,
+    }
+
+    impl LspClientRpc for MockClient {
+        fn show_message(&mut self, _: ShowMessageParams) -> GResult<()> {
+            Ok(())
+        }
+        fn show_message_request(&mut self, _: ShowMessageRequestParams)
+            -> GResult<RequestFuture<MessageActionItem, ()>>
+        {
+            Err("Not supported".into())
+        }
+        fn log_message(&mut self, _: LogMessageParams) -> GResult<()> {
+            Ok(())
+        }
+        fn telemetry_event(&mut self, _: Value) -> GResult<()> {
+            Ok(())
+        }
+        fn publish_diagnostics(&mut self, _: PublishDiagnosticsParams) -> GResult<()> {
+            Ok(())
+        }
+        fn configuration(&mut self, params: ConfigurationParams) -> GResult<RequestFuture<Vec<Value>, ()>> {
+            let width = self.width;
+            let values = params.items.iter().map(|_| ObjectBuilder::new().insert("width", width).build()).collect();
+            self.requests.push(params);
+            Ok(futures::future::ok(RequestResult::MethodResult(Ok(values))).boxed())
+        }
+    }
+
+    let section = Some("rust.format");
+    let item = ConfigurationItem::new(None, section.map(String::from));
+    let mut client = MockClient { width : 80, requests : vec![] };
+    let cache = SettingsCache::new();
+
+    cache.fetch(&mut client, vec![item.clone()]).unwrap().wait().unwrap();
+    assert_eq!(cache.get::<FormatSettings>(None, section).unwrap().unwrap(), FormatSettings { width : 80 });
+
+    // Fetched sections are fetched again on change
+    client.width = 120;
+    let params = DidChangeConfigurationParams { settings : Value::Null };
+    cache.did_change_configuration(&mut client, &params).unwrap();
+    assert_eq!(client.requests.len(), 2);
+    assert_eq!(client.requests[1].items, vec![item]);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while cache.get::<FormatSettings>(None, section).unwrap().unwrap().width != 120 {
+        assert!(Instant::now() < deadline, "Settings were not fetched again");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
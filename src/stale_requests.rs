@@ -0,0 +1,322 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Dropping of request results computed for an outdated version of a document.
+
+`StaleRequestHandler` wraps another request handler, and tracks the open documents from the
+`didOpen`/`didChange`/`didClose` notifications. Version sensitive requests (those whose result refers
+to positions in a document, see `default_version_sensitive`) that are still pending when their document changes
+are answered with a `ContentModified` error instead of their result.
+
+With `cancel_on_change`, the pending requests are answered as soon as the change arrives, and the result the
+handler later provides is discarded. Handlers can avoid computing such results by checking
+`DocumentVersions::revision`.
+
+*/
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use util::core::*;
+
+use jsonrpc::*;
+use jsonrpc::jsonrpc_common::*;
+use jsonrpc::jsonrpc_request::RequestParams;
+use jsonrpc::jsonrpc_response::Response;
+
+use serde_json;
+use serde_json::Value;
+
+use url::Url;
+
+use ls_types::*;
+use ls_types_ext::*;
+
+/* -----------------  ----------------- */
+
+pub fn error_ContentModified() -> RequestError {
+    RequestError::new(ERROR_CODE__ContentModified, "Content modified.".to_string())
+}
+
+/// The requests whose result refers to positions in the document of their `textDocument` parameter.
+pub fn default_version_sensitive(method_name: &str) -> bool {
+    match method_name {
+        REQUEST__Completion |
+        REQUEST__Hover |
+        REQUEST__SignatureHelp |
+        REQUEST__GotoDefinition |
+        REQUEST__References |
+        REQUEST__DocumentHighlight |
+        REQUEST__DocumentSymbols |
+        REQUEST__CodeAction |
+        REQUEST__CodeLens |
+        REQUEST__Formatting |
+        REQUEST__RangeFormatting |
+        REQUEST__OnTypeFormatting |
+        REQUEST__Rename |
+        REQUEST__PrepareRename |
+        REQUEST__LinkedEditingRange |
+        REQUEST__InlayHint |
+        REQUEST__InlineValue => true,
+        _ => false,
+    }
+}
+
+type PendingCompletable = Arc<Mutex<Option<ResponseCompletable>>>;
+
+struct TrackedDocument {
+    version: Option<u64>,
+    /// Incremented on each change, regardless of the version sent by the client.
+    revision: u64,
+    pending: HashMap<u64, PendingCompletable>,
+}
+
+impl TrackedDocument {
+    fn answer_pending(&mut self) {
+        for (_, pending) in self.pending.drain() {
+            if let Some(completable) = pending.lock().unwrap().take() {
+                completable.complete_with_error(error_ContentModified());
+            }
+        }
+    }
+}
+
+/// The open documents, as tracked by a `StaleRequestHandler`.
+#[derive(Clone)]
+pub struct DocumentVersions {
+    documents: Arc<Mutex<HashMap<Url, TrackedDocument>>>,
+}
+
+impl DocumentVersions {
+
+    /// The current version of given document, as sent by the client.
+    pub fn version(&self, uri: &Url) -> Option<u64> {
+        self.documents.lock().unwrap().get(uri).and_then(|document| document.version)
+    }
+
+    /// The number of changes received for given document since it was opened.
+    /// `None` if the document is not open.
+    pub fn revision(&self, uri: &Url) -> Option<u64> {
+        self.documents.lock().unwrap().get(uri).map(|document| document.revision)
+    }
+
+}
+
+/// A request handler that answers version sensitive requests with `ContentModified` if their
+/// document changes before they complete. See module docs.
+pub struct StaleRequestHandler<HANDLER> {
+    pub handler: HANDLER,
+    /// Answer pending requests as soon as their document changes, rather than when they complete.
+    pub cancel_on_change: bool,
+    /// The classification of version sensitive methods. `default_version_sensitive` unless changed.
+    pub is_version_sensitive: fn(&str) -> bool,
+    versions: DocumentVersions,
+    request_counter: u64,
+}
+
+impl<HANDLER : RequestHandler> StaleRequestHandler<HANDLER> {
+
+    pub fn new(handler: HANDLER, cancel_on_change: bool) -> StaleRequestHandler<HANDLER> {
+        StaleRequestHandler {
+            handler : handler,
+            cancel_on_change : cancel_on_change,
+            is_version_sensitive : default_version_sensitive,
+            versions : DocumentVersions { documents : newArcMutex(HashMap::new()) },
+            request_counter : 0,
+        }
+    }
+
+    pub fn versions(&self) -> DocumentVersions {
+        self.versions.clone()
+    }
+
+    fn track_notification(&mut self, method_name: &str, params: &RequestParams) {
+        // Note: params are only cloned for the tracked notifications
+        match method_name {
+            NOTIFICATION__DidOpenTextDocument => {
+                let params = params.clone().into_value();
+                if let Ok(params) = serde_json::from_value::<DidOpenTextDocumentParams>(params) {
+                    let document = TrackedDocument {
+                        version : params.text_document.version, revision : 0, pending : HashMap::new()
+                    };
+                    self.versions.documents.lock().unwrap().insert(params.text_document.uri, document);
+                }
+            }
+            NOTIFICATION__DidChangeTextDocument => {
+                let params = params.clone().into_value();
+                if let Ok(params) = serde_json::from_value::<DidChangeTextDocumentParams>(params) {
+                    let mut documents = self.versions.documents.lock().unwrap();
+                    if let Some(document) = documents.get_mut(&params.text_document.uri) {
+                        document.version = Some(params.text_document.version);
+                        document.revision += 1;
+                        if self.cancel_on_change {
+                            document.answer_pending();
+                        }
+                    }
+                }
+            }
+            NOTIFICATION__DidCloseTextDocument => {
+                let params = params.clone().into_value();
+                if let Ok(params) = serde_json::from_value::<DidCloseTextDocumentParams>(params) {
+                    let document = self.versions.documents.lock().unwrap().remove(&params.text_document.uri);
+                    if let Some(mut document) = document {
+                        document.answer_pending();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Return a completable to pass on to the handler in place of given `completable`,
+    /// if the request is version sensitive.
+    fn track_request(&mut self, method_name: &str, params: &RequestParams, completable: ResponseCompletable)
+        -> ResponseCompletable
+    {
+        if !(self.is_version_sensitive)(method_name) {
+            return completable;
+        }
+
+        // Read the uri in place, rather than cloning all the params (which can hold a whole document)
+        let uri = match *params {
+            RequestParams::Object(ref object) => object.get("textDocument"),
+            _ => None,
+        };
+        let uri = uri.and_then(|text_document| text_document.find("uri")).and_then(Value::as_str).and_then(|uri| {
+            Url::parse(uri).ok()
+        });
+        let uri = match uri {
+            Some(uri) => uri,
+            None => return completable,
+        };
+
+        let mut documents = self.versions.documents.lock().unwrap();
+        let document = match documents.get_mut(&uri) {
+            Some(document) => document,
+            None => return completable,
+        };
+
+        self.request_counter += 1;
+        let request_key = self.request_counter;
+        let revision = document.revision;
+        let pending : PendingCompletable = newArcMutex(Some(completable));
+        document.pending.insert(request_key, pending.clone());
+
+        let documents = self.versions.documents.clone();
+        ResponseCompletable::new(Some(Id::Null), new(move |response: Option<Response>| {
+            let current_revision = documents.lock().unwrap().get_mut(&uri).map(|document| {
+                document.pending.remove(&request_key);
+                document.revision
+            });
+
+            let completable = pending.lock().unwrap().take();
+            if let Some(completable) = completable {
+                if current_revision == Some(revision) {
+                    completable.complete(response.map(|response| response.result_or_error));
+                } else {
+                    completable.complete_with_error(error_ContentModified());
+                }
+            }
+        }))
+    }
+
+}
+
+impl<HANDLER : RequestHandler> RequestHandler for StaleRequestHandler<HANDLER> {
+
+    fn handle_request(
+        &mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable
+    ) {
+        self.track_notification(method_name, &params);
+        let completable = self.track_request(method_name, &params, completable);
+        self.handler.handle_request(method_name, params, completable);
+    }
+
+}
+
+
+#[test]
+fn test_StaleRequestHandler() {
+    use std::sync::mpsc;
+    use jsonrpc::jsonrpc_response::ResponseResult;
+
+    /// Keeps requests pending until `complete_all`.
+    struct DeferringHandler {
+        pending: Vec<ResponseCompletable>,
+    }
+
+    impl RequestHandler for DeferringHandler {
+        fn handle_request(&mut self, _: &str, _: RequestParams, completable: ResponseCompletable) {
+            self.pending.push(completable);
+        }
+    }
+
+    impl DeferringHandler {
+        fn complete_all(&mut self) {
+            for completable in self.pending.drain(..) {
+                completable.complete(Some(ResponseResult::Result(Value::Bool(true))));
+            }
+        }
+    }
+
+    fn to_params<T: ::serde::Serialize>(params: T) -> RequestParams {
+        match serde_json::to_value(&params) {
+            Value::Object(object) => RequestParams::Object(object),
+            _ => panic!("Expected an object"),
+        }
+    }
+
+    let uri = Url::parse("file:///foo.rs").unwrap();
+    let open_params = to_params(DidOpenTextDocumentParams {
+        text_document : TextDocumentItem::new(uri.clone(), None, Some(1), "".into())
+    });
+    let change_params = to_params(DidChangeTextDocumentParams {
+        text_document : VersionedTextDocumentIdentifier { uri : uri.clone(), version : 2 },
+        content_changes : vec![],
+    });
+    let hover_params = to_params(TextDocumentPositionParams::new(
+        TextDocumentIdentifier::new(uri.clone()), Position::new(0, 0)));
+
+    for &cancel_on_change in &[false, true] {
+        let (sender, receiver) = mpsc::channel();
+        let new_completable = |id| {
+            let sender = sender.clone();
+            ResponseCompletable::new(Some(Id::Number(id)), new(move |response: Option<Response>| {
+                if let Some(response) = response {
+                    sender.send(response).unwrap();
+                }
+            }))
+        };
+
+        let mut handler = StaleRequestHandler::new(DeferringHandler { pending : vec![] }, cancel_on_change);
+        let versions = handler.versions();
+
+        handler.handle_request(NOTIFICATION__DidOpenTextDocument, open_params.clone(), new_completable(0));
+        assert_eq!(versions.version(&uri), Some(1));
+
+        handler.handle_request(REQUEST__Hover, hover_params.clone(), new_completable(1));
+        handler.handler.complete_all();
+        assert_eq!(receiver.recv().unwrap().result_or_error, ResponseResult::Result(Value::Bool(true)));
+
+        handler.handle_request(REQUEST__Hover, hover_params.clone(), new_completable(2));
+        handler.handle_request(NOTIFICATION__DidChangeTextDocument, change_params.clone(), new_completable(0));
+        assert_eq!(versions.version(&uri), Some(2));
+        assert_eq!(versions.revision(&uri), Some(1));
+        if cancel_on_change {
+            assert_eq!(receiver.try_recv().unwrap().result_or_error, ResponseResult::Error(error_ContentModified()));
+            handler.handler.complete_all();
+        } else {
+            assert!(receiver.try_recv().is_err());
+            handler.handler.complete_all();
+            assert_eq!(receiver.recv().unwrap().result_or_error, ResponseResult::Error(error_ContentModified()));
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+}
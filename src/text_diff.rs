@@ -0,0 +1,262 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+
+use ls_types::*;
+use ls_types_ext::PositionEncodingKind;
+use line_index::LineIndex;
+use text_position;
+
+/* -----------------  ----------------- */
+
+/// Compute the edits that turn `old_text` into `new_text`, with positions relative to `old_text`.
+///
+/// Lines are compared first (using the Myers diff algorithm), producing one edit per group of changed lines,
+/// and each edit is then narrowed down to the characters that actually changed.
+/// The result is suitable as the response of a `textDocument/formatting` request.
+pub fn diff_text_edits(old_text: &str, new_text: &str, encoding: PositionEncodingKind) -> Vec<TextEdit> {
+    let old_lines = split_lines(old_text);
+    let new_lines = split_lines(new_text);
+
+    let line_index = LineIndex::new(old_text, encoding);
+    let mut edits = vec![];
+
+    let mut old_offset = 0;
+    let mut hunk : Option<(usize, String)> = None;
+
+    for op in diff_lines(&old_lines, &new_lines) {
+        match op {
+            DiffOp::Equal(line) => {
+                if let Some((hunk_start, hunk_text)) = hunk.take() {
+                    edits.push(narrowed_edit(old_text, hunk_start, old_offset, &hunk_text, &line_index));
+                }
+                old_offset += line.len();
+            }
+            DiffOp::Delete(line) => {
+                if hunk.is_none() {
+                    hunk = Some((old_offset, String::new()));
+                }
+                old_offset += line.len();
+            }
+            DiffOp::Insert(line) => {
+                if hunk.is_none() {
+                    hunk = Some((old_offset, String::new()));
+                }
+                if let Some((_, ref mut hunk_text)) = hunk {
+                    hunk_text.push_str(line);
+                }
+            }
+        }
+    }
+    if let Some((hunk_start, hunk_text)) = hunk.take() {
+        edits.push(narrowed_edit(old_text, hunk_start, old_offset, &hunk_text, &line_index));
+    }
+
+    edits
+}
+
+/// Split given text into lines, each including its line terminator.
+fn split_lines(text: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut line_start = 0;
+    while let Some(next) = text_position::next_line_start(text, line_start) {
+        lines.push(&text[line_start..next]);
+        line_start = next;
+    }
+    if line_start < text.len() {
+        lines.push(&text[line_start..]);
+    }
+    lines
+}
+
+/// Create the edit replacing `old_text[start..end]` with `new_text`, removing the common prefix and suffix.
+fn narrowed_edit(old_text: &str, start: usize, end: usize, new_text: &str, line_index: &LineIndex) -> TextEdit {
+    let old_slice = &old_text[start..end];
+
+    let mut prefix = common_prefix_len(old_slice, new_text);
+    // Do not split a `\r\n` terminator: a position cannot point between the two characters.
+    if old_slice[..prefix].ends_with('\r') {
+        prefix -= 1;
+    }
+
+    let mut suffix = common_suffix_len(&old_slice[prefix..], &new_text[prefix..]);
+    let suffix_start = old_slice.len() - suffix;
+    if old_slice[..suffix_start].ends_with('\r') && old_slice[suffix_start..].starts_with('\n') {
+        suffix -= 1;
+    }
+
+    let range = line_index.offsets_to_range(old_text, start + prefix, end - suffix)
+        .expect("offsets should be valid");
+    TextEdit::new(range, new_text[prefix .. new_text.len() - suffix].to_string())
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices().zip(b.chars())
+        .find(|&((_, a_ch), b_ch)| a_ch != b_ch)
+        .map_or(::std::cmp::min(a.len(), b.len()), |((ix, _), _)| ix)
+}
+
+fn common_suffix_len(a: &str, b: &str) -> usize {
+    let mut len = 0;
+    for (a_ch, b_ch) in a.chars().rev().zip(b.chars().rev()) {
+        if a_ch != b_ch {
+            break;
+        }
+        len += a_ch.len_utf8();
+    }
+    len
+}
+
+#[derive(Debug, PartialEq)]
+enum DiffOp<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Compute a shortest edit script from `a` to `b`, using the linear space variant of the Myers algorithm:
+/// the middle snake of an optimal path is found, and the parts before and after it are diffed recursively.
+fn diff_lines<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<DiffOp<'a>> {
+    let mut ops = vec![];
+    diff_lines_into(a, b, &mut ops);
+    ops
+}
+
+fn diff_lines_into<'a>(a: &[&'a str], b: &[&'a str], ops: &mut Vec<DiffOp<'a>>) {
+    let prefix = a.iter().zip(b).take_while(|&(a_line, b_line)| a_line == b_line).count();
+    ops.extend(a[..prefix].iter().map(|&line| DiffOp::Equal(line)));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|&(a_line, b_line)| a_line == b_line).count();
+    let suffix_lines = &a[a.len() - suffix..];
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if a.is_empty() {
+        ops.extend(b.iter().map(|&line| DiffOp::Insert(line)));
+    } else if b.is_empty() {
+        ops.extend(a.iter().map(|&line| DiffOp::Delete(line)));
+    } else {
+        // With no common prefix or suffix, and both sides not empty, at least 2 edits are needed,
+        // so both parts around the middle snake need fewer edits than the whole.
+        let (x, y, u, v) = middle_snake(a, b);
+        diff_lines_into(&a[..x], &b[..y], ops);
+        ops.extend(a[x..u].iter().map(|&line| DiffOp::Equal(line)));
+        diff_lines_into(&a[u..], &b[v..], ops);
+    }
+    ops.extend(suffix_lines.iter().map(|&line| DiffOp::Equal(line)));
+}
+
+/// Find the middle snake of a shortest edit script from `a` to `b`: the diagonal run `(x, y)` to `(u, v)`
+/// where the furthest reaching paths searched from both ends meet.
+fn middle_snake(a: &[&str], b: &[&str]) -> (usize, usize, usize, usize) {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let delta = n - m;
+    let odd_delta = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    // `forward[k]` is the furthest x reached on diagonal k (x - y) from the start, and `backward[k]` that from
+    // the end, in the coordinates of the reversed sequences. Diagonal k is stored at index k + max + 1.
+    let index = |k: isize| (k + max + 1) as usize;
+    let mut forward = vec![0isize; 2 * max as usize + 3];
+    let mut backward = vec![0isize; 2 * max as usize + 3];
+
+    for d in 0..max + 1 {
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)]) {
+                forward[index(k + 1)]
+            } else {
+                forward[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (start_x, start_y) = (x, y);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index(k)] = x;
+
+            // Diagonal k is diagonal `delta - k` of the reversed sequences
+            let backward_k = delta - k;
+            if odd_delta && backward_k >= -(d - 1) && backward_k <= d - 1 && x + backward[index(backward_k)] >= n {
+                return (start_x as usize, start_y as usize, x as usize, y as usize);
+            }
+            k += 2;
+        }
+
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                backward[index(k + 1)]
+            } else {
+                backward[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (start_x, start_y) = (x, y);
+            while x < n && y < m && a[(n - 1 - x) as usize] == b[(m - 1 - y) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[index(k)] = x;
+
+            let forward_k = delta - k;
+            if !odd_delta && forward_k >= -d && forward_k <= d && x + forward[index(forward_k)] >= n {
+                return ((n - x) as usize, (m - y) as usize, (n - start_x) as usize, (m - start_y) as usize);
+            }
+            k += 2;
+        }
+    }
+    unreachable!("The paths from both ends always meet")
+}
+
+/// Check that diffing `old_text` with `new_text` gives `expected_edits` edits, which turn `old_text` into `new_text`.
+#[cfg(test)]
+fn check_diff(old_text: &str, new_text: &str, expected_edits: usize) {
+    use workspace_edit::Document;
+
+    let edits = diff_text_edits(old_text, new_text, PositionEncodingKind::UTF16);
+    assert_eq!(edits.len(), expected_edits);
+
+    let uri = "file:///foo.rs".parse().unwrap();
+    let mut document = Document::new(uri, None, old_text.into(), PositionEncodingKind::UTF16);
+    document.apply_text_edits(&edits).unwrap();
+    assert_eq!(document.text(), new_text);
+}
+
+#[test]
+fn test_diff_text_edits() {
+    use ls_types_ext::PositionEncodingKind::*;
+
+    check_diff("", "", 0);
+    check_diff("abc\n", "abc\n", 0);
+    check_diff("", "a\nb", 1);
+    check_diff("a\nb", "", 1);
+    check_diff("fn foo(){\nlet x=1;\n}\n", "fn foo() {\n    let x = 1;\n}\n", 1);
+    check_diff("a\nb\nc\nd\ne\n", "a\nB\nc\nd\nE\n", 2);
+    check_diff("a\r\nb\r\n", "a\nb\n", 1);
+    check_diff("a\rb", "a\r\nb", 1);
+    check_diff("a\r\nb", "a\rb", 1);
+    check_diff("x𐐀y\nz", "x𐐀Y\nz", 1);
+
+    let edits = diff_text_edits("let  x = 1;\nlet y = 2;\n", "let x = 1;\nlet y = 2;\n", UTF16);
+    assert_eq!(edits, vec![TextEdit::new(Range::new(Position::new(0, 4), Position::new(0, 5)), "".into())]);
+}
+
+#[test]
+fn test_diff_text_edits_large() {
+    // A few changes in a large file
+    let old_text : String = (0..20000).map(|i| format!("line {}\n", i)).collect();
+    let new_text : String = (0..20000).map(|i| {
+        if i % 1000 == 0 { format!("changed {}\n", i) } else { format!("line {}\n", i) }
+    }).collect();
+    check_diff(&old_text, &new_text, 20);
+
+    // Every line changed: the search storing all steps would need (N+M)^2 diagonals
+    let old_text : String = (0..2000).map(|i| format!("let x{}=1;\n", i)).collect();
+    let new_text : String = (0..2000).map(|i| format!("let x{} = 1;\n", i)).collect();
+    check_diff(&old_text, &new_text, 1);
+}
@@ -0,0 +1,207 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Conversions between LSP `Position`/`Range` and byte offsets into a Rust `str`.
+
+The `character` of a `Position` is counted in code units of the negotiated `PositionEncodingKind`
+(see `negotiation::ClientFeatures::position_encoding`), whereas Rust strings are indexed by UTF-8 bytes.
+Lines are terminated by `\n`, `\r\n` or `\r`, as the spec mandates.
+
+These functions scan the text from the start. For repeated conversions over the same text,
+see `line_index::LineIndex`.
+
+*/
+
+use std::cmp;
+
+use util::core::*;
+
+use ls_types::*;
+use ls_types_ext::PositionEncodingKind;
+
+/* -----------------  ----------------- */
+
+/// The length of given character, in code units of given encoding.
+pub fn encoded_len(ch: char, encoding: PositionEncodingKind) -> u64 {
+    match encoding {
+        PositionEncodingKind::UTF8 => ch.len_utf8() as u64,
+        PositionEncodingKind::UTF16 => ch.len_utf16() as u64,
+        PositionEncodingKind::UTF32 => 1,
+    }
+}
+
+/// The length of given string, in code units of given encoding.
+pub fn encoded_str_len(string: &str, encoding: PositionEncodingKind) -> u64 {
+    match encoding {
+        PositionEncodingKind::UTF8 => string.len() as u64,
+        _ => string.chars().map(|ch| encoded_len(ch, encoding)).sum(),
+    }
+}
+
+/// Convert a column of given line (in `encoding` code units) to a byte offset into the line.
+///
+/// `line` must not include the line terminator. Columns past the end of the line are clamped to the line length,
+/// and columns in the middle of a character are rounded down to the start of that character.
+pub fn column_to_byte(line: &str, column: u64, encoding: PositionEncodingKind) -> usize {
+    if encoding == PositionEncodingKind::UTF8 {
+        let mut byte = cmp::min(column, line.len() as u64) as usize;
+        while !line.is_char_boundary(byte) {
+            byte -= 1;
+        }
+        return byte;
+    }
+
+    let mut units = 0;
+    for (byte, ch) in line.char_indices() {
+        units += encoded_len(ch, encoding);
+        if units > column {
+            return byte;
+        }
+    }
+    line.len()
+}
+
+/// Convert a byte offset into given line to a column, in `encoding` code units.
+///
+/// `line` must not include the line terminator. Offsets past the end of the line are clamped to the line length,
+/// and offsets in the middle of a character are rounded down to the start of that character.
+pub fn byte_to_column(line: &str, byte: usize, encoding: PositionEncodingKind) -> u64 {
+    let mut byte = cmp::min(byte, line.len());
+    while !line.is_char_boundary(byte) {
+        byte -= 1;
+    }
+    encoded_str_len(&line[..byte], encoding)
+}
+
+/// The offset of the start of the line after the line starting at `line_start`,
+/// or `None` if that is the last line.
+pub fn next_line_start(text: &str, line_start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    for ix in line_start..bytes.len() {
+        match bytes[ix] {
+            b'\n' => return Some(ix + 1),
+            b'\r' if bytes.get(ix + 1) == Some(&b'\n') => return Some(ix + 2),
+            b'\r' => return Some(ix + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The offset of the end of the line starting at `line_start`, excluding the line terminator.
+pub fn line_content_end(text: &str, line_start: usize) -> usize {
+    text[line_start..].find(|ch: char| ch == '\n' || ch == '\r').map_or(text.len(), |ix| line_start + ix)
+}
+
+/// The start and end offsets of given line, excluding the line terminator.
+/// `None` if the text has no such line.
+pub fn line_range(text: &str, line: u64) -> Option<(usize, usize)> {
+    let mut line_start = 0;
+    for _ in 0..line {
+        line_start = match next_line_start(text, line_start) {
+            Some(next) => next,
+            None => return None,
+        };
+    }
+    Some((line_start, line_content_end(text, line_start)))
+}
+
+/// Convert given position to a byte offset into `text`.
+/// The `character` of the position is interpreted as in `column_to_byte`.
+pub fn position_to_offset(text: &str, position: &Position, encoding: PositionEncodingKind) -> GResult<usize> {
+    match line_range(text, position.line) {
+        Some((start, end)) => {
+            Ok(start + column_to_byte(&text[start..end], position.character, encoding))
+        }
+        None => {
+            Err(format!("Invalid position {}:{}: line is past the end of the document.",
+                position.line, position.character).into())
+        }
+    }
+}
+
+/// Convert given byte offset into `text` to a position.
+/// The offset must be a char boundary, and not greater than the length of `text`.
+pub fn offset_to_position(text: &str, offset: usize, encoding: PositionEncodingKind) -> GResult<Position> {
+    if offset > text.len() {
+        return Err(format!("Invalid offset {}: past the end of the document ({}).", offset, text.len()).into());
+    }
+    if !text.is_char_boundary(offset) {
+        return Err(format!("Invalid offset {}: not at a character boundary.", offset).into());
+    }
+
+    let mut line = 0;
+    let mut line_start = 0;
+    while let Some(next) = next_line_start(text, line_start) {
+        if next > offset {
+            break;
+        }
+        line += 1;
+        line_start = next;
+    }
+
+    let line_text = &text[line_start..line_content_end(text, line_start)];
+    Ok(Position::new(line, byte_to_column(line_text, offset - line_start, encoding)))
+}
+
+/// Convert given range to start and end byte offsets into `text`.
+pub fn range_to_offsets(text: &str, range: &Range, encoding: PositionEncodingKind) -> GResult<(usize, usize)> {
+    let start = try!(position_to_offset(text, &range.start, encoding));
+    let end = try!(position_to_offset(text, &range.end, encoding));
+    if start > end {
+        return Err(format!("Invalid range {}:{}-{}:{}: start is after end.",
+            range.start.line, range.start.character, range.end.line, range.end.character).into());
+    }
+    Ok((start, end))
+}
+
+/// Convert given start and end byte offsets into `text` to a range.
+pub fn offsets_to_range(text: &str, start: usize, end: usize, encoding: PositionEncodingKind) -> GResult<Range> {
+    let start = try!(offset_to_position(text, start, encoding));
+    let end = try!(offset_to_position(text, end, encoding));
+    Ok(Range::new(start, end))
+}
+
+
+#[test]
+fn test_position_conversions() {
+    use ls_types_ext::PositionEncodingKind::*;
+
+    // "𐐀" is 4 bytes in UTF-8, 2 code units in UTF-16, 1 in UTF-32
+    let text = "a𐐀b\r\nxé\ry\n";
+
+    assert_eq!(line_range(text, 0), Some((0, 6)));
+    assert_eq!(line_range(text, 1), Some((8, 11)));
+    assert_eq!(line_range(text, 2), Some((12, 13)));
+    assert_eq!(line_range(text, 3), Some((14, 14)));
+    assert_eq!(line_range(text, 4), None);
+
+    assert_eq!(position_to_offset(text, &Position::new(0, 3), UTF16).unwrap(), 5);
+    assert_eq!(position_to_offset(text, &Position::new(0, 2), UTF32).unwrap(), 5);
+    assert_eq!(position_to_offset(text, &Position::new(0, 5), UTF8).unwrap(), 5);
+    // Middle of a character rounds down, past the end of the line clamps
+    assert_eq!(position_to_offset(text, &Position::new(0, 2), UTF16).unwrap(), 1);
+    assert_eq!(position_to_offset(text, &Position::new(0, 3), UTF8).unwrap(), 1);
+    assert_eq!(position_to_offset(text, &Position::new(1, 10), UTF16).unwrap(), 11);
+    assert!(position_to_offset(text, &Position::new(4, 0), UTF16).is_err());
+
+    assert_eq!(offset_to_position(text, 5, UTF16).unwrap(), Position::new(0, 3));
+    assert_eq!(offset_to_position(text, 5, UTF32).unwrap(), Position::new(0, 2));
+    assert_eq!(offset_to_position(text, 11, UTF8).unwrap(), Position::new(1, 3));
+    assert_eq!(offset_to_position(text, 11, UTF16).unwrap(), Position::new(1, 2));
+    assert_eq!(offset_to_position(text, 12, UTF16).unwrap(), Position::new(2, 0));
+    assert_eq!(offset_to_position(text, text.len(), UTF16).unwrap(), Position::new(3, 0));
+    assert!(offset_to_position(text, 2, UTF16).is_err());
+    assert!(offset_to_position(text, text.len() + 1, UTF16).is_err());
+
+    let range = Range::new(Position::new(0, 1), Position::new(2, 1));
+    assert_eq!(range_to_offsets(text, &range, UTF16).unwrap(), (1, 13));
+    assert_eq!(offsets_to_range(text, 1, 13, UTF16).unwrap(), range);
+    assert!(range_to_offsets(text, &Range::new(range.end, range.start), UTF16).is_err());
+}
@@ -0,0 +1,415 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+Message tracing.
+
+`TracingMessageReader` and `TracingMessageWriter` wrap the message reader and writer of an endpoint
+(typically `LSPMessageReader` and `LSPMessageWriter`), and record each message read or written with a
+`TraceRecorder`. Example, for a server:
+
+```ignore
+let recorder = try!(TraceRecorder::create_file("lsp-trace.log"));
+
+let out_recorder = recorder.clone();
+let endpoint = LSPEndpoint::create_lsp_output(move || {
+    TracingMessageWriter::new(LSPMessageWriter(io::stdout()), out_recorder)
+});
+let stdin = io::stdin();
+let mut reader = TracingMessageReader::new(LSPMessageReader(stdin.lock()), recorder);
+LSPEndpoint::run_server(&mut reader, endpoint, language_server);
+```
+
+Traces are recorded one entry per line, in the JSON format of the VS Code `trace.server` setting
+(the format of the LSP inspector): `{"isLSPMessage":true,"type":"receive-request","message":{..},"timestamp":..}`.
+
+`TraceRequestHandler` implements the `$/setTrace` notification (and the `trace` param of `initialize`),
+so that the client can change at runtime the verbosity of the `$/logTrace` notifications sent with `ServerTrace`.
+
+*/
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use util::core::*;
+
+use jsonrpc::*;
+use jsonrpc::jsonrpc_request::RequestParams;
+use jsonrpc::service_util::MessageReader;
+use jsonrpc::service_util::MessageWriter;
+
+use serde;
+use serde::de::Error as Error_;
+use serde_json;
+use serde_json::Value;
+
+use ls_types::*;
+use ls_types_ext::*;
+use lsp::duration_millis;
+
+/* ----------------- Trace entries ----------------- */
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum MessageDirection {
+    /// A message written by the traced endpoint.
+    Sent,
+    /// A message read by the traced endpoint.
+    Received,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum TraceEntryType {
+    SendRequest,
+    SendNotification,
+    SendResponse,
+    ReceiveRequest,
+    ReceiveNotification,
+    ReceiveResponse,
+}
+
+impl TraceEntryType {
+
+    /// The type of an entry for given message. Messages with a `method` are requests if they have an `id`,
+    /// notifications otherwise. Other messages are responses.
+    pub fn for_message(direction: MessageDirection, message: &Value) -> TraceEntryType {
+        let is_request = message.find("method").is_some();
+        let is_notification = is_request && message.find("id").map_or(true, Value::is_null);
+
+        match (direction, is_request, is_notification) {
+            (MessageDirection::Sent, _, true) => TraceEntryType::SendNotification,
+            (MessageDirection::Sent, true, false) => TraceEntryType::SendRequest,
+            (MessageDirection::Sent, false, _) => TraceEntryType::SendResponse,
+            (MessageDirection::Received, _, true) => TraceEntryType::ReceiveNotification,
+            (MessageDirection::Received, true, false) => TraceEntryType::ReceiveRequest,
+            (MessageDirection::Received, false, _) => TraceEntryType::ReceiveResponse,
+        }
+    }
+
+    pub fn direction(&self) -> MessageDirection {
+        match *self {
+            TraceEntryType::SendRequest |
+            TraceEntryType::SendNotification |
+            TraceEntryType::SendResponse => MessageDirection::Sent,
+            TraceEntryType::ReceiveRequest |
+            TraceEntryType::ReceiveNotification |
+            TraceEntryType::ReceiveResponse => MessageDirection::Received,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            TraceEntryType::SendRequest => "send-request",
+            TraceEntryType::SendNotification => "send-notification",
+            TraceEntryType::SendResponse => "send-response",
+            TraceEntryType::ReceiveRequest => "receive-request",
+            TraceEntryType::ReceiveNotification => "receive-notification",
+            TraceEntryType::ReceiveResponse => "receive-response",
+        }
+    }
+
+    pub fn from_str(entry_type: &str) -> Option<TraceEntryType> {
+        match entry_type {
+            "send-request" => Some(TraceEntryType::SendRequest),
+            "send-notification" => Some(TraceEntryType::SendNotification),
+            "send-response" => Some(TraceEntryType::SendResponse),
+            "receive-request" => Some(TraceEntryType::ReceiveRequest),
+            "receive-notification" => Some(TraceEntryType::ReceiveNotification),
+            "receive-response" => Some(TraceEntryType::ReceiveResponse),
+            _ => None,
+        }
+    }
+
+}
+
+impl serde::Deserialize for TraceEntryType {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: serde::Deserializer
+    {
+        let entry_type = try!(String::deserialize(deserializer));
+        TraceEntryType::from_str(&entry_type).ok_or_else(|| {
+            D::Error::invalid_value("Invalid TraceEntryType")
+        })
+    }
+}
+
+impl serde::Serialize for TraceEntryType {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: serde::Serializer
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// A recorded message.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct TraceEntry {
+    #[serde(rename="isLSPMessage")]
+    pub is_lsp_message: bool,
+
+    #[serde(rename="type")]
+    pub entry_type: TraceEntryType,
+
+    /// The JSON-RPC message. If the message was not valid JSON, this is the message text as a JSON string.
+    pub message: Value,
+
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl TraceEntry {
+
+    pub fn new(direction: MessageDirection, message: &str) -> TraceEntry {
+        let message = serde_json::from_str::<Value>(message).unwrap_or_else(|_| Value::String(message.into()));
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(duration_millis).unwrap_or(0);
+        TraceEntry {
+            is_lsp_message : true,
+            entry_type : TraceEntryType::for_message(direction, &message),
+            message : message,
+            timestamp : timestamp,
+        }
+    }
+
+    pub fn direction(&self) -> MessageDirection {
+        self.entry_type.direction()
+    }
+
+}
+
+/// Read the entries of a trace written by a `TraceRecorder`. Empty lines are ignored.
+pub fn read_trace_entries<R : io::BufRead>(input: R) -> GResult<Vec<TraceEntry>> {
+    let mut entries = vec![];
+    for line in input.lines() {
+        let line = try!(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(try!(serde_json::from_str::<TraceEntry>(&line)));
+    }
+    Ok(entries)
+}
+
+/* ----------------- Recording ----------------- */
+
+/// Records messages to an output, one `TraceEntry` per line. Clones record to the same output.
+#[derive(Clone)]
+pub struct TraceRecorder {
+    output: Arc<Mutex<Box<io::Write + Send>>>,
+}
+
+impl TraceRecorder {
+
+    pub fn new<OUT : io::Write + Send + 'static>(output: OUT) -> TraceRecorder {
+        let output : Box<io::Write + Send> = new(output);
+        TraceRecorder { output : newArcMutex(output) }
+    }
+
+    /// Create a recorder writing to given file. The file is truncated if it exists.
+    pub fn create_file<P : AsRef<Path>>(path: P) -> GResult<TraceRecorder> {
+        Ok(TraceRecorder::new(try!(File::create(path))))
+    }
+
+    pub fn record(&self, direction: MessageDirection, message: &str) {
+        self.record_entry(&TraceEntry::new(direction, message))
+    }
+
+    /// Write given entry. Errors are logged, not returned, so that tracing never disrupts the session.
+    pub fn record_entry(&self, entry: &TraceEntry) {
+        let mut line = serde_json::to_string(entry).expect("Failed to serialize TraceEntry");
+        line.push('\n');
+
+        let mut output = self.output.lock().unwrap();
+        let result = output.write_all(line.as_bytes()).and_then(|_| output.flush());
+        if let Err(error) = result {
+            error!("Failed to record trace entry: {}", error);
+        }
+    }
+
+}
+
+/// A message reader that records the messages read.
+pub struct TracingMessageReader<MR> {
+    pub reader: MR,
+    pub recorder: TraceRecorder,
+}
+
+impl<MR : MessageReader> TracingMessageReader<MR> {
+    pub fn new(reader: MR, recorder: TraceRecorder) -> TracingMessageReader<MR> {
+        TracingMessageReader { reader : reader, recorder : recorder }
+    }
+}
+
+impl<MR : MessageReader> MessageReader for TracingMessageReader<MR> {
+    fn read_next(&mut self) -> GResult<String> {
+        let message = try!(self.reader.read_next());
+        self.recorder.record(MessageDirection::Received, &message);
+        Ok(message)
+    }
+}
+
+/// A message writer that records the messages written.
+pub struct TracingMessageWriter<MW> {
+    pub writer: MW,
+    pub recorder: TraceRecorder,
+}
+
+impl<MW : MessageWriter> TracingMessageWriter<MW> {
+    pub fn new(writer: MW, recorder: TraceRecorder) -> TracingMessageWriter<MW> {
+        TracingMessageWriter { writer : writer, recorder : recorder }
+    }
+}
+
+impl<MW : MessageWriter> MessageWriter for TracingMessageWriter<MW> {
+    fn write_message(&mut self, msg: &str) -> GResult<()> {
+        self.recorder.record(MessageDirection::Sent, msg);
+        self.writer.write_message(msg)
+    }
+}
+
+/* ----------------- $/setTrace and $/logTrace ----------------- */
+
+/// The trace setting of the server, as set by the client. Clones share the same setting.
+#[derive(Clone)]
+pub struct ServerTrace {
+    value: Arc<Mutex<TraceValue>>,
+}
+
+impl ServerTrace {
+
+    pub fn new(value: TraceValue) -> ServerTrace {
+        ServerTrace { value : newArcMutex(value) }
+    }
+
+    pub fn value(&self) -> TraceValue {
+        *self.value.lock().unwrap()
+    }
+
+    pub fn set_value(&self, value: TraceValue) {
+        *self.value.lock().unwrap() = value;
+    }
+
+    /// Send a `$/logTrace` notification, if tracing is enabled.
+    /// `verbose` is only called, and its result sent, if the trace setting is `verbose`.
+    pub fn log_trace<FN>(&self, endpoint: &Endpoint, message: &str, verbose: FN) -> GResult<()>
+    where
+        FN : FnOnce() -> String
+    {
+        let verbose = match self.value() {
+            TraceValue::Off => return Ok(()),
+            TraceValue::Messages => None,
+            TraceValue::Verbose => Some(verbose()),
+        };
+        let params = LogTraceParams { message : message.into(), verbose : verbose };
+        endpoint.send_notification(NOTIFICATION__LogTrace, params)
+    }
+
+}
+
+/// A request handler that updates a `ServerTrace` from the `$/setTrace` notification and the `trace` param
+/// of the `initialize` request. All other messages, including `initialize`, are passed on to `handler`.
+pub struct TraceRequestHandler<HANDLER> {
+    pub handler: HANDLER,
+    pub trace: ServerTrace,
+}
+
+impl<HANDLER : RequestHandler> TraceRequestHandler<HANDLER> {
+    pub fn new(handler: HANDLER, trace: ServerTrace) -> TraceRequestHandler<HANDLER> {
+        TraceRequestHandler { handler : handler, trace : trace }
+    }
+}
+
+impl<HANDLER : RequestHandler> RequestHandler for TraceRequestHandler<HANDLER> {
+
+    fn handle_request(
+        &mut self, method_name: &str, params: RequestParams, completable: ResponseCompletable
+    ) {
+        match method_name {
+            NOTIFICATION__SetTrace => {
+                let trace = &self.trace;
+                completable.handle_notification_with(params,
+                    |params: SetTraceParams| trace.set_value(params.value)
+                )
+            }
+            REQUEST__Initialize => {
+                let initial_value = params.clone().into_value().find("trace").and_then(|trace| {
+                    serde_json::from_value::<TraceValue>(trace.clone()).ok()
+                });
+                if let Some(initial_value) = initial_value {
+                    self.trace.set_value(initial_value);
+                }
+                self.handler.handle_request(method_name, params, completable)
+            }
+            _ => {
+                self.handler.handle_request(method_name, params, completable)
+            }
+        }
+    }
+
+}
+
+
+#[test]
+fn test_TraceRecorder() {
+    use lsp_transport::*;
+    use jsonrpc::jsonrpc_response::Response;
+
+    #[derive(Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = SharedBuffer(newArcMutex(vec![]));
+    let recorder = TraceRecorder::new(buffer.clone());
+
+    let input = "Content-Length: 44\r\n\r\n{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"shutdown\"}\n";
+    let mut reader = TracingMessageReader::new(LSPMessageReader(input.as_bytes()), recorder.clone());
+    reader.read_next().unwrap();
+
+    let mut writer = TracingMessageWriter::new(LSPMessageWriter(vec![]), recorder.clone());
+    writer.write_message(r#"{"jsonrpc":"2.0","id":1,"result":null}"#).unwrap();
+    writer.write_message(r#"{"jsonrpc":"2.0","method":"$/logTrace","params":{"message":"foo"}}"#).unwrap();
+
+    let trace = buffer.0.lock().unwrap().clone();
+    let entries = read_trace_entries(&trace[..]).unwrap();
+    let entry_types : Vec<_> = entries.iter().map(|entry| entry.entry_type).collect();
+    assert_eq!(entry_types, vec![
+        TraceEntryType::ReceiveRequest, TraceEntryType::SendResponse, TraceEntryType::SendNotification]);
+    assert_eq!(entries[0].message.find("method"), Some(&Value::String("shutdown".into())));
+    assert_eq!(entries[1].direction(), MessageDirection::Sent);
+
+    let entry = TraceEntry::new(MessageDirection::Received, "not json");
+    assert_eq!(entry.message, Value::String("not json".into()));
+
+    // $/setTrace
+    let trace = ServerTrace::new(TraceValue::Off);
+    struct NoopHandler;
+    impl RequestHandler for NoopHandler {
+        fn handle_request(&mut self, _: &str, _: RequestParams, completable: ResponseCompletable) {
+            completable.complete(None);
+        }
+    }
+    let mut handler = TraceRequestHandler::new(NoopHandler, trace.clone());
+    let params = match serde_json::to_value(&SetTraceParams { value : TraceValue::Verbose }) {
+        Value::Object(object) => RequestParams::Object(object),
+        _ => panic!("Expected an object"),
+    };
+    let completable = ResponseCompletable::new(None, new(|_: Option<Response>| {}));
+    handler.handle_request(NOTIFICATION__SetTrace, params, completable);
+    assert_eq!(trace.value(), TraceValue::Verbose);
+}
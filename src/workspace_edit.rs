@@ -0,0 +1,453 @@
+// Copyright 2016 Bruno Medeiros
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>.
+// This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+
+In-memory text documents, and application of `TextEdit`s and `WorkspaceEdit`s to them.
+
+*/
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use util::core::*;
+
+use url::Url;
+
+use ls_types::*;
+use ls_types_ext::*;
+use line_index::LineIndex;
+
+/* ----------------- Errors ----------------- */
+
+/// An error applying an edit. Nothing is modified when an edit fails.
+#[derive(Debug, PartialEq, Clone)]
+pub enum EditError {
+    /// The edit refers to a document that does not exist.
+    DocumentNotFound(Url),
+    /// A file operation would create a document that already exists.
+    DocumentExists(Url),
+    /// A folder cannot be deleted because it has documents, and the deletion is not recursive.
+    FolderNotEmpty(Url),
+    /// A versioned edit was computed for a different version of the document.
+    VersionMismatch { uri: Url, expected: u64, actual: Option<u64> },
+    /// The edit with given index (in its list of edits) has an invalid range.
+    InvalidRange { uri: Url, edit_index: usize, range: Range, message: String },
+    /// The edits with given indexes (in their list of edits) overlap.
+    OverlappingEdits { uri: Url, first_index: usize, first: Range, second_index: usize, second: Range },
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EditError::DocumentNotFound(ref uri) => write!(f, "Document not found: {}", uri),
+            EditError::DocumentExists(ref uri) => write!(f, "Document already exists: {}", uri),
+            EditError::FolderNotEmpty(ref uri) => write!(f, "Folder is not empty: {}", uri),
+            EditError::VersionMismatch { ref uri, expected, actual } => {
+                match actual {
+                    Some(actual) => write!(f, "Edit is for version {} of {}, but document is at version {}",
+                        expected, uri, actual),
+                    None => write!(f, "Edit is for version {} of {}, but document has no version", expected, uri),
+                }
+            }
+            EditError::InvalidRange { ref uri, edit_index, ref range, ref message } => {
+                write!(f, "Edit #{} for {} has invalid range {}:{}-{}:{}: {}", edit_index, uri,
+                    range.start.line, range.start.character, range.end.line, range.end.character, message)
+            }
+            EditError::OverlappingEdits { ref uri, first_index, ref first, second_index, ref second } => {
+                write!(f, "Edits #{} ({}:{}-{}:{}) and #{} ({}:{}-{}:{}) for {} overlap",
+                    first_index, first.start.line, first.start.character, first.end.line, first.end.character,
+                    second_index, second.start.line, second.start.character, second.end.line, second.end.character,
+                    uri)
+            }
+        }
+    }
+}
+
+impl error::Error for EditError {
+    fn description(&self) -> &str {
+        match *self {
+            EditError::DocumentNotFound(_) => "document not found",
+            EditError::DocumentExists(_) => "document already exists",
+            EditError::FolderNotEmpty(_) => "folder is not empty",
+            EditError::VersionMismatch { .. } => "document version mismatch",
+            EditError::InvalidRange { .. } => "invalid edit range",
+            EditError::OverlappingEdits { .. } => "overlapping edits",
+        }
+    }
+}
+
+/* ----------------- Document ----------------- */
+
+/// An in-memory text document, with a `LineIndex` kept up to date with its text.
+#[derive(Debug, Clone)]
+pub struct Document {
+    uri: Url,
+    version: Option<u64>,
+    text: String,
+    line_index: LineIndex,
+}
+
+impl Document {
+
+    pub fn new(uri: Url, version: Option<u64>, text: String, encoding: PositionEncodingKind) -> Document {
+        let line_index = LineIndex::new(&text, encoding);
+        Document { uri : uri, version : version, text : text, line_index : line_index }
+    }
+
+    pub fn uri(&self) -> &Url {
+        &self.uri
+    }
+
+    pub fn version(&self) -> Option<u64> {
+        self.version
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_index(&self) -> &LineIndex {
+        &self.line_index
+    }
+
+    pub fn position_to_offset(&self, position: &Position) -> GResult<usize> {
+        self.line_index.position_to_offset(&self.text, position)
+    }
+
+    pub fn offset_to_position(&self, offset: usize) -> GResult<Position> {
+        self.line_index.offset_to_position(&self.text, offset)
+    }
+
+    /// Apply the content changes of a `textDocument/didChange` notification, and update the version.
+    /// If any change is invalid, nothing is applied.
+    pub fn apply_changes(&mut self, params: &DidChangeTextDocumentParams) -> GResult<()> {
+        // Later changes refer to the result of earlier ones, so apply to a copy, as for workspace edits.
+        let mut text = self.text.clone();
+        let mut line_index = self.line_index.clone();
+        try!(line_index.apply_changes(&mut text, params));
+        self.text = text;
+        self.line_index = line_index;
+        self.version = Some(params.text_document.version);
+        Ok(())
+    }
+
+    /// Check that given edits can be applied, returning their byte offsets, sorted in document order.
+    fn edit_offsets(&self, edits: &[TextEdit]) -> Result<Vec<(usize, usize, usize)>, EditError> {
+        let mut offsets = Vec::with_capacity(edits.len());
+        for (edit_index, edit) in edits.iter().enumerate() {
+            match self.line_index.range_to_offsets(&self.text, &edit.range) {
+                Ok((start, end)) => offsets.push((start, end, edit_index)),
+                Err(error) => {
+                    return Err(EditError::InvalidRange {
+                        uri : self.uri.clone(), edit_index : edit_index, range : edit.range, message : error.to_string()
+                    })
+                }
+            }
+        }
+
+        // Stable sort: inserts at the same position are applied in the order given.
+        offsets.sort_by_key(|&(start, end, _)| (start, end));
+
+        for pair in offsets.windows(2) {
+            let (_, previous_end, previous_index) = pair[0];
+            let (start, _, index) = pair[1];
+            if start < previous_end {
+                return Err(EditError::OverlappingEdits {
+                    uri : self.uri.clone(),
+                    first_index : previous_index,
+                    first : edits[previous_index].range,
+                    second_index : index,
+                    second : edits[index].range,
+                });
+            }
+        }
+        Ok(offsets)
+    }
+
+    /// Apply given edits, which all refer to the current text of the document.
+    /// If any edit is invalid, or edits overlap, nothing is applied.
+    pub fn apply_text_edits(&mut self, edits: &[TextEdit]) -> Result<(), EditError> {
+        let offsets = try!(self.edit_offsets(edits));
+
+        // Apply from the end of the document, so that the offsets of the remaining edits stay valid.
+        for &(start, end, edit_index) in offsets.iter().rev() {
+            self.line_index.replace(&mut self.text, start, end, &edits[edit_index].new_text);
+        }
+        Ok(())
+    }
+
+}
+
+/* ----------------- Document store ----------------- */
+
+/// A set of in-memory documents, all using the same position encoding.
+///
+/// It can track the documents opened by a client (`did_open`, `did_change`, `did_close`),
+/// and apply workspace edits to them: `apply_workspace_edit` either applies the whole edit, or nothing.
+#[derive(Debug, Clone)]
+pub struct DocumentStore {
+    encoding: PositionEncodingKind,
+    documents: HashMap<Url, Document>,
+}
+
+impl DocumentStore {
+
+    pub fn new(encoding: PositionEncodingKind) -> DocumentStore {
+        DocumentStore { encoding : encoding, documents : HashMap::new() }
+    }
+
+    pub fn encoding(&self) -> PositionEncodingKind {
+        self.encoding
+    }
+
+    pub fn get(&self, uri: &Url) -> Option<&Document> {
+        self.documents.get(uri)
+    }
+
+    pub fn documents(&self) -> &HashMap<Url, Document> {
+        &self.documents
+    }
+
+    /// Add a document, replacing any existing document with the same URI.
+    pub fn insert(&mut self, uri: Url, version: Option<u64>, text: String) {
+        let document = Document::new(uri.clone(), version, text, self.encoding);
+        self.documents.insert(uri, document);
+    }
+
+    pub fn remove(&mut self, uri: &Url) -> Option<Document> {
+        self.documents.remove(uri)
+    }
+
+    pub fn did_open(&mut self, params: &DidOpenTextDocumentParams) {
+        let document = &params.text_document;
+        self.insert(document.uri.clone(), document.version, document.text.clone());
+    }
+
+    pub fn did_change(&mut self, params: &DidChangeTextDocumentParams) -> GResult<()> {
+        let uri = &params.text_document.uri;
+        match self.documents.get_mut(uri) {
+            Some(document) => document.apply_changes(params),
+            None => Err(EditError::DocumentNotFound(uri.clone()).into()),
+        }
+    }
+
+    pub fn did_close(&mut self, params: &DidCloseTextDocumentParams) -> Option<Document> {
+        self.remove(&params.text_document.uri)
+    }
+
+    /// Apply given edits to the document with given URI.
+    pub fn apply_text_edits(&mut self, uri: &Url, edits: &[TextEdit]) -> Result<(), EditError> {
+        match self.documents.get_mut(uri) {
+            Some(document) => document.apply_text_edits(edits),
+            None => Err(EditError::DocumentNotFound(uri.clone())),
+        }
+    }
+
+    /// Apply a `WorkspaceEdit`. Either all edits are applied, or none.
+    pub fn apply_workspace_edit(&mut self, edit: &WorkspaceEdit) -> Result<(), EditError> {
+        // Validate all edits before applying any of them.
+        for (uri, edits) in &edit.changes {
+            match self.documents.get(uri) {
+                Some(document) => { try!(document.edit_offsets(edits)); }
+                None => return Err(EditError::DocumentNotFound(uri.clone())),
+            }
+        }
+        for (uri, edits) in &edit.changes {
+            try!(self.apply_text_edits(uri, edits));
+        }
+        Ok(())
+    }
+
+    /// Apply a workspace edit with document changes, in order. Either all changes are applied, or none.
+    /// If `document_changes` is present, `changes` is ignored, as clients do.
+    pub fn apply_workspace_edit_with_document_changes(&mut self, edit: &WorkspaceEditWithDocumentChanges)
+        -> Result<(), EditError>
+    {
+        let document_changes = match edit.document_changes {
+            Some(ref document_changes) => document_changes,
+            None => {
+                let changes = edit.changes.clone().unwrap_or(HashMap::new());
+                return self.apply_workspace_edit(&WorkspaceEdit::new(changes));
+            }
+        };
+
+        // File operations and later edits depend on the result of earlier ones,
+        // so apply to a copy and keep it only if all changes succeed.
+        let mut documents = self.documents.clone();
+        for document_change in document_changes {
+            try!(Self::apply_document_change(&mut documents, self.encoding, document_change));
+        }
+        self.documents = documents;
+        Ok(())
+    }
+
+    fn apply_document_change(documents: &mut HashMap<Url, Document>, encoding: PositionEncodingKind,
+        document_change: &DocumentChange) -> Result<(), EditError>
+    {
+        match *document_change {
+            DocumentChange::Edit(ref text_document_edit) => {
+                let text_document = &text_document_edit.text_document;
+                let document = match documents.get_mut(&text_document.uri) {
+                    Some(document) => document,
+                    None => return Err(EditError::DocumentNotFound(text_document.uri.clone())),
+                };
+                if let Some(expected) = text_document.version {
+                    if document.version != Some(expected) {
+                        return Err(EditError::VersionMismatch {
+                            uri : text_document.uri.clone(), expected : expected, actual : document.version
+                        });
+                    }
+                }
+                document.apply_text_edits(&text_document_edit.edits)
+            }
+            DocumentChange::Create(ref create) => {
+                let options = create.options.clone().unwrap_or(CreateFileOptions::default());
+                if documents.contains_key(&create.uri) && !options.overwrite.unwrap_or(false) {
+                    if options.ignore_if_exists.unwrap_or(false) {
+                        return Ok(());
+                    }
+                    return Err(EditError::DocumentExists(create.uri.clone()));
+                }
+                let document = Document::new(create.uri.clone(), None, String::new(), encoding);
+                documents.insert(create.uri.clone(), document);
+                Ok(())
+            }
+            DocumentChange::Rename(ref rename) => {
+                let options = rename.options.clone().unwrap_or(RenameFileOptions::default());
+                if !documents.contains_key(&rename.old_uri) {
+                    return Err(EditError::DocumentNotFound(rename.old_uri.clone()));
+                }
+                if documents.contains_key(&rename.new_uri) && !options.overwrite.unwrap_or(false) {
+                    if options.ignore_if_exists.unwrap_or(false) {
+                        return Ok(());
+                    }
+                    return Err(EditError::DocumentExists(rename.new_uri.clone()));
+                }
+                let mut document = documents.remove(&rename.old_uri).unwrap();
+                document.uri = rename.new_uri.clone();
+                documents.insert(rename.new_uri.clone(), document);
+                Ok(())
+            }
+            DocumentChange::Delete(ref delete) => {
+                let options = delete.options.clone().unwrap_or(DeleteFileOptions::default());
+                if documents.remove(&delete.uri).is_some() {
+                    return Ok(());
+                }
+
+                // The URI may denote a folder containing documents
+                let folder_prefix = format!("{}/", delete.uri.as_str().trim_right_matches('/'));
+                let contained : Vec<Url> = documents.keys()
+                    .filter(|uri| uri.as_str().starts_with(&folder_prefix))
+                    .cloned()
+                    .collect();
+
+                if contained.is_empty() {
+                    if options.ignore_if_not_exists.unwrap_or(false) {
+                        return Ok(());
+                    }
+                    return Err(EditError::DocumentNotFound(delete.uri.clone()));
+                }
+                if !options.recursive.unwrap_or(false) {
+                    return Err(EditError::FolderNotEmpty(delete.uri.clone()));
+                }
+                for uri in contained {
+                    documents.remove(&uri);
+                }
+                Ok(())
+            }
+        }
+    }
+
+}
+
+
+#[test]
+fn test_DocumentStore() {
+    let foo = Url::parse("file:///src/foo.rs").unwrap();
+    let bar = Url::parse("file:///src/bar.rs").unwrap();
+    let mut store = DocumentStore::new(PositionEncodingKind::UTF16);
+    store.insert(foo.clone(), Some(1), "fn foo() {}\nfn bar() {}\n".into());
+
+    let edit = |range: ((u64, u64), (u64, u64)), new_text: &str| {
+        let ((start_line, start_char), (end_line, end_char)) = range;
+        let range = Range::new(Position::new(start_line, start_char), Position::new(end_line, end_char));
+        TextEdit::new(range, new_text.into())
+    };
+
+    // Edits refer to the original text; inserts at the same position keep their order
+    let edits = vec![edit(((1, 3), (1, 6)), "baz"), edit(((0, 0), (0, 0)), "// a\n"), edit(((0, 0), (0, 0)), "// b\n")];
+    store.apply_text_edits(&foo, &edits).unwrap();
+    assert_eq!(store.get(&foo).unwrap().text(), "// a\n// b\nfn foo() {}\nfn baz() {}\n");
+
+    let edits = vec![edit(((0, 0), (0, 4)), ""), edit(((0, 2), (1, 0)), "")];
+    match store.apply_text_edits(&foo, &edits).unwrap_err() {
+        EditError::OverlappingEdits { first_index : 0, second_index : 1, .. } => {}
+        error => panic!("Unexpected error: {}", error),
+    }
+    match store.apply_text_edits(&foo, &[edit(((9, 0), (9, 0)), "")]).unwrap_err() {
+        EditError::InvalidRange { edit_index : 0, .. } => {}
+        error => panic!("Unexpected error: {}", error),
+    }
+
+    let change = |line: u64, text: &str| {
+        let range = Range::new(Position::new(line, 0), Position::new(line, 0));
+        TextDocumentContentChangeEvent { range : Some(range), range_length : None, text : text.into() }
+    };
+    let text_document = VersionedTextDocumentIdentifier { uri : foo.clone(), version : 2 };
+    let params = DidChangeTextDocumentParams {
+        text_document : text_document,
+        content_changes : vec![change(0, "// c\n"), change(9, "")],
+    };
+    assert!(store.did_change(&params).is_err());
+    assert_eq!(store.get(&foo).unwrap().text(), "// a\n// b\nfn foo() {}\nfn baz() {}\n");
+    assert_eq!(store.get(&foo).unwrap().version(), Some(1));
+
+    // Document changes are applied in order, and not at all if any fails
+    let text_document = OptionalVersionedTextDocumentIdentifier { uri : bar.clone(), version : None };
+    let mut workspace_edit = WorkspaceEditWithDocumentChanges {
+        changes : None,
+        document_changes : Some(vec![
+            DocumentChange::Rename(RenameFile { old_uri : foo.clone(), new_uri : bar.clone(), options : None }),
+            DocumentChange::Edit(TextDocumentEdit {
+                text_document : text_document,
+                edits : vec![edit(((0, 0), (2, 0)), "")],
+            }),
+            DocumentChange::Create(CreateFile { uri : bar.clone(), options : None }),
+        ]),
+    };
+    assert_eq!(store.apply_workspace_edit_with_document_changes(&workspace_edit),
+        Err(EditError::DocumentExists(bar.clone())));
+    assert!(store.get(&foo).is_some());
+    assert!(store.get(&bar).is_none());
+
+    workspace_edit.document_changes.as_mut().unwrap().pop();
+    store.apply_workspace_edit_with_document_changes(&workspace_edit).unwrap();
+    assert!(store.get(&foo).is_none());
+    assert_eq!(store.get(&bar).unwrap().text(), "fn foo() {}\nfn baz() {}\n");
+
+    let text_document = OptionalVersionedTextDocumentIdentifier { uri : bar.clone(), version : Some(2) };
+    let workspace_edit = WorkspaceEditWithDocumentChanges {
+        changes : None,
+        document_changes : Some(vec![
+            DocumentChange::Edit(TextDocumentEdit { text_document : text_document, edits : vec![] }),
+        ]),
+    };
+    assert_eq!(store.apply_workspace_edit_with_document_changes(&workspace_edit),
+        Err(EditError::VersionMismatch { uri : bar.clone(), expected : 2, actual : Some(1) }));
+
+    let delete = |uri: &str, recursive: bool| {
+        let options = DeleteFileOptions { recursive : Some(recursive), ignore_if_not_exists : None };
+        let delete = DeleteFile { uri : Url::parse(uri).unwrap(), options : Some(options) };
+        let document_changes = vec![DocumentChange::Delete(delete)];
+        WorkspaceEditWithDocumentChanges { changes : None, document_changes : Some(document_changes) }
+    };
+    assert!(store.apply_workspace_edit_with_document_changes(&delete("file:///src", false)).is_err());
+    store.apply_workspace_edit_with_document_changes(&delete("file:///src", true)).unwrap();
+    assert!(store.documents().is_empty());
+}